@@ -1,5 +1,5 @@
 use anyhow::{anyhow, Context, Result};
-use reqwest::Client;
+use crate::network::http_client;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -29,57 +29,192 @@ pub struct Episode {
     pub session: String,
     #[serde(default)]
     pub snapshot: Option<String>,
+    /// Release timestamp from the API, e.g. `"2024-01-06 15:31:12"`
+    #[serde(default)]
+    pub created_at: Option<String>,
+}
+
+impl Episode {
+    /// Day the episode was released, when the host reports it
+    pub fn aired_on(&self) -> Option<chrono::NaiveDate> {
+        parse_air_date(self.created_at.as_deref()?)
+    }
+
+    /// Episode number, tolerant of hosts that send `"12"` instead of `12`.
+    /// Specials keep their fraction (e.g. 12.5).
+    pub fn number(&self) -> Option<EpisodeNumber> {
+        EpisodeNumber::from_f64(parse_episode_number(&self.episode)?)
+    }
+}
+
+/// Episode number that keeps specials like `12.5`. Held in tenths so it
+/// stays `Eq`/`Ord` and can key maps; serializes as a plain JSON number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EpisodeNumber(u32);
+
+impl EpisodeNumber {
+    pub fn from_f64(n: f64) -> Option<Self> {
+        let tenths = (n * 10.0).round();
+        if !n.is_finite() || n < 0.0 || tenths > u32::MAX as f64 || (tenths / 10.0 - n).abs() > 1e-6 {
+            return None;
+        }
+        Some(Self(tenths as u32))
+    }
+
+    pub fn as_f64(self) -> f64 {
+        self.0 as f64 / 10.0
+    }
+
+    pub fn whole(self) -> u32 {
+        self.0 / 10
+    }
+
+    pub fn is_whole(self) -> bool {
+        self.0 % 10 == 0
+    }
+
+    /// Fractional suffix such as `.5`, empty for regular episodes
+    pub fn suffix(self) -> String {
+        if self.is_whole() {
+            String::new()
+        } else {
+            format!(".{}", self.0 % 10)
+        }
+    }
+
+    /// Whether `next` directly follows this one in a run of regular episodes
+    pub fn precedes(self, next: Self) -> bool {
+        self.is_whole() && next.is_whole() && next.0 == self.0 + 10
+    }
+}
+
+impl From<u32> for EpisodeNumber {
+    fn from(n: u32) -> Self {
+        Self(n.saturating_mul(10))
+    }
+}
+
+impl std::fmt::Display for EpisodeNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.whole(), self.suffix())
+    }
+}
+
+impl Serialize for EpisodeNumber {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if self.is_whole() {
+            serializer.serialize_u32(self.whole())
+        } else {
+            serializer.serialize_f64(self.as_f64())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for EpisodeNumber {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        parse_episode_number(&value)
+            .and_then(EpisodeNumber::from_f64)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid episode number {value}")))
+    }
+}
+
+/// Accepts the release API's `YYYY-MM-DD HH:MM:SS` as well as a bare `YYYY-MM-DD`
+pub fn parse_air_date(value: &str) -> Option<chrono::NaiveDate> {
+    let value = value.trim();
+    chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .map(|dt| dt.date())
+        .or_else(|_| chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d"))
+        .ok()
+}
+
+/// Parse an episode value that may be a JSON number (`12`, `12.5`) or a numeric string
+pub fn parse_episode_number(value: &serde_json::Value) -> Option<f64> {
+    let n = match value {
+        serde_json::Value::Number(n) => n.as_f64(),
+        serde_json::Value::String(s) => s.trim().parse::<f64>().ok(),
+        _ => None,
+    }?;
+    (n.is_finite() && n >= 0.0).then_some(n)
 }
 
 pub async fn search_anime(name: &str, cookie: &str, host: &str) -> Result<Vec<SearchItem>> {
-    let client = client();
     let base = host.trim_end_matches('/');
     let url = format!("{}/api?m=search&q={}", base, urlencoding::encode(name));
-    let text = client
-        .get(url)
-        .header(reqwest::header::COOKIE, cookie)
-        .send()
-        .await?
-        .error_for_status()?
-        .text()
-        .await?;
-    let resp: SearchResponse = serde_json::from_str(&text).context("parse search response")?;
+    let text = get_text(&url, cookie).await?;
+    let resp: SearchResponse = parse_json(&text, "parse search response")?;
     Ok(resp.data)
 }
 
+/// Episode ordering supported by the release API
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EpisodeSort {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl EpisodeSort {
+    fn as_param(self) -> &'static str {
+        match self {
+            EpisodeSort::Asc => "episode_asc",
+            EpisodeSort::Desc => "episode_desc",
+        }
+    }
+}
+
 pub async fn fetch_release_page(
     slug: &str,
     page: u32,
+    sort: EpisodeSort,
     cookie: &str,
     host: &str,
 ) -> Result<ReleaseResponse> {
-    let client = client();
     let base = host.trim_end_matches('/');
     let url = format!(
-        "{}/api?m=release&id={}&sort=episode_asc&page={}",
-        base, slug, page
+        "{}/api?m=release&id={}&sort={}&page={}",
+        base,
+        slug,
+        sort.as_param(),
+        page
     );
-    let text = client
-        .get(&url)
-        .header(reqwest::header::COOKIE, cookie)
-        .send()
-        .await?
-        .error_for_status()?
-        .text()
-        .await?;
+    let text = get_text(&url, cookie).await?;
 
-    let resp: ReleaseResponse = serde_json::from_str(&text).context("parse release page")?;
+    let resp: ReleaseResponse = parse_json(&text, "parse release page")?;
     Ok(resp)
 }
 
-pub async fn fetch_all_episodes(slug: &str, cookie: &str, host: &str) -> Result<Vec<Episode>> {
-    let first = fetch_release_page(slug, 1, cookie, host).await?;
-    let mut out = first.data.clone();
-    for p in 2..=first.last_page {
-        let page = fetch_release_page(slug, p, cookie, host).await?;
+pub async fn fetch_all_episodes(
+    slug: &str,
+    sort: EpisodeSort,
+    cookie: &str,
+    host: &str,
+) -> Result<Vec<Episode>> {
+    let (episodes, _) = fetch_episode_range(slug, sort, 1, None, cookie, host).await?;
+    Ok(episodes)
+}
+
+/// Episodes on pages `first..=last` in `sort` order, plus the show's page
+/// count. `last` is clamped to the final page; `None` reads to the end.
+pub async fn fetch_episode_range(
+    slug: &str,
+    sort: EpisodeSort,
+    first: u32,
+    last: Option<u32>,
+    cookie: &str,
+    host: &str,
+) -> Result<(Vec<Episode>, u32)> {
+    let first = first.max(1);
+    let head = fetch_release_page(slug, first, sort, cookie, host).await?;
+    let last_page = head.last_page;
+    let end = last.map_or(last_page, |l| l.min(last_page));
+    let mut out = head.data;
+    for p in (first + 1)..=end {
+        let page = fetch_release_page(slug, p, sort, cookie, host).await?;
         out.extend(page.data);
     }
-    Ok(out)
+    Ok((out, last_page))
 }
 
 /// Extract status from anime title (e.g., "[Completed]", "[Ongoing]")
@@ -133,26 +268,158 @@ pub async fn resolve_anime_info(
     host: &str,
 ) -> Result<(String, Option<String>)> {
     // Best-effort: fetch anime page and read <title>
-    let client = client();
     let base = host.trim_end_matches('/');
     let url = format!("{}/anime/{}", base, slug);
-    let html = client
-        .get(url)
-        .header(reqwest::header::COOKIE, cookie)
-        .send()
-        .await?
-        .text()
-        .await?;
-    if let Some(title) = scraper::Html::parse_document(&html)
+    let html = get_text(&url, cookie)
+        .await
+        .with_context(|| format!("resolve anime name for {}", slug))?;
+    let title = scraper::Html::parse_document(&html)
         .select(&scraper::Selector::parse("title").unwrap())
         .next()
-        .map(|n| n.inner_html())
-    {
-        let status = extract_anime_status(&title);
-        let cleaned = clean_anime_title(&title);
-        Ok((cleaned, status))
-    } else {
-        Ok((fallback.to_string(), None))
+        .map(|n| n.text().collect::<String>());
+    match title {
+        Some(title) => {
+            let status = extract_anime_status(&title);
+            let cleaned = clean_anime_title(&title);
+            if cleaned.is_empty() {
+                Ok((fallback.to_string(), status))
+            } else {
+                Ok((cleaned, status))
+            }
+        }
+        None => Ok((fallback.to_string(), None)),
+    }
+}
+
+/// Attempts per API request before giving up
+const API_ATTEMPTS: u32 = 3;
+
+/// The host answered with DDoS-Guard's interstitial instead of the page,
+/// usually because it no longer trusts the session cookie
+#[derive(Debug)]
+pub struct ChallengePage;
+
+impl std::fmt::Display for ChallengePage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DDoS-Guard challenge page")
+    }
+}
+
+impl std::error::Error for ChallengePage {}
+
+/// Whether a request failed on a challenge page, so a fresh cookie may help
+pub fn is_challenge(err: &anyhow::Error) -> bool {
+    err.is::<ChallengePage>()
+}
+
+fn is_challenge_page(body: &str) -> bool {
+    let head: String = body.chars().take(4096).collect::<String>().to_ascii_lowercase();
+    head.trim_start().starts_with('<')
+        && (head.contains("<title>ddos-guard</title>") || head.contains("/.well-known/ddos-guard/js-challenge"))
+}
+
+/// Parse an API response. HTML where JSON belongs is an interstitial we
+/// didn't recognise, so it's reported as a challenge rather than bad JSON.
+fn parse_json<T: serde::de::DeserializeOwned>(text: &str, what: &'static str) -> Result<T> {
+    serde_json::from_str(text).map_err(|e| {
+        if text.trim_start().starts_with('<') {
+            anyhow!(ChallengePage).context(what)
+        } else {
+            anyhow::Error::new(e).context(what)
+        }
+    })
+}
+
+/// Fetch fresh DDoS-Guard cookies: the homepage hands out the `__ddg*` ids
+/// and the `check` endpoint `__ddg2_`. Returns a `Cookie` header value.
+pub async fn refresh_cookie(host: &str) -> Result<String> {
+    let base = host.trim_end_matches('/');
+    let client = http_client();
+    let mut cookies: Vec<(String, String)> = Vec::new();
+    for url in [
+        format!("{}/", base),
+        format!("{}/.well-known/ddos-guard/check?context=free_splash", base),
+    ] {
+        let resp = match client.get(&url).send().await {
+            Ok(resp) => resp,
+            Err(e) => {
+                log_warn!("Cookie refresh: GET {} failed: {}", url, e);
+                continue;
+            }
+        };
+        for header in resp.headers().get_all(reqwest::header::SET_COOKIE) {
+            let Some((name, value)) = header
+                .to_str()
+                .ok()
+                .and_then(|v| v.split(';').next())
+                .and_then(|pair| pair.split_once('='))
+            else {
+                continue;
+            };
+            let name = name.trim();
+            if name.starts_with("__ddg") {
+                cookies.retain(|(n, _)| n != name);
+                cookies.push((name.to_string(), value.trim().to_string()));
+            }
+        }
+    }
+    if cookies.is_empty() {
+        return Err(anyhow!("{} didn't set any DDoS-Guard cookies", base));
+    }
+    Ok(cookies
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("; "))
+}
+
+/// GET `url` and return its body, retrying connection errors, timeouts, 429
+/// and 5xx with exponential backoff. Other statuses fail immediately. The
+/// final error says how many attempts were made.
+async fn get_text(url: &str, cookie: &str) -> Result<String> {
+    let client = http_client();
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+        let result = client
+            .get(url)
+            .header(reqwest::header::COOKIE, cookie)
+            .send()
+            .await;
+        let (err, transient) = match result {
+            Ok(resp) => {
+                let status = resp.status();
+                let body = match resp.text().await {
+                    Ok(body) => body,
+                    Err(e) if status.is_success() => return Err(anyhow::Error::new(e).context("read response body")),
+                    Err(_) => String::new(),
+                };
+                if is_challenge_page(&body) {
+                    return Err(anyhow!(ChallengePage).context(format!("GET {} returned a challenge page", url)));
+                }
+                if status.is_success() {
+                    return Ok(body);
+                }
+                (
+                    anyhow!("HTTP {}", status),
+                    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+                )
+            }
+            Err(e) => {
+                let transient = e.is_timeout() || e.is_connect() || e.is_request();
+                (anyhow::Error::new(e), transient)
+            }
+        };
+
+        if !transient || attempt == API_ATTEMPTS {
+            return Err(err.context(format!(
+                "GET {} failed after {} attempt(s)",
+                url, attempt
+            )));
+        }
+        log_warn!("GET {} failed ({}), retrying", url, err);
+        tokio::time::sleep(std::time::Duration::from_millis(500 * 2u64.pow(attempt - 1))).await;
     }
 }
 
@@ -168,6 +435,9 @@ pub struct AnimeMetadata {
     pub status: Option<String>,
     pub mal_link: Option<String>,
     pub poster_url: Option<String>,
+    /// Announced episode total; `None` while it's still "?"
+    #[serde(default)]
+    pub episode_count: Option<u32>,
 }
 
 /// Scrape full anime metadata from detail page
@@ -176,16 +446,9 @@ pub async fn fetch_anime_metadata(
     cookie: &str,
     host: &str,
 ) -> Result<AnimeMetadata> {
-    let client = client();
     let base = host.trim_end_matches('/');
     let url = format!("{}/anime/{}", base, slug);
-    let html = client
-        .get(url)
-        .header(reqwest::header::COOKIE, cookie)
-        .send()
-        .await?
-        .text()
-        .await?;
+    let html = get_text(&url, cookie).await?;
 
     let document = scraper::Html::parse_document(&html);
 
@@ -258,12 +521,18 @@ pub async fn fetch_anime_metadata(
             .and_then(|y| y.parse::<u32>().ok())
     });
 
-    // Extract poster image
-    let poster_url = document
-        .select(&scraper::Selector::parse("div.anime-poster img, div.anime-poster a img").unwrap())
-        .next()
-        .and_then(|img| img.value().attr("data-src").or_else(|| img.value().attr("src")))
-        .map(|s| s.to_string());
+    // Extract episode count from the info sidebar ("Episodes: 12")
+    let episode_count = document
+        .select(&scraper::Selector::parse("div.anime-info p").unwrap())
+        .find_map(|p| {
+            let text = p.text().collect::<Vec<_>>().join("");
+            text.trim()
+                .strip_prefix("Episodes:")
+                .and_then(|n| n.trim().parse::<u32>().ok())
+        });
+
+    // Extract poster image, preferring the full-size cover
+    let poster_url = parse_posters(&html, base).pick(PosterSize::Full);
 
     Ok(AnimeMetadata {
         title,
@@ -275,6 +544,7 @@ pub async fn fetch_anime_metadata(
         status,
         mal_link,
         poster_url,
+        episode_count,
     })
 }
 
@@ -283,46 +553,104 @@ pub async fn fetch_anime_poster(
     cookie: &str,
     host: &str,
 ) -> Result<Option<String>> {
-    let client = client();
     let base = host.trim_end_matches('/');
     let url = format!("{}/anime/{}", base, slug);
-    let html = client
-        .get(url)
-        .header(reqwest::header::COOKIE, cookie)
-        .send()
-        .await?
-        .text()
-        .await?;
+    let html = get_text(&url, cookie).await?;
 
-    let document = scraper::Html::parse_document(&html);
+    let posters = parse_posters(&html, base);
+    Ok(posters.full.or(posters.thumbnail))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PosterSize {
+    Thumbnail,
+    #[default]
+    Full,
+}
+
+/// Poster URLs found on an anime page
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AnimePosters {
+    /// The image shown on the page, usually a `.th.jpg` thumbnail
+    pub thumbnail: Option<String>,
+    /// Full-size cover, when the page links one
+    pub full: Option<String>,
+}
+
+impl AnimePosters {
+    /// URL for `size`, falling back to the other size when it's missing
+    pub fn pick(&self, size: PosterSize) -> Option<String> {
+        match size {
+            PosterSize::Full => self.full.clone().or_else(|| self.thumbnail.clone()),
+            PosterSize::Thumbnail => self.thumbnail.clone().or_else(|| self.full.clone()),
+        }
+    }
+}
+
+pub async fn fetch_anime_posters(slug: &str, cookie: &str, host: &str) -> Result<AnimePosters> {
+    let base = host.trim_end_matches('/');
+    let url = format!("{}/anime/{}", base, slug);
+    let html = get_text(&url, cookie).await?;
+    Ok(parse_posters(&html, base))
+}
+
+fn parse_posters(html: &str, base: &str) -> AnimePosters {
+    let document = scraper::Html::parse_document(html);
+    let mut posters = AnimePosters::default();
 
     // Try to find poster image - Animepahe uses div.anime-poster > a > img
     if let Some(img) = document
         .select(&scraper::Selector::parse("div.anime-poster img, div.anime-poster a img").unwrap())
         .next()
     {
-        if let Some(src) = img.value().attr("data-src").or_else(|| img.value().attr("src")) {
-            return Ok(Some(src.to_string()));
-        }
+        posters.thumbnail = img
+            .value()
+            .attr("data-src")
+            .or_else(|| img.value().attr("src"))
+            .map(|src| resolve_image_url(src, base));
     }
 
-    Ok(None)
+    // The poster link points at the full-size image; og:image is the fallback
+    let linked = document
+        .select(&scraper::Selector::parse("div.anime-poster a[href]").unwrap())
+        .filter_map(|a| a.value().attr("href"))
+        .find(|href| is_image_url(href));
+    let og_image = document
+        .select(&scraper::Selector::parse("meta[property='og:image']").unwrap())
+        .filter_map(|m| m.value().attr("content"))
+        .find(|content| !content.trim().is_empty());
+    posters.full = linked
+        .or(og_image)
+        .map(|src| resolve_image_url(src, base))
+        .filter(|full| posters.thumbnail.as_deref() != Some(full.as_str()));
+
+    posters
 }
 
+fn is_image_url(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or(url).to_ascii_lowercase();
+    [".jpg", ".jpeg", ".png", ".webp"]
+        .iter()
+        .any(|ext| path.ends_with(ext))
+}
 
-pub async fn find_session_for_episode(
-    slug: &str,
-    episode: u32,
-    cookie: &str,
-    host: &str,
-) -> Result<String> {
-    let eps = fetch_all_episodes(slug, cookie, host).await?;
-    for e in eps {
-        if e.episode.as_u64() == Some(episode as u64) {
-            return Ok(e.session);
-        }
+/// Resolve an image `src` scraped from the page against the host base.
+/// Handles protocol-relative (`//cdn...`), root-relative (`/images/...`)
+/// and already absolute URLs.
+fn resolve_image_url(src: &str, base: &str) -> String {
+    let src = src.trim();
+    let base = base.trim_end_matches('/');
+    if src.starts_with("http://") || src.starts_with("https://") {
+        src.to_string()
+    } else if let Some(rest) = src.strip_prefix("//") {
+        let scheme = base.split_once("://").map(|(scheme, _)| scheme).unwrap_or("https");
+        format!("{}://{}", scheme, rest)
+    } else if src.starts_with('/') {
+        format!("{}{}", base, src)
+    } else {
+        format!("{}/{}", base, src)
     }
-    Err(anyhow!("Episode {} not found", episode))
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -340,18 +668,10 @@ pub async fn fetch_featured_anime(
     cookie: &str,
     host: &str,
 ) -> Result<Vec<FeaturedAnime>> {
-    let client = client();
     let base = host.trim_end_matches('/');
     let url = format!("{}/", base);
 
-    let html = client
-        .get(&url)
-        .header(reqwest::header::COOKIE, cookie)
-        .send()
-        .await?
-        .error_for_status()?
-        .text()
-        .await?;
+    let html = get_text(&url, cookie).await?;
 
     let document = scraper::Html::parse_document(&html);
     let mut featured = Vec::new();
@@ -427,7 +747,7 @@ pub async fn fetch_featured_anime(
 pub struct LatestRelease {
     pub slug: String,
     pub anime_title: String,
-    pub episode_number: u32,
+    pub episode_number: EpisodeNumber,
     pub snapshot_url: String,
     pub session_id: String,
 }
@@ -468,7 +788,7 @@ struct LatestReleaseApiData {
     pub anime_id: u32,
     pub anime_title: String,
     pub anime_session: String,
-    pub episode: u32,
+    pub episode: EpisodeNumber,
     pub snapshot: String,
     pub session: String,
     #[allow(dead_code)]
@@ -480,25 +800,17 @@ pub async fn fetch_latest_releases(
     host: &str,
     page: u32,
 ) -> Result<PaginatedLatestReleases> {
-    let client = client();
     let base = host.trim_end_matches('/');
 
     // Try the API endpoint for latest releases
     // Based on existing API patterns, AnimePahe likely uses /api?m=airing or similar
     let api_url = format!("{}/api?m=airing&page={}", base, page);
 
-    let text = client
-        .get(&api_url)
-        .header(reqwest::header::COOKIE, cookie)
-        .send()
-        .await?
-        .error_for_status()?
-        .text()
-        .await?;
+    let text = get_text(&api_url, cookie).await?;
 
 
-    let api_response: LatestReleaseApiResponse = serde_json::from_str(&text)
-        .context("Failed to parse latest releases API response")?;
+    let api_response: LatestReleaseApiResponse =
+        parse_json(&text, "Failed to parse latest releases API response")?;
 
 
     let releases: Vec<LatestRelease> = api_response.data
@@ -530,18 +842,11 @@ pub async fn fetch_latest_releases(
     })
 }
 
-fn client() -> Client {
-    reqwest::Client::builder()
-        .user_agent("Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/115 Safari/537.36")
-        .build()
-        .expect("client")
-}
-
 pub async fn fetch_image_with_referer(
     url: &str,
     host: &str,
 ) -> Result<Vec<u8>> {
-    let client = client();
+    let client = http_client();
     let base = host.trim_end_matches('/');
 
     let bytes = client
@@ -555,3 +860,83 @@ pub async fn fetch_image_with_referer(
 
     Ok(bytes.to_vec())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fractional_episode_is_selectable() {
+        let list: Vec<Episode> = serde_json::from_str(
+            r#"[
+                {"episode": 12, "session": "a"},
+                {"episode": "12.5", "session": "b"},
+                {"episode": 13, "session": "c"}
+            ]"#,
+        )
+        .unwrap();
+        let numbers: Vec<EpisodeNumber> = list.iter().filter_map(Episode::number).collect();
+        assert_eq!(numbers.len(), 3);
+
+        // The UI sends selections back as plain JSON numbers
+        let selected: EpisodeNumber = serde_json::from_str("12.5").unwrap();
+        let session = list
+            .iter()
+            .find(|ep| ep.number() == Some(selected))
+            .map(|ep| ep.session.as_str());
+        assert_eq!(session, Some("b"));
+        assert_eq!(selected.to_string(), "12.5");
+        assert_eq!(selected.suffix(), ".5");
+        assert!(!EpisodeNumber::from(12).precedes(selected));
+    }
+
+    #[test]
+    fn parses_numeric_episode() {
+        let ep: Episode = serde_json::from_str(r#"{"episode": 12, "session": "a"}"#).unwrap();
+        assert_eq!(parse_episode_number(&ep.episode), Some(12.0));
+        assert_eq!(ep.number(), Some(EpisodeNumber::from(12)));
+    }
+
+    #[test]
+    fn parses_string_episode() {
+        let ep: Episode = serde_json::from_str(r#"{"episode": "12", "session": "a"}"#).unwrap();
+        assert_eq!(parse_episode_number(&ep.episode), Some(12.0));
+        assert_eq!(ep.number(), Some(EpisodeNumber::from(12)));
+        assert_eq!(parse_episode_number(&serde_json::json!(" 7 ")), Some(7.0));
+    }
+
+    #[test]
+    fn rejects_non_numeric_episode() {
+        assert_eq!(parse_episode_number(&serde_json::json!("OVA")), None);
+        assert_eq!(parse_episode_number(&serde_json::json!(-1)), None);
+        assert_eq!(parse_episode_number(&serde_json::Value::Null), None);
+    }
+
+    #[test]
+    fn resolves_protocol_relative_image_url() {
+        assert_eq!(
+            resolve_image_url("//i.animepahe.ru/posters/abc.jpg", "https://animepahe.ru/"),
+            "https://i.animepahe.ru/posters/abc.jpg"
+        );
+        assert_eq!(
+            resolve_image_url("//cdn.example/abc.jpg", "http://mirror.example"),
+            "http://cdn.example/abc.jpg"
+        );
+    }
+
+    #[test]
+    fn resolves_root_relative_image_url() {
+        assert_eq!(
+            resolve_image_url("/images/abc.png", "https://animepahe.ru/"),
+            "https://animepahe.ru/images/abc.png"
+        );
+    }
+
+    #[test]
+    fn keeps_absolute_image_url() {
+        assert_eq!(
+            resolve_image_url(" https://cdn.example/abc.webp ", "https://animepahe.ru"),
+            "https://cdn.example/abc.webp"
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+use std::{collections::BTreeMap, path::{Path, PathBuf}, sync::Arc};
 use std::collections::HashMap;
 use std::sync::Mutex as StdMutex;
 use std::io::Write;
@@ -10,34 +10,291 @@ use serde::{Deserialize, Serialize};
 use tauri::path::BaseDirectory;
 use tauri::{async_runtime::JoinHandle, AppHandle, Emitter, Manager, State, Window};
 use base64::Engine;
+use futures::StreamExt;
 
 use crate::{
-    api, download, scrape,
+    api::{self, EpisodeNumber},
+    download, scrape,
     settings::{self, AppSettings, AppState},
     download_tracker::{DownloadTracker, DownloadRecord},
+    metrics::{Metrics, Stage},
 };
 
+// A running episode download: its pause/cancel signal plus the shared progress counters
+struct ActiveDownload {
+    cancel_tx: tokio::sync::watch::Sender<download::DownloadControl>,
+    episode: EpisodeNumber,
+    anime_name: String,
+    done: Arc<std::sync::atomic::AtomicUsize>,
+    segments_done: Arc<std::sync::atomic::AtomicUsize>,
+    // The parallel path counts bytes and leaves its segments on disk when interrupted;
+    // the ffmpeg path counts media milliseconds and keeps nothing reusable
+    segmented: bool,
+}
+
+/// Latest figures from one episode's progress loop, for the tray summary
+#[derive(Debug, Clone, Copy)]
+struct EpisodeProgress {
+    /// Share done, when the total is known yet
+    fraction: Option<f64>,
+    bytes_per_sec: f64,
+}
+
+/// Tray tooltip while nothing is downloading
+pub const TRAY_TOOLTIP: &str = "Animepahe DL Desktop";
+
+/// A running episode as the tray menu lists it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveSummary {
+    pub download_id: String,
+    pub episode: EpisodeNumber,
+    /// "Naruto E12 – 38%"
+    pub label: String,
+}
+
 // Track active downloads for cancellation
 #[derive(Clone)]
 pub struct DownloadState {
-    active: Arc<TokioMutex<HashMap<u32, tokio::sync::watch::Sender<bool>>>>,
+    // Keyed by tracker download id; two shows can be on the same episode number
+    active: Arc<TokioMutex<HashMap<String, ActiveDownload>>>,
+    host_limiter: download::HostLimiter,
+    bandwidth: download::BandwidthLimiter,
+    // Segment workers and the batch scheduler wait while this is true; in-flight segments finish
+    paused: Arc<tokio::sync::watch::Sender<bool>>,
+    // Why we're paused; `paused` carries the OR of these
+    user_paused: Arc<std::sync::atomic::AtomicBool>,
+    metered_paused: Arc<std::sync::atomic::AtomicBool>,
+    extraction_cache: scrape::ExtractionCache,
+    metrics: Metrics,
+    // Keyed by tracker download id; fed by each progress loop
+    progress: Arc<StdMutex<HashMap<String, EpisodeProgress>>>,
 }
 
 impl DownloadState {
-    pub fn new() -> Self {
+    pub fn new(max_connections_per_host: usize, metrics: Metrics) -> Self {
+        let (paused, _) = tokio::sync::watch::channel(false);
         Self {
             active: Arc::new(TokioMutex::new(HashMap::new())),
+            host_limiter: download::HostLimiter::new(max_connections_per_host),
+            bandwidth: download::BandwidthLimiter::new(0),
+            paused: Arc::new(paused),
+            user_paused: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            metered_paused: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            extraction_cache: scrape::ExtractionCache::new(),
+            metrics,
+            progress: Arc::new(StdMutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn set_metered_paused(&self, paused: bool) {
+        self.metered_paused
+            .store(paused, std::sync::atomic::Ordering::Relaxed);
+        self.refresh_paused();
+    }
+
+    /// "3 downloading · 42% · 5.2 MB/s" across active episodes, or the
+    /// plain tooltip when nothing is running
+    pub fn tray_summary(&self) -> String {
+        let progress = self.progress.lock().unwrap();
+        if progress.is_empty() {
+            return TRAY_TOOLTIP.to_string();
+        }
+        let fractions: Vec<f64> = progress.values().filter_map(|p| p.fraction).collect();
+        let speed: f64 = progress.values().map(|p| p.bytes_per_sec).sum();
+        let mut parts = vec![format!("{} downloading", progress.len())];
+        if !fractions.is_empty() {
+            let percent = fractions.iter().sum::<f64>() / fractions.len() as f64 * 100.0;
+            parts.push(format!("{:.0}%", percent.clamp(0.0, 100.0)));
+        }
+        parts.push(if speed >= 1_000_000.0 {
+            format!("{:.1} MB/s", speed / 1_000_000.0)
+        } else {
+            format!("{:.0} KB/s", speed / 1_000.0)
+        });
+        parts.join(" · ")
+    }
+
+    /// Episodes currently downloading
+    pub fn active_count(&self) -> usize {
+        self.progress.lock().unwrap().len()
+    }
+
+    /// Running episodes in episode order, labelled with their progress
+    pub async fn active_summaries(&self) -> Vec<ActiveSummary> {
+        let active = self.active.lock().await;
+        let progress = self.progress.lock().unwrap();
+        let mut summaries: Vec<ActiveSummary> = active
+            .iter()
+            .map(|(id, entry)| {
+                let mut label = format!("{} E{}", entry.anime_name, entry.episode);
+                if let Some(fraction) = progress.get(id).and_then(|p| p.fraction) {
+                    label.push_str(&format!(" – {:.0}%", (fraction * 100.0).clamp(0.0, 100.0)));
+                }
+                ActiveSummary {
+                    download_id: id.clone(),
+                    episode: entry.episode,
+                    label,
+                }
+            })
+            .collect();
+        // Ties on episode number need a stable order too, or the tray keeps rebuilding
+        summaries.sort_by(|a, b| (a.episode, &a.download_id).cmp(&(b.episode, &b.download_id)));
+        summaries
+    }
+
+    fn set_user_paused(&self, paused: bool) {
+        self.user_paused
+            .store(paused, std::sync::atomic::Ordering::Relaxed);
+        self.refresh_paused();
+    }
+
+    fn refresh_paused(&self) {
+        let paused = self.user_paused.load(std::sync::atomic::Ordering::Relaxed)
+            || self.metered_paused.load(std::sync::atomic::Ordering::Relaxed);
+        self.paused.send_replace(paused);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Why downloads are held, user pause taking precedence
+    pub fn pause_reason(&self) -> Option<&'static str> {
+        if self.user_paused.load(std::sync::atomic::Ordering::Relaxed) {
+            Some("user")
+        } else if self.metered_paused.load(std::sync::atomic::Ordering::Relaxed) {
+            Some("metered")
+        } else {
+            None
         }
     }
 }
 
+// Cancellation flags for long-running maintenance operations, keyed by op name
+#[derive(Clone)]
+pub struct BulkOpState {
+    ops: Arc<StdMutex<HashMap<String, Arc<std::sync::atomic::AtomicBool>>>>,
+}
+
+impl BulkOpState {
+    pub fn new() -> Self {
+        Self {
+            ops: Arc::new(StdMutex::new(HashMap::new())),
+        }
+    }
+
+    fn begin(&self, op: &str) -> Result<Arc<std::sync::atomic::AtomicBool>, String> {
+        let mut ops = self.ops.lock().unwrap();
+        if ops.contains_key(op) {
+            return Err(format!("{} is already running", op));
+        }
+        let flag = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        ops.insert(op.to_string(), flag.clone());
+        Ok(flag)
+    }
+
+    fn finish(&self, op: &str) {
+        self.ops.lock().unwrap().remove(op);
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BulkOpProgress {
+    pub op: String,
+    pub done: usize,
+    pub total: usize,
+    pub current: Option<String>,
+}
+
+fn emit_bulk_progress(window: &Window, op: &str, done: usize, total: usize, current: Option<&str>) {
+    let _ = window.emit(
+        "bulk-op-progress",
+        BulkOpProgress {
+            op: op.to_string(),
+            done,
+            total,
+            current: current.map(|c| c.to_string()),
+        },
+    );
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PausedPayload {
+    pub paused: bool,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct QueueState {
+    pub paused: bool,
+    /// "user" or "metered" while paused
+    pub reason: Option<String>,
+    pub active_episodes: Vec<EpisodeNumber>,
+}
+
+async fn queue_state(download_state: &DownloadState) -> QueueState {
+    let mut active_episodes: Vec<EpisodeNumber> =
+        download_state.active.lock().await.values().map(|entry| entry.episode).collect();
+    active_episodes.sort_unstable();
+    QueueState {
+        paused: download_state.is_paused(),
+        reason: download_state.pause_reason().map(String::from),
+        active_episodes,
+    }
+}
+
+/// Hold every download: segment workers stop picking up new segments and
+/// batches don't start their next episode. Single-connection (ffmpeg)
+/// downloads run to completion.
+#[tauri::command]
+pub async fn pause_queue(
+    window: Window,
+    download_state: State<'_, DownloadState>,
+) -> Result<QueueState, String> {
+    download_state.set_user_paused(true);
+    let _ = window.emit(
+        "downloads-paused",
+        PausedPayload {
+            paused: true,
+            reason: "user".into(),
+        },
+    );
+    Ok(queue_state(&download_state).await)
+}
+
+/// Lift a user pause; downloads continue from the segments they already have
+#[tauri::command]
+pub async fn resume_queue(
+    window: Window,
+    download_state: State<'_, DownloadState>,
+) -> Result<QueueState, String> {
+    download_state.set_user_paused(false);
+    let _ = window.emit(
+        "downloads-paused",
+        PausedPayload {
+            paused: download_state.is_paused(),
+            reason: download_state.pause_reason().unwrap_or("user").into(),
+        },
+    );
+    Ok(queue_state(&download_state).await)
+}
+
+#[tauri::command]
+pub async fn get_queue_state(
+    download_state: State<'_, DownloadState>,
+) -> Result<QueueState, String> {
+    Ok(queue_state(&download_state).await)
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct EpisodeInfo {
-    pub number: u32,
+    pub number: EpisodeNumber,
     pub session: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub snapshot_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aired_at: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -57,17 +314,28 @@ pub struct FetchEpisodesResponse {
 
 #[derive(Debug, Serialize)]
 pub struct PreviewItem {
-    pub episode: u32,
+    pub episode: EpisodeNumber,
     pub sources: Vec<scrape::Candidate>,
 }
 
 #[derive(Debug, Serialize, Clone)]
 pub struct DownloadCompleteNotification {
     pub anime_name: String,
-    pub episode: u32,
+    pub episode: EpisodeNumber,
     pub file_path: String,
     pub file_size: i64,
     pub success: bool,
+    /// Whether the notification settings want an OS popup for this event.
+    /// Completion popups are sent by the backend itself, with actions.
+    pub desktop: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CancelResult {
+    pub cancelled: bool,
+    pub bytes_downloaded: u64,
+    pub segments_done: usize,
+    pub partial_kept: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -84,21 +352,119 @@ pub async fn load_settings(state: State<'_, AppState>) -> Result<AppSettings, St
 #[tauri::command]
 pub async fn save_settings(
     state: State<'_, AppState>,
-    settings: AppSettings,
+    download_state: State<'_, DownloadState>,
+    mut settings: AppSettings,
 ) -> Result<(), String> {
+    // Profiles are edited through their own commands; the settings form doesn't send them
+    settings.host_profiles = state.settings.lock().unwrap().host_profiles.clone();
+    crate::network::configure(&settings.proxy_url, &settings.user_agent)
+        .map_err(|err| format!("{:#}", err))?;
+    crate::logging::set_level(&settings.log_level);
+    // Applies to downloads already running, not just the next batch
+    download_state.bandwidth.set_rate(settings.max_bandwidth_kbps);
     state.persist(settings).map_err(|err| err.to_string())
 }
 
+/// Save another host to switch to. Returns the updated settings.
+#[tauri::command]
+pub async fn add_host_profile(state: State<'_, AppState>, url: String) -> Result<AppSettings, String> {
+    let url = settings::normalize_host(&url);
+    let parsed = reqwest::Url::parse(&url).map_err(|e| format!("Invalid host URL '{}': {}", url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        return Err(format!("Host URL '{}' must be an http(s) address", url));
+    }
+
+    let mut updated = state.settings.lock().unwrap().clone();
+    if !updated.host_profiles.contains(&url) {
+        updated.host_profiles.push(url);
+    }
+    state.persist(updated).map_err(|err| err.to_string())?;
+    Ok(state.settings.lock().unwrap().clone())
+}
+
+/// Forget a saved host. The active one can't be removed.
+#[tauri::command]
+pub async fn remove_host_profile(state: State<'_, AppState>, url: String) -> Result<AppSettings, String> {
+    let url = settings::normalize_host(&url);
+    let mut updated = state.settings.lock().unwrap().clone();
+    if updated.host_url == url {
+        return Err("Switch to another host before removing the active one".into());
+    }
+    updated.host_profiles.retain(|h| *h != url);
+    state.persist(updated).map_err(|err| err.to_string())?;
+    Ok(state.settings.lock().unwrap().clone())
+}
+
+/// Make a saved host the active one. Returns the updated settings.
+#[tauri::command]
+pub async fn select_host_profile(state: State<'_, AppState>, url: String) -> Result<AppSettings, String> {
+    let url = settings::normalize_host(&url);
+    let mut updated = state.settings.lock().unwrap().clone();
+    if !updated.host_profiles.contains(&url) {
+        return Err(format!("No saved host {}", url));
+    }
+    let changed = updated.host_url != url;
+    updated.host_url = url;
+    state.persist(updated).map_err(|err| err.to_string())?;
+    // DDoS-Guard cookies belong to the old domain
+    if changed {
+        state.set_cookie(None);
+    }
+    Ok(state.settings.lock().unwrap().clone())
+}
+
+/// Run an API call with the session cookie. If the host answers with a
+/// DDoS-Guard challenge, fetch fresh cookies (or roll a new random one when
+/// it won't hand any out) and retry once.
+async fn with_cookie_refresh<T, F, Fut>(state: &AppState, host: &str, call: F) -> anyhow::Result<T>
+where
+    F: Fn(String) -> Fut,
+    Fut: std::future::Future<Output = anyhow::Result<T>>,
+{
+    match call(state.cookie()).await {
+        Err(err) if api::is_challenge(&err) => {
+            log_warn!("{} served a challenge page, refreshing cookie", host);
+            match api::refresh_cookie(host).await {
+                Ok(cookie) => state.set_cookie(Some(cookie)),
+                Err(e) => {
+                    log_warn!("Cookie refresh failed ({}), using a new random cookie", e);
+                    state.set_cookie(None);
+                }
+            }
+            call(state.cookie()).await
+        }
+        result => result,
+    }
+}
+
+/// The session cookie and refresh history, for troubleshooting challenge pages
+#[tauri::command]
+pub fn get_cookie_state(state: State<'_, AppState>) -> settings::CookieState {
+    state.cookie_state()
+}
+
 #[tauri::command]
 pub async fn search_anime(
     state: State<'_, AppState>,
+    metrics: State<'_, Metrics>,
     req: SearchRequest,
 ) -> Result<Vec<api::SearchItem>, String> {
-    let cookie = state.cookie();
     let host = settings::normalize_host(&req.host);
-    api::search_anime(&req.name, &cookie, &host)
-        .await
-        .map_err(|err| err.to_string())
+    let (name, host) = (req.name.as_str(), host.as_str());
+    let started = std::time::Instant::now();
+    let results = with_cookie_refresh(&state, host, |cookie| async move {
+        api::search_anime(name, &cookie, host).await
+    })
+    .await
+    .map_err(|err| err.to_string());
+    metrics.record_since(Stage::Search, started);
+    results
+}
+
+/// Percentiles of recent pipeline stage durations
+#[tauri::command]
+pub fn get_performance_metrics(metrics: State<'_, Metrics>) -> Vec<crate::metrics::StageMetrics> {
+    metrics.summary()
 }
 
 #[derive(Debug, Deserialize)]
@@ -111,11 +477,13 @@ pub async fn fetch_featured_anime(
     state: State<'_, AppState>,
     req: FeaturedAnimeRequest,
 ) -> Result<Vec<api::FeaturedAnime>, String> {
-    let cookie = state.cookie();
     let host = settings::normalize_host(&req.host);
-    api::fetch_featured_anime(&cookie, &host)
-        .await
-        .map_err(|err| err.to_string())
+    let host = host.as_str();
+    with_cookie_refresh(&state, host, |cookie| async move {
+        api::fetch_featured_anime(&cookie, host).await
+    })
+    .await
+    .map_err(|err| err.to_string())
 }
 
 #[derive(Debug, Deserialize)]
@@ -129,12 +497,91 @@ pub async fn fetch_latest_releases(
     state: State<'_, AppState>,
     req: LatestReleasesRequest,
 ) -> Result<api::PaginatedLatestReleases, String> {
-    let cookie = state.cookie();
     let host = settings::normalize_host(&req.host);
+    let host = host.as_str();
     let page = req.page.unwrap_or(1);
-    api::fetch_latest_releases(&cookie, &host, page)
-        .await
-        .map_err(|err| err.to_string())
+    with_cookie_refresh(&state, host, |cookie| async move {
+        api::fetch_latest_releases(&cookie, host, page).await
+    })
+    .await
+    .map_err(|err| err.to_string())
+}
+
+fn episode_infos(episodes: Vec<api::Episode>) -> Vec<EpisodeInfo> {
+    episodes
+        .into_iter()
+        .filter_map(|ep| {
+            Some(EpisodeInfo {
+                number: ep.number()?,
+                aired_at: ep.aired_on().map(|d| d.to_string()),
+                session: ep.session,
+                snapshot_url: ep.snapshot,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FetchEpisodePagesRequest {
+    pub slug: String,
+    pub host: String,
+    #[serde(default)]
+    pub sort: api::EpisodeSort,
+    /// First release page to read, 1-based
+    pub page: u32,
+    /// Last page to read; defaults to just `page`
+    #[serde(default)]
+    pub page_to: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpisodePagesResponse {
+    pub episodes: Vec<EpisodeInfo>,
+    pub last_page: u32,
+}
+
+/// A slice of an anime's episode list, for lazy loading. Page 1 sorted
+/// `desc` holds the newest episodes.
+#[tauri::command]
+pub async fn fetch_episode_pages(
+    state: State<'_, AppState>,
+    req: FetchEpisodePagesRequest,
+) -> Result<EpisodePagesResponse, String> {
+    let host = settings::normalize_host(&req.host);
+    let (slug, host) = (req.slug.as_str(), host.as_str());
+    let (sort, page, page_to) = (req.sort, req.page, req.page_to.unwrap_or(req.page));
+    let (episodes, last_page) = with_cookie_refresh(&state, host, |cookie| async move {
+        api::fetch_episode_range(slug, sort, page, Some(page_to), &cookie, host).await
+    })
+    .await
+    .map_err(|err| err.to_string())?;
+    Ok(EpisodePagesResponse {
+        episodes: episode_infos(episodes),
+        last_page,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnimeDetailsRequest {
+    pub slug: String,
+    pub host: String,
+}
+
+/// Synopsis, genres, status, year and episode count for the info panel.
+/// Anything the page doesn't list comes back empty rather than failing.
+#[tauri::command]
+pub async fn fetch_anime_details(
+    state: State<'_, AppState>,
+    req: AnimeDetailsRequest,
+) -> Result<api::AnimeMetadata, String> {
+    let host = settings::normalize_host(&req.host);
+    let (slug, host) = (req.slug.as_str(), host.as_str());
+    with_cookie_refresh(&state, host, |cookie| async move {
+        api::fetch_anime_metadata(slug, &cookie, host).await
+    })
+    .await
+    .map_err(|err| err.to_string())
 }
 
 #[derive(Debug, Deserialize)]
@@ -142,6 +589,8 @@ pub struct FetchEpisodesRequest {
     pub slug: String,
     pub host: String,
     pub name_hint: String,
+    #[serde(default)]
+    pub sort: api::EpisodeSort,
 }
 
 #[tauri::command]
@@ -149,11 +598,14 @@ pub async fn fetch_episodes(
     state: State<'_, AppState>,
     req: FetchEpisodesRequest,
 ) -> Result<FetchEpisodesResponse, String> {
-    let cookie = state.cookie();
     let host = settings::normalize_host(&req.host);
-    let episodes = api::fetch_all_episodes(&req.slug, &cookie, &host)
-        .await
-        .map_err(|err| err.to_string())?;
+    let (slug, sort, host) = (req.slug.as_str(), req.sort, host.as_str());
+    let episodes = with_cookie_refresh(&state, host, |cookie| async move {
+        api::fetch_all_episodes(slug, sort, &cookie, host).await
+    })
+    .await
+    .map_err(|err| err.to_string())?;
+    let cookie = state.cookie();
 
     // Fetch full anime metadata
     let metadata = api::fetch_anime_metadata(&req.slug, &cookie, &host)
@@ -168,20 +620,11 @@ pub async fn fetch_episodes(
             status: None,
             mal_link: None,
             poster_url: None,
+            episode_count: None,
         });
 
-    let mut items = Vec::new();
-    for ep in episodes {
-        if let Some(num) = ep.episode.as_u64() {
-            items.push(EpisodeInfo {
-                number: num as u32,
-                session: ep.session.clone(),
-                snapshot_url: ep.snapshot.clone(),
-            });
-        }
-    }
     Ok(FetchEpisodesResponse {
-        episodes: items,
+        episodes: episode_infos(episodes),
         display_name: metadata.title,
         poster_url: metadata.poster_url,
         status: metadata.status,
@@ -194,35 +637,179 @@ pub async fn fetch_episodes(
     })
 }
 
+#[derive(Debug, Serialize)]
+pub struct EpisodeSelectionCheck {
+    /// Requested episodes the anime actually has, in request order
+    pub valid: Vec<EpisodeNumber>,
+    /// Requested episodes that don't exist (out of range, specials, typos)
+    pub skipped: Vec<EpisodeNumber>,
+}
+
+fn episode_sessions(episodes: &[api::Episode]) -> BTreeMap<EpisodeNumber, String> {
+    episodes
+        .iter()
+        .filter_map(|ep| Some((ep.number()?, ep.session.clone())))
+        .collect()
+}
+
+/// Episode -> session map for a series, from the cache when it's fresh
+async fn cached_sessions(
+    cache: &scrape::ExtractionCache,
+    slug: &str,
+    cookie: &str,
+    host: &str,
+) -> anyhow::Result<BTreeMap<EpisodeNumber, String>> {
+    if let Some(sessions) = cache.sessions(slug, host) {
+        return Ok(sessions);
+    }
+    let list = api::fetch_all_episodes(slug, api::EpisodeSort::Asc, cookie, host).await?;
+    let sessions = episode_sessions(&list);
+    cache.store_sessions(slug, host, &sessions);
+    Ok(sessions)
+}
+
+fn check_episode_selection(requested: &[EpisodeNumber], sessions: &BTreeMap<EpisodeNumber, String>) -> EpisodeSelectionCheck {
+    let (valid, skipped) = requested.iter().copied().partition(|ep| sessions.contains_key(ep));
+    EpisodeSelectionCheck { valid, skipped }
+}
+
+/// Collapse sorted runs for status messages: `[13, 14, 15, 20]` -> `13-15, 20`
+fn format_episode_list(episodes: &[EpisodeNumber]) -> String {
+    let mut sorted = episodes.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+    let mut parts = Vec::new();
+    let mut i = 0;
+    while i < sorted.len() {
+        let start = sorted[i];
+        while i + 1 < sorted.len() && sorted[i].precedes(sorted[i + 1]) {
+            i += 1;
+        }
+        let end = sorted[i];
+        parts.push(if start == end {
+            start.to_string()
+        } else {
+            format!("{}-{}", start, end)
+        });
+        i += 1;
+    }
+    parts.join(", ")
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ValidateEpisodesRequest {
+    pub slug: String,
+    pub host: String,
+    pub episodes: Vec<EpisodeNumber>,
+}
+
+/// Dry run: which of the requested episodes exist, without downloading anything
+#[tauri::command]
+pub async fn validate_episode_selection(
+    state: State<'_, AppState>,
+    req: ValidateEpisodesRequest,
+) -> Result<EpisodeSelectionCheck, String> {
+    let host = settings::normalize_host(&req.host);
+    let (slug, host) = (req.slug.as_str(), host.as_str());
+    let list = with_cookie_refresh(&state, host, |cookie| async move {
+        api::fetch_all_episodes(slug, api::EpisodeSort::Asc, &cookie, host).await
+    })
+    .await
+    .map_err(|err| err.to_string())?;
+    Ok(check_episode_selection(&req.episodes, &episode_sessions(&list)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AirDateRequest {
+    pub slug: String,
+    pub host: String,
+    /// Inclusive `YYYY-MM-DD` bounds; either may be omitted
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub to: Option<String>,
+    /// Shorthand for "aired in the last N days"; overrides `from`
+    #[serde(default)]
+    pub last_days: Option<u32>,
+}
+
+/// Episode numbers that aired inside the requested date range
+#[tauri::command]
+pub async fn select_episodes_by_air_date(
+    state: State<'_, AppState>,
+    req: AirDateRequest,
+) -> Result<Vec<EpisodeNumber>, String> {
+    let parse_bound = |value: &Option<String>, name: &str| -> Result<Option<chrono::NaiveDate>, String> {
+        match value.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+            Some(v) => api::parse_air_date(v)
+                .map(Some)
+                .ok_or_else(|| format!("Invalid {name} date '{v}', expected YYYY-MM-DD")),
+            None => Ok(None),
+        }
+    };
+    let mut from = parse_bound(&req.from, "start")?;
+    let to = parse_bound(&req.to, "end")?;
+    if let Some(days) = req.last_days {
+        from = Some(chrono::Local::now().date_naive() - chrono::Duration::days(days as i64));
+    }
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            return Err("Start date is after end date".into());
+        }
+    }
+
+    let host = settings::normalize_host(&req.host);
+    let (slug, host) = (req.slug.as_str(), host.as_str());
+    let episodes = with_cookie_refresh(&state, host, |cookie| async move {
+        api::fetch_all_episodes(slug, api::EpisodeSort::Asc, &cookie, host).await
+    })
+    .await
+    .map_err(|err| err.to_string())?;
+
+    if !episodes.iter().any(|ep| ep.aired_on().is_some()) {
+        return Err("Air dates aren't available for this anime; select episodes by number instead".into());
+    }
+
+    let mut selected: Vec<EpisodeNumber> = episodes
+        .iter()
+        .filter_map(|ep| Some((ep.number()?, ep.aired_on()?)))
+        .filter(|(_, aired)| !from.is_some_and(|f| *aired < f) && !to.is_some_and(|t| *aired > t))
+        .map(|(number, _)| number)
+        .collect();
+    selected.sort_unstable();
+    selected.dedup();
+    Ok(selected)
+}
+
 #[derive(Debug, Deserialize)]
 pub struct PreviewRequest {
     pub slug: String,
     pub host: String,
-    pub episodes: Vec<u32>,
+    pub episodes: Vec<EpisodeNumber>,
     pub cached: Vec<EpisodeInfo>,
+    /// Resolve every source and sample its segments for `approx_bytes`.
+    /// Slow, so off unless asked for.
+    #[serde(default)]
+    pub estimate_size: bool,
 }
 
 #[tauri::command]
 pub async fn preview_sources(
     state: State<'_, AppState>,
+    download_state: State<'_, DownloadState>,
     req: PreviewRequest,
 ) -> Result<Vec<PreviewItem>, String> {
     let cookie = state.cookie();
     let host = settings::normalize_host(&req.host);
-    let mut session_map: BTreeMap<u32, String> = req
+    let mut session_map: BTreeMap<EpisodeNumber, String> = req
         .cached
         .into_iter()
         .map(|c| (c.number, c.session))
         .collect();
     if session_map.is_empty() {
-        let episodes = api::fetch_all_episodes(&req.slug, &cookie, &host)
+        session_map = cached_sessions(&download_state.extraction_cache, &req.slug, &cookie, &host)
             .await
             .map_err(|err| err.to_string())?;
-        for ep in episodes {
-            if let Some(num) = ep.episode.as_u64() {
-                session_map.insert(num as u32, ep.session.clone());
-            }
-        }
     }
 
     let mut items = Vec::new();
@@ -232,9 +819,31 @@ pub async fn preview_sources(
             .cloned()
             .ok_or_else(|| format!("Episode {ep} not found"))?;
         let play_page = format!("{}/play/{}/{}", host, req.slug, sess);
-        let sources = scrape::extract_candidates(&play_page, &cookie)
+        let mut sources = scrape::extract_candidates(&play_page, &cookie)
             .await
             .map_err(|err| err.to_string())?;
+        if req.estimate_size {
+            let cache = &download_state.extraction_cache;
+            let (slug, cookie, host) = (req.slug.as_str(), cookie.as_str(), host.as_str());
+            let sizes = futures::future::join_all(sources.iter().map(|candidate| async move {
+                let m3u8 = match cache.playlist(slug, ep, &candidate.src) {
+                    Some(m3u8) => m3u8,
+                    None => {
+                        let m3u8 = scrape::extract_m3u8_from_link(&candidate.src, cookie, host).await.ok()?;
+                        cache.store_playlist(slug, ep, &candidate.src, &m3u8);
+                        m3u8
+                    }
+                };
+                download::estimate_stream_size(&m3u8, cookie, host, candidate.resolution.as_deref())
+                    .await
+                    .ok()
+                    .flatten()
+            }))
+            .await;
+            for (candidate, size) in sources.iter_mut().zip(sizes) {
+                candidate.approx_bytes = size;
+            }
+        }
         items.push(PreviewItem {
             episode: ep,
             sources,
@@ -258,12 +867,66 @@ pub async fn resolve_video_url(
         .map_err(|err| err.to_string())
 }
 
+#[tauri::command]
+pub fn supported_providers() -> Vec<scrape::Provider> {
+    scrape::PROVIDERS.to_vec()
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExtractionTestResult {
+    pub m3u8: Option<String>,
+    /// Which step stopped extraction, when it failed
+    pub failed_stage: Option<scrape::ExtractionStage>,
+    pub error: Option<String>,
+    /// Debug builds only: size of the fetched page and the unpacked script output
+    pub page_len: Option<usize>,
+    pub unpacked_len: Option<usize>,
+}
+
+/// Run playlist extraction alone on one candidate URL, to separate
+/// extraction breakage from session or candidate lookup problems
+#[tauri::command]
+pub async fn test_extraction(
+    state: State<'_, AppState>,
+    candidate_src: String,
+    host: String,
+) -> Result<ExtractionTestResult, String> {
+    let cookie = state.cookie();
+    let host = settings::normalize_host(&host);
+    let mut trace = scrape::ExtractionTrace::default();
+    let result = scrape::extract_m3u8_traced(&candidate_src, &cookie, &host, &mut trace).await;
+
+    let debug = cfg!(debug_assertions);
+    let (m3u8, failed_stage, error) = match result {
+        Ok(url) => (Some(url), None, None),
+        Err(err) => (None, Some(trace.stage), Some(format!("{:#}", err))),
+    };
+    Ok(ExtractionTestResult {
+        m3u8,
+        failed_stage,
+        error,
+        page_len: trace.page_len.filter(|_| debug),
+        unpacked_len: trace.unpacked_len.filter(|_| debug),
+    })
+}
+
+#[tauri::command]
+pub fn metered_detection_supported() -> bool {
+    crate::network::metered_detection_supported()
+}
+
+/// Hardware accelerators the settings screen should offer on this platform
+#[tauri::command]
+pub fn supported_hwaccels() -> Vec<settings::HwAccel> {
+    settings::HwAccel::supported()
+}
+
 // Request type for start_download command
 #[derive(Debug, Deserialize)]
 pub struct StartDownloadRequest {
     pub anime_name: String,
     pub anime_slug: String,
-    pub episodes: Vec<u32>,
+    pub episodes: Vec<EpisodeNumber>,
     pub audio_type: Option<String>,
     pub resolution: Option<String>,
     pub download_dir: Option<String>,
@@ -272,6 +935,31 @@ pub struct StartDownloadRequest {
     pub resume_download_id: Option<String>,
     #[serde(default)]
     pub threads: Option<usize>,
+    /// Mux chapter markers from the playlist into the output when available
+    #[serde(default)]
+    pub chapters: bool,
+    /// Debug builds only: known AES key (hex) to bypass key extraction
+    #[serde(default)]
+    pub override_key_hex: Option<String>,
+    /// Keep decrypted segments and a local index.m3u8 instead of muxing to mp4
+    #[serde(default)]
+    pub keep_as_hls: bool,
+    /// Stop before concat and leave the raw numbered segments in the work dir
+    #[serde(default)]
+    pub keep_segments: bool,
+    /// Download episodes again even when the library already has them on disk
+    #[serde(default)]
+    pub overwrite: bool,
+    /// Container for this batch; falls back to the `output_format` setting
+    #[serde(default)]
+    pub output_format: Option<settings::OutputFormat>,
+    /// Queue position persisted with the batch; lower runs first
+    #[serde(default)]
+    pub priority: i32,
+    /// Segment directory of an earlier attempt to resume from. Only used
+    /// for single-episode requests, which is what resume sends.
+    #[serde(default)]
+    pub work_dir: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -290,19 +978,44 @@ pub struct RequirementsCheckResponse {
 
 #[derive(Debug, Serialize, Clone)]
 struct StatusPayload {
-    episode: u32,
+    episode: EpisodeNumber,
     status: String,
     path: Option<String>,
 }
 
+/// Sent once an episode is registered as running, with the id that cancel
+/// and pause take; episode numbers repeat across shows
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct DownloadStartedPayload {
+    episode: EpisodeNumber,
+    anime_slug: String,
+    download_id: String,
+}
+
 #[derive(Debug, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 struct ProgressPayload {
-    episode: u32,
+    episode: EpisodeNumber,
     done: usize,
     total: usize,
     speed_bps: f64, // bytes per second
     elapsed_seconds: u64, // time spent downloading
+    indeterminate: bool, // total unknown; show a spinner instead of a percentage
+    // `done`/`total` are bytes or media milliseconds depending on the path;
+    // these are always bytes, with the total estimated (0 until known)
+    downloaded_bytes: usize,
+    total_bytes: usize,
+    eta_seconds: Option<u64>, // None while the speed or byte total is unknown
+}
+
+// Weight of the newest 200ms sample in the smoothed ETA speed; ~2s memory
+const ETA_SMOOTHING: f64 = 0.1;
+
+/// `value` unless it is missing or blank, then `default` under the same rule
+fn filter_or_default(value: Option<String>, default: &Option<String>) -> Option<String> {
+    let non_blank = |v: &String| !v.trim().is_empty();
+    value.filter(non_blank).or_else(|| default.clone().filter(non_blank))
 }
 
 #[tauri::command]
@@ -312,7 +1025,7 @@ pub async fn start_download(
     window: Window,
     tracker: State<'_, DownloadTracker>,
     library: State<'_, crate::library::Library>,
-    req: StartDownloadRequest,
+    mut req: StartDownloadRequest,
 ) -> Result<(), String> {
     // Check requirements before starting download
     let app_handle = window.app_handle();
@@ -344,8 +1057,71 @@ pub async fn start_download(
     let threads = req.threads.unwrap_or_else(|| {
         state.settings.lock().unwrap().max_threads
     });
-    let episodes = req.episodes.clone();
-
+    let settings_snapshot = state.settings.lock().unwrap().clone();
+    // Request fields win; the configured defaults fill in blanks
+    req.audio_type = filter_or_default(req.audio_type, &settings_snapshot.default_audio);
+    req.resolution = filter_or_default(req.resolution, &settings_snapshot.default_resolution);
+    download_state
+        .host_limiter
+        .set_cap(settings_snapshot.max_connections_per_host);
+    download_state
+        .bandwidth
+        .set_rate(settings_snapshot.max_bandwidth_kbps);
+    let write_nfo = settings_snapshot.write_nfo;
+    let notifications = &settings_snapshot.notifications;
+    let desktop_on_complete = notifications.enable_desktop && notifications.notify_on_complete;
+    let desktop_on_fail = notifications.enable_desktop && notifications.notify_on_fail;
+    let auto_clear_completed = settings_snapshot.auto_clear_completed;
+    let work_in_temp = settings_snapshot.work_in_temp_dir;
+    let verify_segment_length = settings_snapshot.verify_segment_length;
+    let dual_audio = settings_snapshot.dual_audio;
+    let download_subtitles = settings_snapshot.download_subtitles;
+    let subtitle_format = settings_snapshot.subtitle_format;
+    let allow_all_extensions = settings_snapshot.ffmpeg_allow_all_extensions;
+    let max_concurrent_episodes = settings_snapshot.max_concurrent_episodes.max(1);
+    let output_format = req.output_format.unwrap_or(settings_snapshot.output_format);
+    let override_key_hex = if cfg!(debug_assertions) {
+        req.override_key_hex.clone()
+    } else {
+        if req.override_key_hex.is_some() {
+            log_warn!("Ignoring override_key_hex: only honoured in debug builds");
+        }
+        None
+    };
+    let download_options = download::DownloadOptions {
+        chapters: req.chapters,
+        host_limiter: Some(download_state.host_limiter.clone()),
+        bandwidth: Some(download_state.bandwidth.clone()),
+        override_key_hex,
+        pause_rx: Some(download_state.paused.subscribe()),
+        extraction_cache: Some(download_state.extraction_cache.clone()),
+        keep_as_hls: req.keep_as_hls,
+        keep_segments: req.keep_segments,
+        temp_work_key: None,
+        verify_segment_length,
+        file_suffix: None,
+        metrics: Some(download_state.metrics.clone()),
+        allow_all_extensions,
+        filename_template: settings_snapshot.filename_template.clone(),
+        audio: req.audio_type.clone(),
+        hwaccel: settings_snapshot.hwaccel,
+        transcode: settings_snapshot
+            .transcode
+            .enabled
+            .then(|| settings_snapshot.transcode.clone()),
+        output_format,
+        resolution: req.resolution.clone(),
+        ffmpeg_timeout_secs: settings_snapshot.ffmpeg_timeout_secs,
+        segment_retries: settings_snapshot.segment_retries,
+        segment_timeout_secs: settings_snapshot.segment_timeout_secs,
+        decrypt_concurrency: settings_snapshot.max_decrypt_concurrency,
+        on_segment_retry: None,
+        work_dir: None,
+    };
+    let metrics = download_state.metrics.clone();
+    let extraction_cache = download_state.extraction_cache.clone();
+    let episodes = req.episodes.clone();
+
     // Clone states before spawning to avoid lifetime issues
     let download_state_arc = (*download_state).clone();
     let tracker_clone = (*tracker).clone();
@@ -356,7 +1132,7 @@ pub async fn start_download(
             let _ = window.emit(
                 "download-status",
                 StatusPayload {
-                    episode: 0,
+                    episode: EpisodeNumber::from(0),
                     status: "No episodes selected".into(),
                     path: None,
                 },
@@ -364,6 +1140,62 @@ pub async fn start_download(
             return;
         }
 
+        // Drop episodes the anime doesn't have before they become tracker failures
+        let mut episodes = episodes;
+        let session_map = match cached_sessions(&extraction_cache, &req.anime_slug, &cookie, &host).await {
+            Ok(sessions) => {
+                let check = check_episode_selection(&episodes, &sessions);
+                if !check.skipped.is_empty() {
+                    let _ = window.emit(
+                        "download-status",
+                        StatusPayload {
+                            episode: EpisodeNumber::from(0),
+                            status: format!(
+                                "Skipping {} unavailable episode(s): {}",
+                                check.skipped.len(),
+                                format_episode_list(&check.skipped)
+                            ),
+                            path: None,
+                        },
+                    );
+                }
+                episodes = check.valid;
+                sessions
+            }
+            Err(e) => {
+                log_warn!("Could not prefetch episode list, checking episodes one by one: {}", e);
+                BTreeMap::new()
+            }
+        };
+        if episodes.is_empty() {
+            return;
+        }
+
+        // Leave episodes that are already in the library and still on disk alone
+        if !req.overwrite {
+            episodes.retain(|&episode| {
+                let present = library_clone
+                    .get_library_entry(&req.anime_slug, episode)
+                    .ok()
+                    .flatten()
+                    .is_some_and(|entry| Path::new(&entry.file_path).exists());
+                if present {
+                    let _ = window.emit(
+                        "download-status",
+                        StatusPayload {
+                            episode,
+                            status: "Already downloaded, skipped".into(),
+                            path: None,
+                        },
+                    );
+                }
+                !present
+            });
+            if episodes.is_empty() {
+                return;
+            }
+        }
+
         // Fetch and save anime poster locally
         let poster_path = match api::fetch_anime_poster(&req.anime_slug, &cookie, &host).await {
             Ok(Some(url)) => {
@@ -371,7 +1203,7 @@ pub async fn start_download(
                 match download_and_save_poster(&url, &req.anime_slug, &cookie, &host).await {
                     Ok(path) => Some(path),
                     Err(e) => {
-                        eprintln!("Failed to download poster: {}", e);
+                        log_warn!("Failed to download poster: {}", e);
                         None
                     }
                 }
@@ -379,74 +1211,104 @@ pub async fn start_download(
             _ => None,
         };
 
-        for episode in episodes {
-            let _ = window.emit(
-                "download-status",
-                StatusPayload {
+        // Series metadata is only needed for media-server sidecars
+        let nfo_metadata = if write_nfo {
+            api::fetch_anime_metadata(&req.anime_slug, &cookie, &host).await.ok()
+        } else {
+            None
+        };
+
+        // Persist the whole batch up front so its order survives a restart
+        let mut queued_ids: HashMap<EpisodeNumber, String> = HashMap::new();
+        if req.resume_download_id.is_none() {
+            for &episode in &episodes {
+                let file_path = download::output_path(
+                    download_dir.as_deref(),
+                    &anime_name,
                     episode,
-                    status: "Fetching link".into(),
-                    path: None,
-                },
-            );
+                    &host,
+                    &download_options,
+                );
+                match tracker_clone.add_queued(
+                    anime_name.clone(),
+                    episode,
+                    req.anime_slug.clone(),
+                    file_path.to_string_lossy().to_string(),
+                    req.audio_type.clone(),
+                    req.resolution.clone(),
+                    req.priority,
+                ) {
+                    Ok(id) => {
+                        queued_ids.insert(episode, id);
+                    }
+                    Err(err) => log_warn!("Failed to queue episode {}: {}", episode, err),
+                }
+            }
+        }
 
-            let sess = match api::find_session_for_episode(&req.anime_slug, episode, &cookie, &host).await
-            {
-                Ok(s) => s,
-                Err(err) => {
+        // Run up to `max_concurrent_episodes` pipelines at once. Each has its own
+        // cancel token and progress task, and every event carries its episode.
+        let (window, req, cookie, host, anime_name) = (&window, &req, &cookie, &host, &anime_name);
+        let (download_state_arc, tracker_clone, library_clone) =
+            (&download_state_arc, &tracker_clone, &library_clone);
+        let (metrics, extraction_cache, download_options) =
+            (&metrics, &extraction_cache, &download_options);
+        let (download_dir, session_map, queued_ids) = (&download_dir, &session_map, &queued_ids);
+        let (poster_path, nfo_metadata) = (&poster_path, &nfo_metadata);
+        futures::stream::iter(episodes)
+            .for_each_concurrent(max_concurrent_episodes, |episode| async move {
+                // Don't start another episode while the queue is paused
+                let mut pause_rx = Some(download_state_arc.paused.subscribe());
+                if download_state_arc.is_paused() {
                     let _ = window.emit(
                         "download-status",
                         StatusPayload {
                             episode,
-                            status: format!("Failed: {err}"),
+                            status: "Paused".into(),
                             path: None,
                         },
                     );
-                    continue;
+                    download::wait_while_paused(&mut pause_rx).await;
                 }
-            };
-            let play_page = format!("{}/play/{}/{}", host, req.anime_slug, sess);
-            let candidates = match scrape::extract_candidates(&play_page, &cookie).await {
-                Ok(c) => c,
-                Err(err) => {
-                    let _ = window.emit(
-                        "download-status",
-                        StatusPayload {
-                            episode,
-                            status: format!("Failed: {err}"),
-                            path: None,
-                        },
-                    );
-                    continue;
+
+                let queued_id = queued_ids.get(&episode).cloned();
+                if let Some(ref id) = queued_id {
+                    let _ = tracker_clone.mark_started(id);
                 }
-            };
-            let chosen = scrape::select_candidate(
-                &candidates,
-                req.audio_type.as_deref(),
-                req.resolution.as_deref(),
-            );
-            let Some(candidate) = chosen else {
+                // Failures before the download starts still need to leave the queue
+                let fail_queued = |err: &str| {
+                    if let Some(ref id) = queued_id {
+                        let _ = tracker_clone.mark_failed(id, err.to_string());
+                    }
+                };
+
                 let _ = window.emit(
                     "download-status",
                     StatusPayload {
                         episode,
-                        status: "No matching source".into(),
+                        status: "Fetching link".into(),
                         path: None,
                     },
                 );
-                continue;
-            };
-            let _ = window.emit(
-                "download-status",
-                StatusPayload {
-                    episode,
-                    status: "Extracting playlist".into(),
-                    path: None,
-                },
-            );
-            let playlist =
-                match scrape::extract_m3u8_from_link(&candidate.src, &cookie, &host).await {
-                    Ok(p) => p,
+
+                let episode_start = std::time::Instant::now();
+                let stage_start = std::time::Instant::now();
+                // The prefetch failed; the first episode to get here fills the cache for the rest
+                let lookup = match session_map.get(&episode) {
+                    Some(sess) => Ok(sess.clone()),
+                    None => cached_sessions(extraction_cache, &req.anime_slug, &cookie, &host)
+                        .await
+                        .and_then(|sessions| {
+                            sessions
+                                .get(&episode)
+                                .cloned()
+                                .ok_or_else(|| anyhow::anyhow!("Episode {} not found", episode))
+                        }),
+                };
+                let sess = match lookup {
+                    Ok(s) => s,
                     Err(err) => {
+                        fail_queued(&err.to_string());
                         let _ = window.emit(
                             "download-status",
                             StatusPayload {
@@ -455,271 +1317,866 @@ pub async fn start_download(
                                 path: None,
                             },
                         );
-                        continue;
+                        return;
                     }
                 };
-
-            eprintln!(
-                "Playlist extraction completed for episode {}, starting download process",
-                episode
-            );
-
-            let _ = window.emit(
-                "download-status",
-                StatusPayload {
-                    episode,
-                    status: "Downloading".into(),
-                    path: None,
-                },
-            );
-
-            // Generate expected file path
-            let sanitized_name = sanitize_filename::sanitize(&anime_name);
-            let file_name = format!("{} - Episode {}.mp4", sanitized_name, episode);
-            let file_path = if let Some(ref dir) = download_dir {
-                dir.join(&file_name)
-            } else {
-                PathBuf::from(&file_name)
-            };
-
-            // Create or get download tracker ID
-            let download_id = if let Some(ref resume_id) = req.resume_download_id {
-                resume_id.clone()
-            } else {
-                match tracker_clone.add_download(
-                    anime_name.clone(),
-                    episode as i32,
-                    req.anime_slug.clone(),
-                    file_path.to_string_lossy().to_string(),
-                    req.audio_type.clone(),
-                    req.resolution.clone(),
-                ) {
-                    Ok(id) => id,
+                metrics.record_since(Stage::SessionLookup, stage_start);
+                let play_page = format!("{}/play/{}/{}", host, req.anime_slug, sess);
+                let stage_start = std::time::Instant::now();
+                let candidates = match scrape::extract_candidates(&play_page, &cookie).await {
+                    Ok(c) => c,
                     Err(err) => {
-                        eprintln!("Failed to create download record: {}", err);
-                        format!("{}-ep{}-{}", req.anime_slug, episode, chrono::Utc::now().timestamp())
+                        fail_queued(&err.to_string());
+                        let _ = window.emit(
+                            "download-status",
+                            StatusPayload {
+                                episode,
+                                status: format!("Failed: {err}"),
+                                path: None,
+                            },
+                        );
+                        return;
                     }
+                };
+                metrics.record_since(Stage::CandidateScrape, stage_start);
+                let ranked = scrape::rank_candidates(
+                    &candidates,
+                    req.audio_type.as_deref(),
+                    req.resolution.as_deref(),
+                );
+                if ranked.is_empty() {
+                    fail_queued("No matching source");
+                    let _ = window.emit(
+                        "download-status",
+                        StatusPayload {
+                            episode,
+                            status: "No matching source".into(),
+                            path: None,
+                        },
+                    );
+                    return;
                 }
-            };
-
-            let total = Arc::new(std::sync::atomic::AtomicUsize::new(0));
-            let done = Arc::new(std::sync::atomic::AtomicUsize::new(0));
-
-            // Create cancellation token for this episode
-            let (cancel_tx, cancel_rx) = tokio::sync::watch::channel(false);
-            {
-                let mut active = download_state_arc.active.lock().await;
-                active.insert(episode, cancel_tx);
-            }
 
-            let progress_window = window.clone();
-            let progress_episode = episode;
-            let progress_total = total.clone();
-            let progress_done = done.clone();
-            let mut progress_cancel_rx = cancel_rx.clone();
-
-            // Track speed and elapsed time
-            let start_time = std::time::Instant::now();
-            let last_done = Arc::new(std::sync::atomic::AtomicUsize::new(0));
-            let last_time = Arc::new(StdMutex::new(std::time::Instant::now()));
-
-            let progress_last_done = last_done.clone();
-            let progress_last_time = last_time.clone();
-            let progress_tracker = tracker_clone.clone();
-            let progress_download_id = download_id.clone();
-
-            let progress_handle: JoinHandle<()> = tauri::async_runtime::spawn(async move {
-                loop {
-                    tokio::select! {
-                        _ = progress_cancel_rx.changed() => {
-                            if *progress_cancel_rx.borrow() {
-                                break;
-                            }
-                        }
-                        _ = sleep(Duration::from_millis(200)) => {
-                            let t = progress_total.load(std::sync::atomic::Ordering::Relaxed);
-                            let d = progress_done.load(std::sync::atomic::Ordering::Relaxed);
-
-                            // Calculate speed
-                            let now = std::time::Instant::now();
-                            let last_d = progress_last_done.swap(d, std::sync::atomic::Ordering::Relaxed);
-                            let elapsed = {
-                                let mut last_t = progress_last_time.lock().unwrap();
-                                let elapsed = now.duration_since(*last_t).as_secs_f64();
-                                *last_t = now;
-                                elapsed
-                            };
-
-                            let speed_bps = if elapsed > 0.0 && d > last_d {
-                                (d - last_d) as f64 / elapsed
+                // Try each source in preference order until one extracts
+                let stage_start = std::time::Instant::now();
+                let mut extracted = None;
+                let mut last_err = None;
+                for (i, candidate) in ranked.iter().enumerate() {
+                    let _ = window.emit(
+                        "download-status",
+                        StatusPayload {
+                            episode,
+                            status: if i == 0 {
+                                "Extracting playlist".into()
                             } else {
-                                0.0
-                            };
-
-                            if t > 0 {
-                                // Update tracker with progress
-                                let _ = progress_tracker.update_progress(
-                                    &progress_download_id,
-                                    d as u64,
-                                    Some(t as u64),
-                                );
-
-                                let elapsed_seconds = start_time.elapsed().as_secs();
-                                let _ = progress_window.emit(
-                                    "download-progress",
-                                    ProgressPayload {
-                                        episode: progress_episode,
-                                        done: d,
-                                        total: t,
-                                        speed_bps,
-                                        elapsed_seconds,
-                                    },
-                                );
-                            }
+                                "Trying alternate source".into()
+                            },
+                            path: None,
+                        },
+                    );
+                    if let Some(p) = extraction_cache.playlist(&req.anime_slug, episode, &candidate.src) {
+                        log_debug!("Reusing cached playlist for episode {}", episode);
+                        extracted = Some((p, *candidate));
+                        break;
+                    }
+                    match scrape::extract_m3u8_from_link(&candidate.src, &cookie, &host).await {
+                        Ok(p) => {
+                            extraction_cache.store_playlist(&req.anime_slug, episode, &candidate.src, &p);
+                            extracted = Some((p, *candidate));
+                            break;
+                        }
+                        Err(err) => {
+                            log_warn!(
+                                "Extraction failed for episode {} from {}: {}",
+                                episode, candidate.src, err
+                            );
+                            last_err = Some(err);
                         }
                     }
                 }
-            });
+                let Some((playlist, candidate)) = extracted else {
+                    let err = last_err.map(|e| e.to_string()).unwrap_or_default();
+                    fail_queued(&err);
+                    let _ = window.emit(
+                        "download-status",
+                        StatusPayload {
+                            episode,
+                            status: format!("Failed: {err}"),
+                            path: None,
+                        },
+                    );
+                    return;
+                };
 
-            eprintln!("Starting download_episode function for episode {}", episode);
-
-            let download_cancel_rx = cancel_rx.clone();
-            let status = download::download_episode(
-                &anime_name,
-                episode,
-                &playlist,
-                threads,
-                &cookie,
-                download_dir.as_deref(),
-                &host,
-                Some((total.clone(), done.clone())),
-                Some(download_cancel_rx),
-            )
-            .await;
+                metrics.record_since(Stage::Extraction, stage_start);
 
-            // Stop progress tracking and remove from active downloads
-            {
-                let mut active = download_state_arc.active.lock().await;
-                if let Some(tx) = active.remove(&episode) {
-                    let _ = tx.send(true);
-                }
-            }
+                // Record what was actually fetched, which may differ from what was asked for
+                let used_audio = candidate.audio.clone().or_else(|| req.audio_type.clone());
+                let used_resolution = candidate.resolution.clone().or_else(|| req.resolution.clone());
+                log_info!(
+                    "Using source {} (audio={:?}, resolution={:?}) for episode {}",
+                    candidate.src, used_audio, used_resolution, episode
+                );
+
+                log_info!(
+                    "Playlist extraction completed for episode {}, starting download process",
+                    episode
+                );
 
-            progress_handle.await.ok();
-
-            match status {
-                Ok(path) => {
-                    // Mark download as completed in tracker
-                    let _ = tracker_clone.mark_completed(&download_id);
-
-                    // Add to library and get file size
-                    let file_size = if let Ok(metadata) = std::fs::metadata(&path) {
-                        let size = metadata.len() as i64;
-                        let _ = library_clone.add_download(
-                            &anime_name,
-                            &req.anime_slug,
-                            episode as i32,
-                            req.resolution.as_deref(),
-                            req.audio_type.as_deref(),
-                            &path.to_string_lossy(),
-                            size,
-                            poster_path.as_deref(),
-                            &host,
+                let _ = window.emit(
+                    "download-status",
+                    StatusPayload {
+                        episode,
+                        status: "Downloading".into(),
+                        path: None,
+                    },
+                );
+
+                // Name the output after the source actually chosen
+                let mut episode_options = download_options.clone();
+                episode_options.audio = used_audio.clone();
+                episode_options.resolution = used_resolution.clone();
+                // Signed segment links can expire mid-episode; re-extract the same source
+                let (refresh_src, refresh_cookie, refresh_host, refresh_slug, refresh_cache) = (
+                    candidate.src.clone(),
+                    cookie.to_string(),
+                    host.to_string(),
+                    req.anime_slug.clone(),
+                    extraction_cache.clone(),
+                );
+                episode_options.refresh_playlist = Some(Arc::new(
+                    move || -> futures::future::BoxFuture<'static, anyhow::Result<String>> {
+                        let (src, cookie, host, slug, cache) = (
+                            refresh_src.clone(),
+                            refresh_cookie.clone(),
+                            refresh_host.clone(),
+                            refresh_slug.clone(),
+                            refresh_cache.clone(),
                         );
-                        size
-                    } else {
-                        0
-                    };
+                        Box::pin(async move {
+                            cache.invalidate_episode(&slug, episode);
+                            let m3u8 = scrape::extract_m3u8_from_link(&src, &cookie, &host).await?;
+                            cache.store_playlist(&slug, episode, &src, &m3u8);
+                            Ok(m3u8)
+                        })
+                    },
+                ));
+                let file_path = download::output_path(
+                    download_dir.as_deref(),
+                    &anime_name,
+                    episode,
+                    &host,
+                    &episode_options,
+                );
 
-                    let folder = path
-                        .parent()
-                        .map(|p| p.to_path_buf())
-                        .unwrap_or(path.clone());
+                // Create or get download tracker ID
+                let download_id = if let Some(ref resume_id) = req.resume_download_id {
+                    resume_id.clone()
+                } else if let Some(id) = queued_id {
+                    // Queued before the source was known; its tokens may have changed
+                    let _ = tracker_clone.set_file_path(&id, file_path.to_string_lossy().to_string());
+                    id
+                } else {
+                    match tracker_clone.add_download(
+                        anime_name.clone(),
+                        episode,
+                        req.anime_slug.clone(),
+                        file_path.to_string_lossy().to_string(),
+                        req.audio_type.clone(),
+                        req.resolution.clone(),
+                    ) {
+                        Ok(id) => id,
+                        Err(err) => {
+                            log_warn!("Failed to create download record: {}", err);
+                            format!("{}-ep{}-{}", req.anime_slug, episode, chrono::Utc::now().timestamp())
+                        }
+                    }
+                };
 
-                    let _ = window.emit(
-                        "download-status",
-                        StatusPayload {
+                let total = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+                let done = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+                let segments_done = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+                let byte_progress = download::ByteProgress::default();
+                episode_options.byte_progress = Some(byte_progress.clone());
+
+                // Create cancellation token for this episode
+                let (cancel_tx, cancel_rx) =
+                    tokio::sync::watch::channel(download::DownloadControl::Running);
+                {
+                    let mut active = download_state_arc.active.lock().await;
+                    active.insert(
+                        download_id.clone(),
+                        ActiveDownload {
+                            cancel_tx,
                             episode,
-                            status: "Done".into(),
-                            path: Some(folder.to_string_lossy().to_string()),
+                            anime_name: anime_name.clone(),
+                            done: done.clone(),
+                            segments_done: segments_done.clone(),
+                            segmented: threads > 1,
                         },
                     );
+                }
+                let _ = window.emit(
+                    "download-started",
+                    DownloadStartedPayload {
+                        episode,
+                        anime_slug: req.anime_slug.clone(),
+                        download_id: download_id.clone(),
+                    },
+                );
 
-                    // Emit download complete notification
-                    let notification = DownloadCompleteNotification {
-                        anime_name: anime_name.clone(),
+                let progress_window = window.clone();
+                let progress_episode = episode;
+                let progress_total = total.clone();
+                let progress_done = done.clone();
+                let progress_bytes = byte_progress.clone();
+                let mut progress_cancel_rx = cancel_rx.clone();
+
+                // Track speed and elapsed time
+                let start_time = std::time::Instant::now();
+                let last_done = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+                let last_time = Arc::new(StdMutex::new(std::time::Instant::now()));
+
+                let progress_last_done = last_done.clone();
+                let progress_last_time = last_time.clone();
+                let progress_tracker = tracker_clone.clone();
+                let progress_download_id = download_id.clone();
+                let progress_aggregate = download_state_arc.progress.clone();
+
+                let progress_handle: JoinHandle<()> = tauri::async_runtime::spawn(async move {
+                    let mut last_bytes = 0usize;
+                    let mut smoothed_bps: Option<f64> = None;
+                    loop {
+                        tokio::select! {
+                            changed = progress_cancel_rx.changed() => {
+                                // A dropped sender means the download is gone too
+                                if changed.is_err()
+                                    || *progress_cancel_rx.borrow() == download::DownloadControl::Cancelled
+                                {
+                                    progress_aggregate.lock().unwrap().remove(&progress_download_id);
+                                    break;
+                                }
+                            }
+                            _ = sleep(Duration::from_millis(200)) => {
+                                let t = progress_total.load(std::sync::atomic::Ordering::Relaxed);
+                                let d = progress_done.load(std::sync::atomic::Ordering::Relaxed);
+
+                                // Calculate speed
+                                let now = std::time::Instant::now();
+                                let last_d = progress_last_done.swap(d, std::sync::atomic::Ordering::Relaxed);
+                                let elapsed = {
+                                    let mut last_t = progress_last_time.lock().unwrap();
+                                    let elapsed = now.duration_since(*last_t).as_secs_f64();
+                                    *last_t = now;
+                                    elapsed
+                                };
+
+                                let speed_bps = if elapsed > 0.0 && d > last_d {
+                                    (d - last_d) as f64 / elapsed
+                                } else {
+                                    0.0
+                                };
+
+                                // ETA from a smoothed byte rate; the instantaneous
+                                // one jumps with every segment boundary
+                                let downloaded_bytes = progress_bytes
+                                    .downloaded
+                                    .load(std::sync::atomic::Ordering::Relaxed);
+                                let total_bytes = progress_bytes
+                                    .total
+                                    .load(std::sync::atomic::Ordering::Relaxed);
+                                if elapsed > 0.0 {
+                                    let sample = downloaded_bytes.saturating_sub(last_bytes) as f64 / elapsed;
+                                    smoothed_bps = Some(match smoothed_bps {
+                                        Some(prev) => prev + ETA_SMOOTHING * (sample - prev),
+                                        None => sample,
+                                    });
+                                }
+                                last_bytes = downloaded_bytes;
+                                let eta_seconds = match smoothed_bps {
+                                    Some(bps) if bps >= 1.0 && total_bytes > 0 => Some(
+                                        (total_bytes.saturating_sub(downloaded_bytes) as f64 / bps).ceil() as u64,
+                                    ),
+                                    _ => None,
+                                };
+
+                                let fraction = if total_bytes > 0 {
+                                    Some(downloaded_bytes as f64 / total_bytes as f64)
+                                } else if t > 0 {
+                                    Some(d as f64 / t as f64)
+                                } else {
+                                    None
+                                };
+                                progress_aggregate.lock().unwrap().insert(
+                                    progress_download_id.clone(),
+                                    EpisodeProgress {
+                                        fraction,
+                                        bytes_per_sec: smoothed_bps.unwrap_or(0.0),
+                                    },
+                                );
+
+                                if t > 0 || d > 0 {
+                                    // Update tracker with progress
+                                    let _ = progress_tracker.update_progress(
+                                        &progress_download_id,
+                                        d as u64,
+                                        if t > 0 { Some(t as u64) } else { None },
+                                    );
+
+                                    let elapsed_seconds = start_time.elapsed().as_secs();
+                                    let _ = progress_window.emit(
+                                        "download-progress",
+                                        ProgressPayload {
+                                            episode: progress_episode,
+                                            done: d,
+                                            total: t,
+                                            speed_bps,
+                                            elapsed_seconds,
+                                            indeterminate: t == 0,
+                                            downloaded_bytes,
+                                            total_bytes,
+                                            eta_seconds,
+                                        },
+                                    );
+                                }
+                            }
+                        }
+                    }
+                });
+
+                log_debug!("Starting download_episode function for episode {}", episode);
+
+                // Kept segments are the result, so they can't live in the OS temp dir
+                if work_in_temp && !req.keep_segments {
+                    episode_options.temp_work_key = Some(download_id.clone());
+                }
+                if req.episodes.len() == 1 {
+                    episode_options.work_dir = req.work_dir.as_ref().map(PathBuf::from);
+                }
+                if threads > 1 || req.keep_as_hls || req.keep_segments {
+                    let work_dir = download::work_dir_for(
+                        download_dir.as_deref(),
+                        &anime_name,
                         episode,
-                        file_path: path.to_string_lossy().to_string(),
-                        file_size,
-                        success: true,
-                    };
-                    println!("[NOTIFICATION] Emitting download-complete event for {} Episode {}", anime_name, episode);
-                    println!("[NOTIFICATION] File path: {}", path.to_string_lossy());
-                    let _ = window.emit("download-complete", notification);
+                        &host,
+                        &episode_options,
+                    );
+                    let _ = tracker_clone
+                        .set_work_dir(&download_id, work_dir.to_string_lossy().to_string());
                 }
-                Err(err) => {
-                    // Mark download as failed in tracker
-                    let _ = tracker_clone.mark_failed(&download_id, err.to_string());
-
-                    let _ = window.emit(
+                let retry_window = window.clone();
+                episode_options.on_segment_retry = Some(Arc::new(move |segment, attempt| {
+                    let _ = retry_window.emit(
                         "download-status",
                         StatusPayload {
                             episode,
-                            status: format!("Failed: {err}"),
+                            status: format!("Retrying segment {} (attempt {})", segment, attempt),
                             path: None,
                         },
                     );
+                }));
 
-                    // Emit download failed notification
-                    let _ = window.emit(
-                        "download-failed",
-                        DownloadCompleteNotification {
+                let download_cancel_rx = cancel_rx.clone();
+                let status = download::download_episode(
+                    &anime_name,
+                    episode,
+                    &playlist,
+                    threads,
+                    &cookie,
+                    download_dir.as_deref(),
+                    &host,
+                    Some((total.clone(), done.clone())),
+                    Some(segments_done.clone()),
+                    Some(download_cancel_rx),
+                    &episode_options,
+                )
+                .await;
+                // Read before cleanup below, which also signals Cancelled
+                let cancelled_by_user = *cancel_rx.borrow() == download::DownloadControl::Cancelled;
+
+                // Stop progress tracking and remove from active downloads
+                {
+                    let mut active = download_state_arc.active.lock().await;
+                    if let Some(entry) = active.remove(&download_id) {
+                        let _ = entry.cancel_tx.send(download::DownloadControl::Cancelled);
+                    }
+                }
+
+                progress_handle.await.ok();
+
+                match status {
+                    Ok(path) => {
+                        metrics.record_since(Stage::Episode, episode_start);
+
+                        // Mark download as completed in tracker, with the size it ended up
+                        let output_size = std::fs::metadata(&path)
+                            .ok()
+                            .filter(|m| m.is_file())
+                            .map(|m| m.len());
+                        let _ = tracker_clone.mark_completed(&download_id, output_size);
+
+                        if req.keep_segments {
+                            let _ = window.emit(
+                                "download-status",
+                                StatusPayload {
+                                    episode,
+                                    status: format!("Segments kept in {}", path.display()),
+                                    path: Some(path.to_string_lossy().to_string()),
+                                },
+                            );
+                            return;
+                        }
+
+                        // Fetch the other language too when asked and the episode has one
+                        let mut used_audio = used_audio.clone();
+                        let extra = if dual_audio != settings::DualAudioMode::Off && !req.keep_as_hls {
+                            scrape::alternate_audio(&candidates, used_audio.as_deref(), req.resolution.as_deref())
+                        } else {
+                            None
+                        };
+                        if let (Some(extra), Some(primary_lang)) = (extra, used_audio.clone()) {
+                            let extra_lang = extra.audio.clone().unwrap_or_default();
+                            let _ = window.emit(
+                                "download-status",
+                                StatusPayload {
+                                    episode,
+                                    status: format!("Downloading {} audio", extra_lang),
+                                    path: None,
+                                },
+                            );
+                            let mut extra_options = episode_options.clone();
+                            extra_options.file_suffix = Some(format!(".{}", sanitize_filename::sanitize(&extra_lang)));
+                            extra_options.work_dir = None;
+                            extra_options.byte_progress = None;
+                            extra_options.refresh_playlist = None;
+                            extra_options.temp_work_key = episode_options
+                                .temp_work_key
+                                .as_ref()
+                                .map(|key| format!("{}-{}", key, extra_lang));
+
+                            let extra_m3u8 = match extraction_cache.playlist(&req.anime_slug, episode, &extra.src) {
+                                Some(p) => Ok(p),
+                                None => scrape::extract_m3u8_from_link(&extra.src, &cookie, &host).await,
+                            };
+                            let extra_path = match extra_m3u8 {
+                                Ok(m3u8) => {
+                                    download::download_episode(
+                                        &anime_name,
+                                        episode,
+                                        &m3u8,
+                                        threads,
+                                        &cookie,
+                                        download_dir.as_deref(),
+                                        &host,
+                                        None,
+                                        None,
+                                        None,
+                                        &extra_options,
+                                    )
+                                    .await
+                                }
+                                Err(e) => Err(e),
+                            };
+                            let added = match extra_path {
+                                Ok(extra_path) if dual_audio == settings::DualAudioMode::Muxed => {
+                                    let video = path.clone();
+                                    let (primary, extra) = (primary_lang.clone(), extra_lang.clone());
+                                    run_blocking(move || {
+                                        download::mux_extra_audio(&video, &extra_path, &primary, &extra)?;
+                                        let _ = std::fs::remove_file(&extra_path);
+                                        Ok(())
+                                    })
+                                    .await
+                                }
+                                other => other.map(|_| ()),
+                            };
+                            match added {
+                                // The library row records every audio track the episode now has
+                                Ok(()) => used_audio = Some(format!("{}+{}", primary_lang, extra_lang)),
+                                Err(e) => log_warn!(
+                                    "Second audio track ({}) for episode {} failed: {}",
+                                    extra_lang, episode, e
+                                ),
+                            }
+                        }
+
+                        if download_subtitles {
+                            // The playlist may have been re-extracted mid-download
+                            let m3u8 = extraction_cache
+                                .playlist(&req.anime_slug, episode, &candidate.src)
+                                .unwrap_or_else(|| playlist.clone());
+                            let mut tracks = scrape::extract_subtitle_tracks(&candidate.src, &cookie, &host)
+                                .await
+                                .unwrap_or_else(|e| {
+                                    log_warn!("Subtitle lookup on {} failed: {}", candidate.src, e);
+                                    Vec::new()
+                                });
+                            match download::playlist_subtitle_tracks(&m3u8, &cookie, &host).await {
+                                Ok(found) => {
+                                    for track in found {
+                                        if !tracks.iter().any(|t| t.url == track.url) {
+                                            tracks.push(track);
+                                        }
+                                    }
+                                }
+                                Err(e) => log_warn!("Subtitle lookup in playlist failed: {}", e),
+                            }
+                            let files = download::download_subtitle_tracks(&tracks, &path, &cookie, &host).await;
+                            let muxed = if files.is_empty() || req.keep_as_hls {
+                                false
+                            } else {
+                                let (video, subs) = (path.clone(), files.clone());
+                                match run_blocking(move || download::mux_subtitles(&video, &subs)).await {
+                                    Ok(()) => true,
+                                    Err(e) => {
+                                        log_warn!("Subtitle mux for episode {} failed, keeping sidecars: {}", episode, e);
+                                        false
+                                    }
+                                }
+                            };
+                            for file in &files {
+                                if muxed {
+                                    let _ = std::fs::remove_file(&file.path);
+                                } else if let Err(e) = crate::subtitles::apply_format(&file.path, subtitle_format) {
+                                    log_warn!("Subtitle conversion failed: {}", e);
+                                }
+                            }
+                        }
+
+                        // Kept HLS has no container to carry artwork
+                        if let Some(poster) = poster_path.as_deref() {
+                            if !req.keep_as_hls {
+                                let (video, poster) = (path.clone(), PathBuf::from(poster));
+                                if let Err(e) = run_blocking(move || download::embed_cover_art(&video, &poster)).await {
+                                    log_warn!("Cover art for episode {} failed: {}", episode, e);
+                                }
+                            }
+                        }
+
+                        // Add to library and get file size
+                        let duration_seconds = crate::player::probe_media_info(&path.to_string_lossy())
+                            .await
+                            .ok()
+                            .and_then(|info| info.duration_seconds)
+                            .map(|secs| secs.round() as i64);
+                        let file_size = if let Some(size) = output_size(&path) {
+                            let size = size as i64;
+                            let added = library_clone.add_download(
+                                &anime_name,
+                                &req.anime_slug,
+                                episode,
+                                used_resolution.as_deref(),
+                                used_audio.as_deref(),
+                                &path.to_string_lossy(),
+                                size,
+                                poster_path.as_deref(),
+                                &host,
+                                duration_seconds,
+                            );
+                            // The library now owns this episode; keep the tracker for in-flight/failed work
+                            if added.is_ok() && auto_clear_completed {
+                                let _ = tracker_clone.remove_download(&download_id);
+                            }
+                            size
+                        } else {
+                            0
+                        };
+
+                        if write_nfo {
+                            if let Err(e) = crate::nfo::write_sidecars(
+                                &path,
+                                &anime_name,
+                                episode,
+                                nfo_metadata.as_ref(),
+                                poster_path.as_deref(),
+                            ) {
+                                log_warn!("Failed to write nfo sidecars: {}", e);
+                            }
+                        }
+
+                        let folder = path
+                            .parent()
+                            .map(|p| p.to_path_buf())
+                            .unwrap_or(path.clone());
+
+                        let _ = window.emit(
+                            "download-status",
+                            StatusPayload {
+                                episode,
+                                status: "Done".into(),
+                                path: Some(folder.to_string_lossy().to_string()),
+                            },
+                        );
+
+                        // Emit download complete notification
+                        let notification = DownloadCompleteNotification {
                             anime_name: anime_name.clone(),
                             episode,
-                            file_path: String::new(),
-                            file_size: 0,
-                            success: false,
-                        },
-                    );
+                            file_path: path.to_string_lossy().to_string(),
+                            file_size,
+                            success: true,
+                            desktop: desktop_on_complete,
+                        };
+                        log_debug!("Emitting download-complete for {} episode {}: {}", anime_name, episode, path.display());
+                        let _ = window.emit("download-complete", notification);
+                        if desktop_on_complete {
+                            crate::notifications::download_complete(window.app_handle(), &anime_name, episode, &path);
+                        }
+                    }
+                    Err(err) => {
+                        // An expired token means the cached playlist is useless for a retry
+                        if download::is_forbidden(&err) {
+                            extraction_cache.invalidate_episode(&req.anime_slug, episode);
+                        }
+
+                        // A user cancel already marked the record; don't turn it into a failure
+                        if cancelled_by_user {
+                            let _ = window.emit(
+                                "download-status",
+                                StatusPayload {
+                                    episode,
+                                    status: "Cancelled".into(),
+                                    path: None,
+                                },
+                            );
+                            return;
+                        }
+
+                        // Mark download as failed in tracker
+                        let _ = tracker_clone.mark_failed(&download_id, err.to_string());
+
+                        let _ = window.emit(
+                            "download-status",
+                            StatusPayload {
+                                episode,
+                                status: format!("Failed: {err}"),
+                                path: None,
+                            },
+                        );
+
+                        // Emit download failed notification
+                        let _ = window.emit(
+                            "download-failed",
+                            DownloadCompleteNotification {
+                                anime_name: anime_name.clone(),
+                                episode,
+                                file_path: String::new(),
+                                file_size: 0,
+                                success: false,
+                                desktop: desktop_on_fail,
+                            },
+                        );
+                    }
                 }
-            }
-        }
+            })
+            .await;
     });
 
     Ok(())
 }
 
+/// Run an ffmpeg post-processing step on the blocking pool, so a long
+/// remux doesn't hold a runtime worker while other episodes download
+async fn run_blocking<F>(step: F) -> anyhow::Result<()>
+where
+    F: FnOnce() -> anyhow::Result<()> + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(step)
+        .await
+        .unwrap_or_else(|e| Err(anyhow::anyhow!("Post-processing task failed: {e}")))
+}
+
+/// The download folder a tracked file was saved under: up as many levels
+/// as `filename_template` has path components
+fn download_root(file_path: &str, filename_template: &str) -> Option<String> {
+    let depth = filename_template
+        .split(['/', '\\'])
+        .filter(|part| !part.trim().is_empty())
+        .count()
+        .max(1);
+    Path::new(file_path)
+        .ancestors()
+        .nth(depth)
+        .and_then(|p| p.to_str())
+        .map(|s| s.to_string())
+}
+
+/// Size on disk of a finished download; for a kept HLS playlist this is the
+/// whole segment folder rather than the tiny index file
+fn output_size(path: &std::path::Path) -> Option<u64> {
+    if path.extension().and_then(|e| e.to_str()) == Some("m3u8") {
+        let dir = path.parent()?;
+        let total = std::fs::read_dir(dir)
+            .ok()?
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.metadata().ok())
+            .filter(|m| m.is_file())
+            .map(|m| m.len())
+            .sum();
+        Some(total)
+    } else {
+        std::fs::metadata(path).ok().map(|m| m.len())
+    }
+}
+
 #[tauri::command]
 pub async fn cancel_download(
     download_state: State<'_, DownloadState>,
     tracker: State<'_, DownloadTracker>,
-    episode: u32,
-) -> Result<(), String> {
-    let mut active = download_state.active.lock().await;
-    if let Some(tx) = active.remove(&episode) {
-        tx.send(true).map_err(|_| "Failed to send cancel signal".to_string())?;
-
-        // Find and mark the download as cancelled in tracker
-        // We need to find the download record for this episode
-        let downloads = tracker.get_incomplete_downloads();
-        for download in downloads {
-            if download.episode == episode as i32 {
-                let _ = tracker.mark_cancelled(&download.id);
-                break;
-            }
+    download_id: String,
+) -> Result<CancelResult, String> {
+    cancel_episode(&download_state, &tracker, &download_id).await
+}
+
+/// Cancel from the tray menu, telling the window so its queue catches up
+pub async fn cancel_from_tray(app: AppHandle, download_id: String) {
+    let download_state = app.state::<DownloadState>();
+    let Some(episode) = download_state.active.lock().await.get(&download_id).map(|entry| entry.episode) else {
+        log_warn!("Tray cancel failed: {} is no longer downloading", download_id);
+        return;
+    };
+    let result = cancel_episode(&download_state, &app.state::<DownloadTracker>(), &download_id).await;
+    match result {
+        Ok(_) => {
+            let _ = app.emit(
+                "download-status",
+                StatusPayload {
+                    episode,
+                    status: "Cancelled".into(),
+                    path: None,
+                },
+            );
         }
+        Err(e) => log_warn!("Tray cancel failed: {}", e),
+    }
+}
 
-        Ok(())
+async fn cancel_episode(
+    download_state: &DownloadState,
+    tracker: &DownloadTracker,
+    download_id: &str,
+) -> Result<CancelResult, String> {
+    let mut active = download_state.active.lock().await;
+    if let Some(entry) = active.remove(download_id) {
+        entry
+            .cancel_tx
+            .send(download::DownloadControl::Cancelled)
+            .map_err(|_| "Failed to send cancel signal".to_string())?;
+
+        let done = entry.done.load(std::sync::atomic::Ordering::Relaxed) as u64;
+        let segments_done = entry.segments_done.load(std::sync::atomic::Ordering::Relaxed);
+
+        let _ = tracker.mark_cancelled(download_id);
+
+        Ok(CancelResult {
+            cancelled: true,
+            bytes_downloaded: if entry.segmented { done } else { 0 },
+            segments_done,
+            partial_kept: entry.segmented && segments_done > 0,
+        })
     } else {
-        Err(format!("Episode {} not found in active downloads", episode))
+        Err(format!("Download {} not found in active downloads", download_id))
+    }
+}
+
+/// Cancel every running download at once; returns how many were cancelled
+#[tauri::command]
+pub async fn cancel_all_downloads(
+    download_state: State<'_, DownloadState>,
+    tracker: State<'_, DownloadTracker>,
+) -> Result<usize, String> {
+    let mut active = download_state.active.lock().await;
+    let mut cancelled = 0;
+    for (id, entry) in active.drain() {
+        if entry.cancel_tx.send(download::DownloadControl::Cancelled).is_ok() {
+            cancelled += 1;
+        }
+        let _ = tracker.mark_cancelled(&id);
+    }
+    Ok(cancelled)
+}
+
+/// Hold one download between segments. Its work dir and scraped links stay
+/// put, so `resume_paused_download` carries on with the remaining segments.
+#[tauri::command]
+pub async fn pause_download(
+    window: Window,
+    download_state: State<'_, DownloadState>,
+    tracker: State<'_, DownloadTracker>,
+    download_id: String,
+) -> Result<(), String> {
+    let active = download_state.active.lock().await;
+    let entry = active
+        .get(&download_id)
+        .ok_or_else(|| format!("Download {} not found in active downloads", download_id))?;
+    if !entry.segmented {
+        return Err("Only multi-threaded downloads can be paused".to_string());
+    }
+    entry.cancel_tx.send_replace(download::DownloadControl::Paused);
+    let _ = tracker.mark_paused(&download_id);
+    let _ = window.emit(
+        "download-status",
+        StatusPayload {
+            episode: entry.episode,
+            status: "Paused".to_string(),
+            path: None,
+        },
+    );
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn resume_paused_download(
+    window: Window,
+    download_state: State<'_, DownloadState>,
+    tracker: State<'_, DownloadTracker>,
+    download_id: String,
+) -> Result<(), String> {
+    let active = download_state.active.lock().await;
+    let entry = active
+        .get(&download_id)
+        .ok_or_else(|| format!("Download {} not found in active downloads", download_id))?;
+    if *entry.cancel_tx.borrow() != download::DownloadControl::Paused {
+        return Err(format!("Episode {} is not paused", entry.episode));
     }
+    entry.cancel_tx.send_replace(download::DownloadControl::Running);
+    let _ = tracker.mark_started(&download_id);
+    let _ = window.emit(
+        "download-status",
+        StatusPayload {
+            episode: entry.episode,
+            status: "Resumed".to_string(),
+            path: None,
+        },
+    );
+    Ok(())
 }
 
 #[tauri::command]
 pub async fn check_requirements(
     app_handle: AppHandle,
+    state: State<'_, AppState>,
 ) -> Result<RequirementsCheckResponse, String> {
-    check_requirements_internal(&app_handle)
+    let mut response = check_requirements_internal(&app_handle)?;
+
+    let (proxy_url, host) = {
+        let settings = state.settings.lock().unwrap();
+        (settings.proxy_url.clone(), settings.host_url.clone())
+    };
+    if !proxy_url.trim().is_empty() {
+        let error = crate::network::check_proxy(&proxy_url, &host)
+            .await
+            .err()
+            .map(|err| format!("{:#}", err));
+        response.all_available &= error.is_none();
+        response.requirements.push(RequirementStatus {
+            name: "proxy".to_string(),
+            available: error.is_none(),
+            path: Some(proxy_url),
+            error,
+        });
+    }
+    Ok(response)
 }
 
 #[tauri::command]
@@ -730,6 +2187,34 @@ pub async fn open_path(path: String) -> Result<(), String> {
     open::that(&path).map_err(|err| err.to_string())
 }
 
+/// Convert a `.vtt` file according to the subtitle format setting.
+/// Returns the subtitle files present afterwards.
+#[tauri::command]
+pub fn convert_subtitles(
+    state: State<'_, AppState>,
+    vtt_path: String,
+) -> Result<Vec<String>, String> {
+    let format = state.settings.lock().unwrap().subtitle_format;
+    crate::subtitles::apply_format(std::path::Path::new(&vtt_path), format)
+        .map(|paths| paths.iter().map(|p| p.to_string_lossy().to_string()).collect())
+        .map_err(|e| e.to_string())
+}
+
+/// Today's log file, for attaching to bug reports
+#[tauri::command]
+pub fn get_log_path() -> Result<String, String> {
+    crate::logging::current_log_path()
+        .map(|p| p.to_string_lossy().to_string())
+        .ok_or_else(|| "File logging is not initialised".to_string())
+}
+
+#[tauri::command]
+pub async fn open_logs() -> Result<(), String> {
+    let dir = crate::logging::log_dir()
+        .ok_or_else(|| "File logging is not initialised".to_string())?;
+    open::that(&dir).map_err(|err| err.to_string())
+}
+
 fn check_requirements_internal(
     app_handle: &AppHandle,
 ) -> Result<RequirementsCheckResponse, String> {
@@ -751,7 +2236,7 @@ fn check_requirements_internal(
                 name: "ffmpeg".to_string(),
                 available: false,
                 path: None,
-                error: Some(format!("ffmpeg not found: {}", err)),
+                error: Some(err),
             });
         }
     }
@@ -762,11 +2247,42 @@ fn check_requirements_internal(
     })
 }
 
-fn resolve_ffmpeg_path(app_handle: &AppHandle) -> Result<PathBuf, which::Error> {
+fn resolve_ffmpeg_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
     if let Some(path) = bundled_ffmpeg_path(app_handle) {
+        ensure_executable(&path)?;
         return Ok(path);
     }
-    which::which("ffmpeg")
+    which::which("ffmpeg").map_err(|err| format!("ffmpeg not found: {}", err))
+}
+
+/// Bundled binaries can lose their execute bit in packaging; restore it once
+/// rather than failing later with an opaque spawn error.
+#[cfg(unix)]
+fn ensure_executable(path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let metadata = std::fs::metadata(path)
+        .map_err(|err| format!("ffmpeg found at {} but unreadable: {}", path.display(), err))?;
+    let mut perms = metadata.permissions();
+    if perms.mode() & 0o111 != 0 {
+        return Ok(());
+    }
+
+    perms.set_mode(perms.mode() | 0o755);
+    std::fs::set_permissions(path, perms).map_err(|err| {
+        format!(
+            "ffmpeg found at {} but not executable, and it could not be made executable: {}",
+            path.display(),
+            err
+        )
+    })?;
+    log_info!("Marked bundled ffmpeg as executable: {}", path.display());
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn ensure_executable(_path: &Path) -> Result<(), String> {
+    Ok(())
 }
 
 fn bundled_ffmpeg_path(app_handle: &AppHandle) -> Option<PathBuf> {
@@ -781,77 +2297,596 @@ fn bundled_ffmpeg_path(app_handle: &AppHandle) -> Option<PathBuf> {
         &["ffmpeg/linux/ffmpeg", "resources/ffmpeg/linux/ffmpeg"]
     };
 
-    candidates.iter().find_map(|relative| {
-        app_handle
-            .path()
-            .resolve(relative, BaseDirectory::Resource)
-            .ok()
-            .filter(|path| path.exists())
+    candidates.iter().find_map(|relative| {
+        app_handle
+            .path()
+            .resolve(relative, BaseDirectory::Resource)
+            .ok()
+            .filter(|path| path.exists())
+    })
+}
+
+#[tauri::command]
+pub fn get_app_version() -> String {
+    env!("CARGO_PKG_VERSION").to_string()
+}
+
+// Resume download commands
+#[tauri::command]
+pub fn get_incomplete_downloads(
+    tracker: State<'_, DownloadTracker>,
+    library: State<'_, crate::library::Library>,
+) -> Result<Vec<DownloadRecord>, String> {
+    let mut pending = tracker.get_incomplete_downloads();
+
+    // Queued items that landed in the library some other way shouldn't be re-queued
+    pending.retain(|record| {
+        let already_downloaded = record.status == crate::download_tracker::DownloadStatus::Queued
+            && library
+                .check_episode_downloaded(&record.slug, record.episode)
+                .unwrap_or(false);
+        if already_downloaded {
+            let _ = tracker.remove_download(&record.id);
+        }
+        !already_downloaded
+    });
+
+    Ok(pending)
+}
+
+#[tauri::command]
+pub fn set_download_priority(
+    tracker: State<'_, DownloadTracker>,
+    download_id: String,
+    priority: i32,
+) -> Result<(), String> {
+    tracker.set_priority(&download_id, priority)
+}
+
+#[tauri::command]
+pub async fn resume_download(
+    tracker: State<'_, DownloadTracker>,
+    download_id: String,
+    state: State<'_, AppState>,
+    download_state: State<'_, DownloadState>,
+    window: Window,
+    library: State<'_, crate::library::Library>,
+) -> Result<(), String> {
+    // Get the download record
+    let record = tracker.get_download(&download_id)
+        .ok_or_else(|| "Download record not found".to_string())?;
+
+    // Remove the old record to allow fresh download with same settings
+    tracker.remove_download(&download_id)?;
+
+    // Prepare download request
+    let filename_template = state.settings.lock().unwrap().filename_template.clone();
+    let req = StartDownloadRequest {
+        anime_slug: record.slug.clone(),
+        anime_name: record.anime_name.clone(),
+        episodes: vec![record.episode],
+        audio_type: record.audio_type.clone(),
+        resolution: record.resolution.clone(),
+        download_dir: download_root(&record.file_path, &filename_template),
+        host: state.settings.lock().unwrap().host_url.clone(),
+        resume_download_id: None,
+        threads: None, // Use default from settings
+        chapters: false,
+        override_key_hex: None,
+        keep_as_hls: false,
+        keep_segments: false,
+        overwrite: false,
+        output_format: None,
+        priority: record.priority,
+        work_dir: record.work_dir.clone(),
+    };
+
+    // Start the download
+    start_download(state, download_state, window, tracker, library, req).await
+}
+
+/// Restart every failed download. Episodes of the same show and settings
+/// go out as one batch so they queue in order instead of all at once.
+/// Returns how many episodes were requeued.
+#[tauri::command]
+pub async fn retry_all_failed(
+    tracker: State<'_, DownloadTracker>,
+    state: State<'_, AppState>,
+    download_state: State<'_, DownloadState>,
+    window: Window,
+    library: State<'_, crate::library::Library>,
+) -> Result<usize, String> {
+    let failed: Vec<DownloadRecord> = tracker
+        .get_incomplete_downloads()
+        .into_iter()
+        .filter(|r| r.status == crate::download_tracker::DownloadStatus::Failed)
+        .collect();
+    requeue_records(failed, "Retrying", tracker, state, download_state, window, library).await
+}
+
+/// Start tracker records again, dropping ones whose file already landed.
+/// Records of the same show and settings become one batch; one with a
+/// segment work dir goes alone so it picks up where it stopped. Each
+/// requeued episode gets a `status` event. Returns how many were requeued.
+async fn requeue_records(
+    mut records: Vec<DownloadRecord>,
+    status: &str,
+    tracker: State<'_, DownloadTracker>,
+    state: State<'_, AppState>,
+    download_state: State<'_, DownloadState>,
+    window: Window,
+    library: State<'_, crate::library::Library>,
+) -> Result<usize, String> {
+    records.sort_by_key(|r| (r.priority, r.started_at, r.episode));
+    let filename_template = state.settings.lock().unwrap().filename_template.clone();
+
+    type BatchKey = (String, String, Option<String>, Option<String>, Option<String>, Option<String>);
+    let mut batches: Vec<(BatchKey, i32, Vec<EpisodeNumber>)> = Vec::new();
+    let now = chrono::Utc::now().timestamp();
+    for record in records {
+        // Failed too recently; a later retry picks it up once the backoff passes
+        if record.retry_after.is_some_and(|after| after > now) {
+            continue;
+        }
+        // Already on disk and in the library: nothing to retry
+        let landed = tracker.validate_file(&record.id).unwrap_or(false)
+            && library
+                .check_episode_downloaded(&record.slug, record.episode)
+                .unwrap_or(false);
+        tracker.remove_download(&record.id)?;
+        if landed {
+            continue;
+        }
+
+        let _ = window.emit(
+            "download-status",
+            StatusPayload {
+                episode: record.episode,
+                status: status.to_string(),
+                path: None,
+            },
+        );
+        let download_dir = download_root(&record.file_path, &filename_template);
+        let key = (
+            record.slug,
+            record.anime_name,
+            record.audio_type,
+            record.resolution,
+            download_dir,
+            record.work_dir,
+        );
+        match batches.iter_mut().find(|(k, _, _)| *k == key) {
+            Some((_, _, episodes)) => episodes.push(record.episode),
+            None => batches.push((key, record.priority, vec![record.episode])),
+        }
+    }
+
+    let host = state.settings.lock().unwrap().host_url.clone();
+    let mut requeued = 0;
+    for ((anime_slug, anime_name, audio_type, resolution, download_dir, work_dir), priority, episodes) in batches {
+        requeued += episodes.len();
+        let req = StartDownloadRequest {
+            anime_slug,
+            anime_name,
+            episodes,
+            audio_type,
+            resolution,
+            download_dir,
+            host: host.clone(),
+            resume_download_id: None,
+            threads: None,
+            chapters: false,
+            override_key_hex: None,
+            keep_as_hls: false,
+            keep_segments: false,
+            overwrite: false,
+            output_format: None,
+            priority,
+            work_dir,
+        };
+        start_download(
+            state.clone(),
+            download_state.clone(),
+            window.clone(),
+            tracker.clone(),
+            library.clone(),
+            req,
+        )
+        .await?;
+    }
+
+    Ok(requeued)
+}
+
+/// Gives the window time to load and subscribe to events before auto-resume
+const AUTO_RESUME_DELAY: Duration = Duration::from_secs(5);
+
+/// With `auto_resume_on_startup`, pick up downloads a crash or quit left
+/// queued, in progress or failed. Paused ones stay paused.
+pub async fn auto_resume_downloads(app: AppHandle) {
+    if !app.state::<AppState>().settings.lock().unwrap().auto_resume_on_startup {
+        return;
+    }
+    sleep(AUTO_RESUME_DELAY).await;
+
+    let records: Vec<DownloadRecord> = app
+        .state::<DownloadTracker>()
+        .get_incomplete_downloads()
+        .into_iter()
+        .filter(|r| r.status != crate::download_tracker::DownloadStatus::Paused)
+        .collect();
+    if records.is_empty() {
+        return;
+    }
+    let Some(window) = app.get_webview_window("main") else {
+        log_warn!("Main window missing; not auto-resuming {} downloads", records.len());
+        return;
+    };
+
+    match requeue_records(
+        records,
+        "Resuming after restart",
+        app.state(),
+        app.state(),
+        app.state(),
+        window.as_ref().window(),
+        app.state(),
+    )
+    .await
+    {
+        Ok(count) => log_info!("Auto-resumed {} downloads from the last session", count),
+        Err(e) => log_warn!("Auto-resume failed: {}", e),
+    }
+}
+
+#[tauri::command]
+pub fn remove_download_record(
+    tracker: State<'_, DownloadTracker>,
+    download_id: String,
+) -> Result<(), String> {
+    tracker.remove_download(&download_id)
+}
+
+#[tauri::command]
+pub async fn clear_completed_downloads(
+    window: Window,
+    tracker: State<'_, DownloadTracker>,
+    bulk_ops: State<'_, BulkOpState>,
+) -> Result<(), String> {
+    let statuses = vec![crate::download_tracker::DownloadStatus::Completed];
+    clear_tracked(window, &tracker, &bulk_ops, statuses).await.map(|_| ())
+}
+
+/// Drop tracker records in any of `statuses`, e.g. failed and cancelled.
+/// Returns how many were removed.
+#[tauri::command]
+pub async fn clear_downloads(
+    window: Window,
+    tracker: State<'_, DownloadTracker>,
+    bulk_ops: State<'_, BulkOpState>,
+    statuses: Vec<crate::download_tracker::DownloadStatus>,
+) -> Result<usize, String> {
+    clear_tracked(window, &tracker, &bulk_ops, statuses).await
+}
+
+/// Remove the records in `statuses` one at a time as a cancellable bulk op.
+/// Returns how many went before it finished or was cancelled.
+async fn clear_tracked(
+    window: Window,
+    tracker: &DownloadTracker,
+    bulk_ops: &BulkOpState,
+    statuses: Vec<crate::download_tracker::DownloadStatus>,
+) -> Result<usize, String> {
+    const OP: &str = "clear_downloads";
+
+    let ids = tracker.ids_with_status(&statuses)?;
+    let cancelled = bulk_ops.begin(OP)?;
+    let tracker = tracker.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let total = ids.len();
+        emit_bulk_progress(&window, OP, 0, total, None);
+        let mut removed = 0;
+        for id in ids {
+            if cancelled.load(std::sync::atomic::Ordering::Relaxed) {
+                break;
+            }
+            tracker.remove_download(&id)?;
+            removed += 1;
+            emit_bulk_progress(&window, OP, removed, total, Some(&id));
+        }
+        Ok(removed)
+    })
+    .await;
+    bulk_ops.finish(OP);
+    result.map_err(|e| e.to_string())?
+}
+
+#[derive(Debug, Serialize)]
+pub struct LibraryScanIssue {
+    pub id: i64,
+    pub anime_name: String,
+    pub episode: EpisodeNumber,
+    pub file_path: String,
+    pub recorded_size: i64,
+    /// Size on disk now; `None` when the file is gone
+    pub actual_size: Option<u64>,
+    /// New location found under the search root; the entry already points there
+    pub relocated_to: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LibraryScanReport {
+    pub scanned: usize,
+    pub missing: Vec<LibraryScanIssue>,
+    pub size_mismatch: Vec<LibraryScanIssue>,
+    pub relocated: Vec<LibraryScanIssue>,
+}
+
+/// Files under `root` whose names are in `wanted`, without following symlinks
+fn find_files_named(root: &Path, wanted: &std::collections::HashSet<String>) -> HashMap<String, Vec<PathBuf>> {
+    let mut found: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(read) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in read.filter_map(|e| e.ok()) {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if file_type.is_file() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if wanted.contains(&name) {
+                    found.entry(name).or_default().push(entry.path());
+                }
+            }
+        }
+    }
+    found
+}
+
+/// Check every library entry against the disk: missing files and files
+/// whose size changed. With `search_root`, a missing file is relocated to
+/// a file of the same name and size found under it. Runs as the
+/// "scan_library" bulk op; a cancelled scan reports the entries checked so
+/// far and skips relocation.
+#[tauri::command]
+pub async fn scan_library(
+    window: Window,
+    library: State<'_, crate::library::Library>,
+    bulk_ops: State<'_, BulkOpState>,
+    search_root: Option<String>,
+) -> Result<LibraryScanReport, String> {
+    const OP: &str = "scan_library";
+
+    let library = (*library).clone();
+    let cancelled = bulk_ops.begin(OP)?;
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let entries = library.get_library_entries().map_err(|e| e.to_string())?;
+        let total = entries.len();
+        emit_bulk_progress(&window, OP, 0, total, None);
+        let mut report = LibraryScanReport {
+            scanned: 0,
+            missing: Vec::new(),
+            size_mismatch: Vec::new(),
+            relocated: Vec::new(),
+        };
+        let issue = |entry: &crate::library::LibraryEntry, actual_size: Option<u64>| LibraryScanIssue {
+            id: entry.id,
+            anime_name: entry.anime_name.clone(),
+            episode: entry.episode,
+            file_path: entry.file_path.clone(),
+            recorded_size: entry.file_size,
+            actual_size,
+            relocated_to: None,
+        };
+
+        let mut missing = Vec::new();
+        for entry in &entries {
+            if cancelled.load(std::sync::atomic::Ordering::Relaxed) {
+                return Ok(report);
+            }
+            match output_size(Path::new(&entry.file_path)) {
+                None => missing.push(entry),
+                // Sizes of kept HLS folders drift as players write thumbnails; only check files
+                Some(size) if size as i64 != entry.file_size && !entry.file_path.ends_with(".m3u8") => {
+                    report.size_mismatch.push(issue(entry, Some(size)));
+                }
+                Some(_) => {}
+            }
+            report.scanned += 1;
+            emit_bulk_progress(&window, OP, report.scanned, total, Some(&entry.anime_name));
+        }
+
+        // Every kept HLS download is called index.m3u8, so matching by name would guess
+        let candidates = match search_root.as_deref() {
+            Some(root) => {
+                let wanted = missing
+                    .iter()
+                    .filter(|e| !e.file_path.ends_with(".m3u8"))
+                    .filter_map(|e| Path::new(&e.file_path).file_name())
+                    .map(|n| n.to_string_lossy().to_string())
+                    .collect();
+                find_files_named(Path::new(root), &wanted)
+            }
+            None => HashMap::new(),
+        };
+        for entry in missing {
+            let name = Path::new(&entry.file_path)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let found = candidates.get(&name).and_then(|paths| {
+                paths.iter().find(|p| {
+                    std::fs::metadata(p).is_ok_and(|m| m.len() as i64 == entry.file_size)
+                })
+            });
+            let relocated = found.filter(|path| {
+                library
+                    .update_file_path(entry.id, &path.to_string_lossy())
+                    .map_err(|e| log_warn!("Relocating library entry {} failed: {}", entry.id, e))
+                    .is_ok()
+            });
+            match relocated {
+                Some(path) => {
+                    let mut moved = issue(entry, Some(entry.file_size as u64));
+                    moved.relocated_to = Some(path.to_string_lossy().to_string());
+                    report.relocated.push(moved);
+                }
+                None => report.missing.push(issue(entry, None)),
+            }
+        }
+        Ok(report)
     })
+    .await;
+    bulk_ops.finish(OP);
+    result.map_err(|e| e.to_string())?
 }
 
-#[tauri::command]
-pub fn get_app_version() -> String {
-    env!("CARGO_PKG_VERSION").to_string()
+/// Matches "Show - 05", "Ep 12.5", "S01E03", "episode_7" and the app's own
+/// "{anime}/{episode}" names such as "3"
+pub const DEFAULT_LOCAL_EPISODE_PATTERN: &str =
+    r"(?i)^(\d{1,4}(?:\.\d)?)$|(?:(?:^|[^a-z])(?:e|ep|episode)[\s._-]*|\s-\s)(\d{1,4}(?:\.\d)?)(?:v\d)?(?:[\s._\[(-]|$)";
+
+#[derive(Debug, Serialize)]
+pub struct SkippedFile {
+    pub path: String,
+    pub reason: String,
 }
 
-// Resume download commands
-#[tauri::command]
-pub fn get_incomplete_downloads(
-    tracker: State<'_, DownloadTracker>,
-) -> Result<Vec<DownloadRecord>, String> {
-    Ok(tracker.get_incomplete_downloads())
+#[derive(Debug, Serialize)]
+pub struct LocalImportReport {
+    pub imported: Vec<crate::library::LibraryEntry>,
+    pub skipped: Vec<SkippedFile>,
 }
 
+/// Add the video files in `dir` to the library under `slug` without
+/// downloading anything. Episode numbers come from `local_episode_pattern`;
+/// files it can't match, or whose episode is already in the library, are
+/// reported as skipped.
 #[tauri::command]
-pub async fn resume_download(
-    tracker: State<'_, DownloadTracker>,
-    download_id: String,
+pub async fn import_local_files(
     state: State<'_, AppState>,
-    download_state: State<'_, DownloadState>,
-    window: Window,
     library: State<'_, crate::library::Library>,
-) -> Result<(), String> {
-    // Get the download record
-    let record = tracker.get_download(&download_id)
-        .ok_or_else(|| "Download record not found".to_string())?;
+    dir: String,
+    slug: String,
+    anime_name: String,
+) -> Result<LocalImportReport, String> {
+    let (pattern, host) = {
+        let settings = state.settings.lock().unwrap();
+        (settings.local_episode_pattern.clone(), settings.host_url.clone())
+    };
+    let pattern = regex::Regex::new(&pattern).map_err(|e| format!("Invalid episode pattern: {}", e))?;
+    if pattern.captures_len() < 2 {
+        return Err("Episode pattern needs a capture group for the episode number".into());
+    }
 
-    // Remove the old record to allow fresh download with same settings
-    tracker.remove_download(&download_id)?;
+    let mut files: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .map_err(|e| format!("Failed to read {}: {}", dir, e))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .filter(|p| {
+            p.extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .is_some_and(|ext| crate::player::VIDEO_EXTENSIONS.contains(&ext.as_str()))
+        })
+        .collect();
+    files.sort();
 
-    // Prepare download request
-    let req = StartDownloadRequest {
-        anime_slug: record.slug.clone(),
-        anime_name: record.anime_name.clone(),
-        episodes: vec![record.episode as u32],
-        audio_type: record.audio_type.clone(),
-        resolution: record.resolution.clone(),
-        download_dir: std::path::Path::new(&record.file_path)
-            .parent()
-            .and_then(|p| p.to_str())
-            .map(|s| s.to_string()),
-        host: state.settings.lock().unwrap().host_url.clone(),
-        resume_download_id: None,
-        threads: None, // Use default from settings
+    let mut report = LocalImportReport { imported: Vec::new(), skipped: Vec::new() };
+    let mut skip = |path: &Path, reason: String| {
+        report.skipped.push(SkippedFile { path: path.to_string_lossy().to_string(), reason });
     };
+    let mut imported = Vec::new();
+    for path in files {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let episode = pattern
+            .captures(&stem)
+            .and_then(|c| c.iter().skip(1).flatten().next())
+            .and_then(|m| m.as_str().parse::<f64>().ok())
+            .and_then(EpisodeNumber::from_f64);
+        let Some(episode) = episode else {
+            skip(&path, "No episode number in file name".into());
+            continue;
+        };
+        if library.check_episode_downloaded(&slug, episode).map_err(|e| e.to_string())? {
+            skip(&path, format!("Episode {} is already in the library", episode));
+            continue;
+        }
+        let size = match std::fs::metadata(&path) {
+            Ok(meta) => meta.len() as i64,
+            Err(e) => {
+                skip(&path, format!("Failed to read file: {}", e));
+                continue;
+            }
+        };
+        let file_path = path.to_string_lossy().to_string();
+        let info = crate::player::probe_media_info(&file_path).await.ok();
+        let resolution = info.as_ref().and_then(|i| i.height).map(|h| h.to_string());
+        let duration_seconds = info
+            .and_then(|i| i.duration_seconds)
+            .map(|secs| secs.round() as i64);
+        match library.add_download(
+            &anime_name,
+            &slug,
+            episode,
+            resolution.as_deref(),
+            None,
+            &file_path,
+            size,
+            None,
+            &host,
+            duration_seconds,
+        ) {
+            Ok(id) => imported.push(id),
+            Err(e) => skip(&path, format!("{:#}", e)),
+        }
+    }
+    log_info!("Imported {} local files for {} ({} skipped)", imported.len(), slug, report.skipped.len());
 
-    // Start the download
-    start_download(state, download_state, window, tracker, library, req).await
+    for id in imported {
+        if let Some(entry) = library.get_entry_by_id(id).map_err(|e| e.to_string())? {
+            report.imported.push(entry);
+        }
+    }
+    Ok(report)
 }
 
+/// Move an entry's file to `new_path` and repoint the library at it
 #[tauri::command]
-pub fn remove_download_record(
-    tracker: State<'_, DownloadTracker>,
-    download_id: String,
-) -> Result<(), String> {
-    tracker.remove_download(&download_id)
+pub async fn relocate_entry(
+    library: State<'_, crate::library::Library>,
+    id: i64,
+    new_path: String,
+) -> Result<crate::library::RelocatedEntry, String> {
+    let library = (*library).clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        library
+            .relocate_entry(id, Path::new(&new_path))
+            .map_err(|e| format!("{:#}", e))
+    })
+    .await
+    .map_err(|e| e.to_string())?
 }
 
+/// Move every episode of a show into `new_dir`; nothing changes if any move fails
 #[tauri::command]
-pub fn clear_completed_downloads(
-    tracker: State<'_, DownloadTracker>,
-) -> Result<(), String> {
-    tracker.clear_completed()
+pub async fn relocate_anime(
+    library: State<'_, crate::library::Library>,
+    slug: String,
+    new_dir: String,
+) -> Result<Vec<crate::library::RelocatedEntry>, String> {
+    let library = (*library).clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        library
+            .relocate_anime(&slug, Path::new(&new_dir))
+            .map_err(|e| format!("{:#}", e))
+    })
+    .await
+    .map_err(|e| e.to_string())?
 }
 
 #[tauri::command]
@@ -862,13 +2897,52 @@ pub fn validate_download_integrity(
     tracker.validate_file(&download_id)
 }
 
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ValidateProgressPayload {
+    download_id: String,
+    bytes_read: u64,
+    total_bytes: u64,
+}
+
+#[tauri::command]
+pub async fn validate_download_integrity_deep(
+    window: Window,
+    tracker: State<'_, DownloadTracker>,
+    download_id: String,
+    hash: Option<bool>,
+) -> Result<crate::download_tracker::DeepValidation, String> {
+    let tracker = (*tracker).clone();
+    let hash = hash.unwrap_or(false);
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut last_emit = std::time::Instant::now();
+        tracker.validate_file_deep(&download_id, hash, |bytes_read, total_bytes| {
+            // Throttle events so multi-GB files don't flood the webview
+            if last_emit.elapsed() >= std::time::Duration::from_millis(200) || bytes_read == total_bytes {
+                last_emit = std::time::Instant::now();
+                let _ = window.emit(
+                    "validate-progress",
+                    ValidateProgressPayload {
+                        download_id: download_id.clone(),
+                        bytes_read,
+                        total_bytes,
+                    },
+                );
+            }
+        })
+    })
+    .await
+    .map_err(|e| format!("Validation task failed: {}", e))?
+}
+
 // Library commands
 
 #[tauri::command]
 pub fn check_episode_downloaded(
     library: State<'_, crate::library::Library>,
     slug: String,
-    episode: i32,
+    episode: EpisodeNumber,
 ) -> Result<bool, String> {
     library.check_episode_downloaded(&slug, episode)
         .map_err(|e| e.to_string())
@@ -878,7 +2952,7 @@ pub fn check_episode_downloaded(
 pub fn get_library_entry(
     library: State<'_, crate::library::Library>,
     slug: String,
-    episode: i32,
+    episode: EpisodeNumber,
 ) -> Result<Option<crate::library::LibraryEntry>, String> {
     library.get_library_entry(&slug, episode)
         .map_err(|e| e.to_string())
@@ -892,11 +2966,83 @@ pub fn get_library_entries(
         .map_err(|e| e.to_string())
 }
 
+/// Downloaded episodes of a show and the regular episodes missing between them
+#[tauri::command]
+pub fn get_episode_gaps(
+    library: State<'_, crate::library::Library>,
+    slug: String,
+) -> Result<crate::library::EpisodeGaps, String> {
+    library.get_episode_gaps(&slug)
+        .map_err(|e| e.to_string())
+}
+
+/// Filtered, sorted page of library entries
+#[tauri::command]
+pub fn query_library(
+    library: State<'_, crate::library::Library>,
+    query: crate::library::LibraryQuery,
+) -> Result<crate::library::LibraryPage, String> {
+    library.query_library(&query)
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub fn get_anime_library(
     library: State<'_, crate::library::Library>,
+    tag: Option<String>,
+    sort: Option<crate::library::LibrarySort>,
+) -> Result<Vec<crate::library::AnimeStats>, String> {
+    library.get_anime_library(tag.as_deref(), sort.unwrap_or_default())
+        .map_err(|e| e.to_string())
+}
+
+/// Rate a show 1-10; `null` clears the rating
+#[tauri::command]
+pub fn set_anime_rating(
+    library: State<'_, crate::library::Library>,
+    slug: String,
+    rating: Option<i64>,
+) -> Result<(), String> {
+    library.set_anime_rating(&slug, rating)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn add_tag(
+    library: State<'_, crate::library::Library>,
+    slug: String,
+    tag: String,
+) -> Result<(), String> {
+    library.add_tag(&slug, &tag)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn remove_tag(
+    library: State<'_, crate::library::Library>,
+    slug: String,
+    tag: String,
+) -> Result<(), String> {
+    library.remove_tag(&slug, &tag)
+        .map_err(|e| e.to_string())
+}
+
+/// Tags on `slug`, or every tag (defaults included) when it's omitted
+#[tauri::command]
+pub fn list_tags(
+    library: State<'_, crate::library::Library>,
+    slug: Option<String>,
+) -> Result<Vec<String>, String> {
+    library.list_tags(slug.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_anime_by_tag(
+    library: State<'_, crate::library::Library>,
+    tag: String,
 ) -> Result<Vec<crate::library::AnimeStats>, String> {
-    library.get_anime_library()
+    library.get_anime_by_tag(&tag)
         .map_err(|e| e.to_string())
 }
 
@@ -909,6 +3055,24 @@ pub fn get_anime_episodes(
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub fn get_unwatched_episodes(
+    library: State<'_, crate::library::Library>,
+    slug: String,
+) -> Result<Vec<crate::library::LibraryEntry>, String> {
+    library.get_unwatched(&slug)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_watched_episodes(
+    library: State<'_, crate::library::Library>,
+    slug: String,
+) -> Result<Vec<crate::library::LibraryEntry>, String> {
+    library.get_watched(&slug)
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub fn mark_episode_watched(
     library: State<'_, crate::library::Library>,
@@ -918,6 +3082,25 @@ pub fn mark_episode_watched(
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub fn update_playback_position(
+    library: State<'_, crate::library::Library>,
+    id: i64,
+    seconds: f64,
+) -> Result<(), String> {
+    library.update_playback_position(id, seconds)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_playback_position(
+    library: State<'_, crate::library::Library>,
+    id: i64,
+) -> Result<Option<f64>, String> {
+    library.get_playback_position(id)
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub fn delete_library_entry(
     library: State<'_, crate::library::Library>,
@@ -944,6 +3127,48 @@ pub fn get_library_stats(
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub fn get_extended_stats(
+    library: State<'_, crate::library::Library>,
+) -> Result<crate::library::ExtendedLibraryStats, String> {
+    library.get_extended_stats()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_distinct_resolutions(
+    library: State<'_, crate::library::Library>,
+) -> Result<Vec<String>, String> {
+    library.distinct_resolutions()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_distinct_audio(
+    library: State<'_, crate::library::Library>,
+) -> Result<Vec<String>, String> {
+    library.distinct_audio()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn find_duplicate_anime(
+    library: State<'_, crate::library::Library>,
+) -> Result<crate::library::DuplicateReport, String> {
+    library.find_duplicate_anime()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn merge_duplicate_anime(
+    library: State<'_, crate::library::Library>,
+    slug: String,
+    canonical_name: String,
+) -> Result<usize, String> {
+    library.merge_duplicate_anime(&slug, &canonical_name)
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub fn search_library(
     library: State<'_, crate::library::Library>,
@@ -982,16 +3207,94 @@ pub fn export_library_to_file(
 }
 
 #[tauri::command]
-pub fn import_library_from_file(
+pub async fn import_library_from_file(
+    window: Window,
     library: State<'_, crate::library::Library>,
+    bulk_ops: State<'_, BulkOpState>,
     file_path: String,
 ) -> Result<usize, String> {
+    const OP: &str = "import_library";
+
     let json = std::fs::read_to_string(&file_path)
         .map_err(|e| format!("Failed to read file: {}", e))?;
-    library.import_library(&json)
+    let cancelled = bulk_ops.begin(OP)?;
+    let library = (*library).clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        library.import_library_with(&json, |done, total, anime_name| {
+            emit_bulk_progress(&window, OP, done, total, Some(anime_name));
+            !cancelled.load(std::sync::atomic::Ordering::Relaxed)
+        })
+    })
+    .await;
+    bulk_ops.finish(OP);
+    result
+        .map_err(|e| e.to_string())?
         .map_err(|e| e.to_string())
 }
 
+#[derive(Debug, Serialize)]
+pub struct CompactResult {
+    pub library_bytes_reclaimed: u64,
+    pub tracker_bytes_reclaimed: u64,
+    pub total_bytes_reclaimed: u64,
+}
+
+/// Vacuum the library, then the download database. Cancelling between the
+/// two skips the second; a VACUUM already running can't be interrupted.
+#[tauri::command]
+pub async fn compact_databases(
+    window: Window,
+    library: State<'_, crate::library::Library>,
+    tracker: State<'_, DownloadTracker>,
+    bulk_ops: State<'_, BulkOpState>,
+) -> Result<CompactResult, String> {
+    const OP: &str = "compact_databases";
+    const TOTAL: usize = 2;
+
+    let library = (*library).clone();
+    let tracker = (*tracker).clone();
+    let cancelled = bulk_ops.begin(OP)?;
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        emit_bulk_progress(&window, OP, 0, TOTAL, Some("library"));
+        let library_bytes_reclaimed = library.compact().map_err(|e| e.to_string())?;
+        emit_bulk_progress(&window, OP, 1, TOTAL, Some("downloads"));
+        let tracker_bytes_reclaimed = if cancelled.load(std::sync::atomic::Ordering::Relaxed) {
+            0
+        } else {
+            let reclaimed = tracker.compact()?;
+            emit_bulk_progress(&window, OP, 2, TOTAL, None);
+            reclaimed
+        };
+        Ok(CompactResult {
+            library_bytes_reclaimed,
+            tracker_bytes_reclaimed,
+            total_bytes_reclaimed: library_bytes_reclaimed + tracker_bytes_reclaimed,
+        })
+    })
+    .await;
+    bulk_ops.finish(OP);
+    result.map_err(|e| e.to_string())?
+}
+
+/// Save the anime's poster at the requested size and return the local path
+#[tauri::command]
+pub async fn download_poster(
+    state: State<'_, AppState>,
+    slug: String,
+    host: String,
+    size: Option<api::PosterSize>,
+) -> Result<String, String> {
+    let cookie = state.cookie();
+    let host = settings::normalize_host(&host);
+    let posters = api::fetch_anime_posters(&slug, &cookie, &host)
+        .await
+        .map_err(|e| e.to_string())?;
+    let url = posters
+        .pick(size.unwrap_or_default())
+        .ok_or_else(|| "No poster found for this anime".to_string())?;
+    download_and_save_poster(&url, &slug, &cookie, &host).await
+}
+
 async fn download_and_save_poster(
     url: &str,
     slug: &str,
@@ -1021,11 +3324,10 @@ async fn download_and_save_poster(
     }
 
     // Download the image
-    let client = reqwest::Client::new();
+    let client = crate::network::http_client();
     let response = client
         .get(url)
         .header("Referer", format!("{}/anime/{}", host.trim_end_matches('/'), slug))
-        .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
         .header("Cookie", cookie)
         .send()
         .await
@@ -1046,11 +3348,28 @@ async fn download_and_save_poster(
     Ok(poster_path.to_string_lossy().to_string())
 }
 
+#[tauri::command]
+pub fn cancel_bulk_op(
+    bulk_ops: State<'_, BulkOpState>,
+    op: String,
+) -> Result<(), String> {
+    let ops = bulk_ops.ops.lock().unwrap();
+    let flag = ops
+        .get(&op)
+        .ok_or_else(|| format!("{} is not running", op))?;
+    flag.store(true, std::sync::atomic::Ordering::Relaxed);
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn migrate_library_posters(
+    window: Window,
     library: State<'_, crate::library::Library>,
     state: State<'_, AppState>,
+    bulk_ops: State<'_, BulkOpState>,
 ) -> Result<(), String> {
+    const OP: &str = "migrate_posters";
+
     let host = {
         let settings = state.settings.lock().unwrap();
         settings.host_url.clone()
@@ -1058,24 +3377,33 @@ pub async fn migrate_library_posters(
     let cookie = ""; // No cookie needed for poster migration
 
     // Get all anime from library
-    let anime_list = library.get_anime_library()
+    let anime_list = library.get_anime_library(None, crate::library::LibrarySort::Recent)
         .map_err(|e| e.to_string())?;
 
-    for anime in anime_list {
+    let cancelled = bulk_ops.begin(OP)?;
+    let total = anime_list.len();
+    emit_bulk_progress(&window, OP, 0, total, None);
+
+    for (i, anime) in anime_list.into_iter().enumerate() {
+        if cancelled.load(std::sync::atomic::Ordering::Relaxed) {
+            break;
+        }
+
         // Skip if already has local path
         if let Some(ref url) = anime.thumbnail_url {
-            if url.starts_with("/") || url.starts_with("~") {
-                continue; // Already local path
-            }
-
-            // Download and save poster
-            if let Ok(local_path) = download_and_save_poster(url, &anime.slug, cookie, &host).await {
-                // Update all episodes with this anime
-                let _ = library.update_poster_path(&anime.slug, &local_path);
+            if !(url.starts_with("/") || url.starts_with("~")) {
+                // Download and save poster
+                if let Ok(local_path) = download_and_save_poster(url, &anime.slug, cookie, &host).await {
+                    // Update all episodes with this anime
+                    let _ = library.update_poster_path(&anime.slug, &local_path);
+                }
             }
         }
+
+        emit_bulk_progress(&window, OP, i + 1, total, Some(&anime.anime_name));
     }
 
+    bulk_ops.finish(OP);
     Ok(())
 }
 
@@ -1107,12 +3435,27 @@ pub async fn fetch_image_as_base64(path: String) -> Result<String, String> {
 // Notification commands
 
 #[tauri::command]
-pub async fn play_notification_sound() -> Result<(), String> {
+pub async fn play_notification_sound(state: State<'_, AppState>) -> Result<(), String> {
+    let (enabled, sound_path) = {
+        let settings = state.settings.lock().unwrap();
+        let notifications = &settings.notifications;
+        let path = notifications.sound_path.clone().filter(|p| !p.trim().is_empty());
+        (notifications.enable_sound, path)
+    };
+    if !enabled {
+        return Ok(());
+    }
+    if let Some(path) = sound_path.as_deref() {
+        if !Path::new(path).is_file() {
+            return Err(format!("Notification sound not found: {}", path));
+        }
+    }
+
     #[cfg(target_os = "macos")]
     {
         use std::process::Command;
         Command::new("afplay")
-            .arg("/System/Library/Sounds/Glass.aiff")
+            .arg(sound_path.as_deref().unwrap_or("/System/Library/Sounds/Glass.aiff"))
             .spawn()
             .map_err(|e| format!("Failed to play sound: {}", e))?;
     }
@@ -1120,8 +3463,16 @@ pub async fn play_notification_sound() -> Result<(), String> {
     #[cfg(target_os = "windows")]
     {
         use std::process::Command;
+        // SoundPlayer only plays WAV files
+        let script = match sound_path.as_deref() {
+            Some(path) => format!(
+                "(New-Object Media.SoundPlayer '{}').PlaySync()",
+                path.replace('\'', "''")
+            ),
+            None => "[console]::beep(800,200)".to_string(),
+        };
         Command::new("powershell")
-            .args(&["-c", "[console]::beep(800,200)"])
+            .args(["-c", &script])
             .spawn()
             .map_err(|e| format!("Failed to play sound: {}", e))?;
     }
@@ -1129,10 +3480,13 @@ pub async fn play_notification_sound() -> Result<(), String> {
     #[cfg(target_os = "linux")]
     {
         use std::process::Command;
-        Command::new("paplay")
-            .arg("/usr/share/sounds/freedesktop/stereo/complete.oga")
-            .spawn()
-            .ok(); // Don't fail if sound file doesn't exist
+        let spawned = Command::new("paplay")
+            .arg(sound_path.as_deref().unwrap_or("/usr/share/sounds/freedesktop/stereo/complete.oga"))
+            .spawn();
+        // The stock sound may not be installed; a chosen one should be reported
+        if let (Err(e), Some(_)) = (spawned, sound_path.as_deref()) {
+            return Err(format!("Failed to play sound: {}", e));
+        }
     }
 
     Ok(())
@@ -1140,16 +3494,16 @@ pub async fn play_notification_sound() -> Result<(), String> {
 
 #[tauri::command]
 pub fn update_tray_title(app: AppHandle, title: String) -> Result<(), String> {
-    println!("[TRAY] Attempting to update tray title to: {}", title);
+    log_debug!("Updating tray title to: {}", title);
     if let Some(tray) = app.tray_by_id("main") {
         tray.set_tooltip(Some(&title))
             .map_err(|e| {
-                println!("[TRAY] Failed to update tray: {}", e);
+                log_warn!("Failed to update tray title: {}", e);
                 format!("Failed to update tray: {}", e)
             })?;
-        println!("[TRAY] Successfully updated tray title");
+        log_debug!("Updated tray title");
     } else {
-        println!("[TRAY] WARNING: Tray not found!");
+        log_warn!("Tray icon not found, title not updated");
     }
     Ok(())
 }
@@ -1246,6 +3600,26 @@ pub fn get_video_metadata(file_path: String) -> Result<crate::player::VideoMetad
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub async fn probe_media_info(file_path: String) -> Result<crate::player::MediaInfo, String> {
+    crate::player::probe_media_info(&file_path)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_media_info(
+    library: State<'_, crate::library::Library>,
+    id: i64,
+) -> Result<crate::player::MediaInfo, String> {
+    let entry = library.get_entry_by_id(id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Library entry {} not found", id))?;
+    crate::player::probe_media_info(&entry.file_path)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn get_video_stream_url(
     video_server: State<'_, crate::VideoServerState>,
@@ -1316,8 +3690,8 @@ fn get_cache_path(original_path: &str) -> Result<PathBuf, String> {
 async fn convert_audio_to_aac_lc(input_path: &str, output_path: &PathBuf) -> Result<(), String> {
     use tokio::process::Command;
 
-    println!("Converting audio for: {}", input_path);
-    println!("Output will be: {:?}", output_path);
+    log_debug!("Converting audio for: {}", input_path);
+    log_debug!("Output will be: {:?}", output_path);
 
     let status = Command::new("ffmpeg")
         .args(&[
@@ -1335,7 +3709,7 @@ async fn convert_audio_to_aac_lc(input_path: &str, output_path: &PathBuf) -> Res
         .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
 
     if status.success() {
-        println!("✓ Audio conversion completed successfully");
+        log_debug!("Audio conversion completed successfully");
         Ok(())
     } else {
         Err(format!("ffmpeg conversion failed with status: {}", status))
@@ -1345,7 +3719,7 @@ async fn convert_audio_to_aac_lc(input_path: &str, output_path: &PathBuf) -> Res
 // Main command: Get compatible video path (converts if needed, caches result)
 #[tauri::command]
 pub async fn get_compatible_video_path(file_path: String) -> Result<String, String> {
-    println!("Checking video compatibility for: {}", file_path);
+    log_debug!("Checking video compatibility for: {}", file_path);
 
     // Check if file exists
     if !std::path::Path::new(&file_path).exists() {
@@ -1356,25 +3730,25 @@ pub async fn get_compatible_video_path(file_path: String) -> Result<String, Stri
     let needs_conversion = has_he_aac_audio(&file_path).await?;
 
     if needs_conversion {
-        println!("⚠ Video has HE-AAC audio, conversion needed");
+        log_info!("Video has HE-AAC audio, conversion needed");
 
         // Get cache path
         let cache_path = get_cache_path(&file_path)?;
 
         // Check if already converted and cached
         if cache_path.exists() {
-            println!("✓ Using cached converted file: {:?}", cache_path);
+            log_debug!("Using cached converted file: {:?}", cache_path);
             return Ok(cache_path.to_string_lossy().to_string());
         }
 
         // Convert audio to AAC LC
-        println!("Converting audio to AAC LC...");
+        log_debug!("Converting audio to AAC LC...");
         convert_audio_to_aac_lc(&file_path, &cache_path).await?;
 
-        println!("✓ Conversion complete, using cached file");
+        log_debug!("Conversion complete, using cached file");
         Ok(cache_path.to_string_lossy().to_string())
     } else {
-        println!("✓ Audio codec is already compatible, using original file");
+        log_debug!("Audio codec is already compatible, using original file");
         Ok(file_path)
     }
 }
@@ -1,16 +1,21 @@
 use anyhow::{anyhow, Context, Result};
 use futures::stream::{FuturesUnordered, StreamExt};
 use regex::Regex;
-use reqwest::Client;
 use sanitize_filename::sanitize;
 use std::fs::{self, File};
 use std::io::{BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, OnceLock};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::fs as tokiofs;
+
+use crate::api::EpisodeNumber;
+use crate::metrics::Stage;
+use crate::network::http_client;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tokio::time::{timeout, Duration, sleep};
 
 fn timestamp() -> String {
@@ -24,84 +29,404 @@ fn timestamp() -> String {
 
 static FFMPEG_PATH: OnceLock<PathBuf> = OnceLock::new();
 
+/// Extensions ffmpeg's HLS demuxer may open when reading a playlist
+const HLS_ALLOWED_EXTENSIONS: &[&str] = &["ts", "m3u8", "key", "mp4", "m4s", "vtt"];
+
+/// Caps simultaneous connections per CDN host across every active download,
+/// so batches don't trip rate limits that a single download stays under.
+#[derive(Clone)]
+pub struct HostLimiter {
+    cap: Arc<AtomicUsize>,
+    hosts: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+}
+
+impl HostLimiter {
+    pub fn new(cap: usize) -> Self {
+        Self {
+            cap: Arc::new(AtomicUsize::new(cap.max(1))),
+            hosts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Change the cap. Hosts pick up the new limit on their next acquire;
+    /// permits already handed out drain on the old semaphore.
+    pub fn set_cap(&self, cap: usize) {
+        let cap = cap.max(1);
+        if self.cap.swap(cap, Ordering::Relaxed) != cap {
+            self.hosts.lock().unwrap().clear();
+        }
+    }
+
+    pub async fn acquire(&self, url: &str) -> Result<OwnedSemaphorePermit> {
+        let key = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()))
+            .unwrap_or_default();
+        let sem = {
+            let mut hosts = self.hosts.lock().unwrap();
+            hosts
+                .entry(key)
+                .or_insert_with(|| Arc::new(Semaphore::new(self.cap.load(Ordering::Relaxed))))
+                .clone()
+        };
+        Ok(sem.acquire_owned().await?)
+    }
+}
+
+/// Token bucket shared by every download, so the total rate stays under the
+/// cap however many segments and episodes are in flight. A rate of 0 means
+/// unlimited.
+#[derive(Clone)]
+pub struct BandwidthLimiter {
+    rate_kbps: Arc<AtomicUsize>,
+    // Available bytes (negative while callers are paying off a burst) and last refill
+    bucket: Arc<Mutex<(f64, std::time::Instant)>>,
+}
+
+impl BandwidthLimiter {
+    pub fn new(rate_kbps: usize) -> Self {
+        Self {
+            rate_kbps: Arc::new(AtomicUsize::new(rate_kbps)),
+            bucket: Arc::new(Mutex::new((0.0, std::time::Instant::now()))),
+        }
+    }
+
+    /// Change the cap; takes effect on the next chunk
+    pub fn set_rate(&self, rate_kbps: usize) {
+        self.rate_kbps.store(rate_kbps, Ordering::Relaxed);
+    }
+
+    /// Take `bytes` out of the bucket, sleeping until the debt is covered
+    pub async fn consume(&self, bytes: usize) {
+        let rate_kbps = self.rate_kbps.load(Ordering::Relaxed);
+        if rate_kbps == 0 {
+            return;
+        }
+        let bytes_per_sec = rate_kbps as f64 * 1000.0 / 8.0;
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = std::time::Instant::now();
+            let refill = now.duration_since(bucket.1).as_secs_f64() * bytes_per_sec;
+            // Allow at most one second of burst after an idle stretch
+            bucket.0 = (bucket.0 + refill).min(bytes_per_sec) - bytes as f64;
+            bucket.1 = now;
+            if bucket.0 < 0.0 {
+                Some(Duration::from_secs_f64(-bucket.0 / bytes_per_sec))
+            } else {
+                None
+            }
+        };
+        if let Some(wait) = wait {
+            sleep(wait).await;
+        }
+    }
+}
+
+/// Bytes fetched so far and the expected total, in both download paths.
+/// The total is an estimate and may be 0 until one is available.
+#[derive(Clone, Default)]
+pub struct ByteProgress {
+    pub downloaded: Arc<AtomicUsize>,
+    pub total: Arc<AtomicUsize>,
+}
+
+/// Segments sampled with HEAD requests to estimate an episode's byte total
+const SIZE_SAMPLE_SEGMENTS: usize = 5;
+
+/// Folder per show, file named after the episode number
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "{anime}/{episode}";
+
+/// Per-download knobs that don't change between episodes of a batch
+#[derive(Clone, Default)]
+pub struct DownloadOptions {
+    /// Mux chapter markers from the playlist into the output when available
+    pub chapters: bool,
+    /// Process-wide per-host connection cap shared with other downloads
+    pub host_limiter: Option<HostLimiter>,
+    /// Process-wide bandwidth cap shared with other downloads
+    pub bandwidth: Option<BandwidthLimiter>,
+    /// Debug builds only: decrypt with this AES-128 key instead of the playlist's
+    pub override_key_hex: Option<String>,
+    /// While this reads `true`, no new segment requests are started
+    pub pause_rx: Option<tokio::sync::watch::Receiver<bool>>,
+    /// Reuse AES keys fetched by an earlier attempt in the same batch
+    pub extraction_cache: Option<crate::scrape::ExtractionCache>,
+    /// Skip muxing and leave decrypted segments plus a local `index.m3u8`
+    pub keep_as_hls: bool,
+    /// Stop after decryption and leave the numbered `.ts` segments and
+    /// `playlist.m3u8` in the work dir, which is returned instead of a video
+    pub keep_segments: bool,
+    /// Put scratch files under the OS temp dir, namespaced by this key
+    /// (the download id), instead of next to the finished video
+    pub temp_work_key: Option<String>,
+    /// Retry segments whose body came up short of their `Content-Length`
+    pub verify_segment_length: bool,
+    /// Appended to the episode number in output names, e.g. `.eng` for `1.eng.mp4`
+    pub file_suffix: Option<String>,
+    /// Where to record segment download, decrypt and mux timings
+    pub metrics: Option<crate::metrics::Metrics>,
+    /// Byte counters for the progress UI
+    pub byte_progress: Option<ByteProgress>,
+    /// Segments decrypted at once; 0 is treated as 1
+    pub decrypt_concurrency: usize,
+    /// Fetch a new playlist URL when the segment links have expired
+    pub refresh_playlist: Option<PlaylistRefreshFn>,
+    /// Compatibility escape hatch: let ffmpeg open any extension from the playlist
+    pub allow_all_extensions: bool,
+    /// Output path relative to the download folder, without extension; see
+    /// `expand_filename_template`. Empty means `DEFAULT_FILENAME_TEMPLATE`.
+    pub filename_template: String,
+    /// Audio language of the chosen source, for the `{audio}` token
+    pub audio: Option<String>,
+    /// Hardware decoder to use while transcoding
+    pub hwaccel: crate::settings::HwAccel,
+    /// Re-encode video with these settings instead of stream copying
+    pub transcode: Option<crate::settings::TranscodeSettings>,
+    /// Container for the muxed episode; ffmpeg picks the muxer from the extension
+    pub output_format: crate::settings::OutputFormat,
+    /// Variant height to pick (e.g. "720") when the source is a master playlist
+    pub resolution: Option<String>,
+    /// Kill single-threaded ffmpeg downloads after this long without a
+    /// progress line; 0 waits forever
+    pub ffmpeg_timeout_secs: u64,
+    /// Extra attempts per segment before the episode fails
+    pub segment_retries: usize,
+    /// Per-attempt deadline for one segment; 0 leaves only the client timeout
+    pub segment_timeout_secs: u64,
+    /// Called with (segment number, upcoming attempt) before each segment retry
+    pub on_segment_retry: Option<SegmentRetryFn>,
+    /// Scratch directory left by an earlier attempt, when it can't be derived
+    /// again (e.g. a temp dir keyed by the old download id)
+    pub work_dir: Option<PathBuf>,
+}
+
+pub type SegmentRetryFn = Arc<dyn Fn(usize, usize) + Send + Sync>;
+
+/// Re-extracts the episode's source link, yielding a freshly signed playlist URL
+pub type PlaylistRefreshFn =
+    Arc<dyn Fn() -> futures::future::BoxFuture<'static, Result<String>> + Send + Sync>;
+
+/// Playlist refetches allowed per episode once segment links start returning 403
+const MAX_PLAYLIST_REFRESHES: usize = 2;
+
+/// Retries used for playlists, keys and other one-off requests
+const REQUEST_RETRIES: usize = 3;
+
+/// Whether the error chain contains an HTTP 403, which usually means the
+/// kwik token in the playlist URL has expired
+pub fn is_forbidden(err: &anyhow::Error) -> bool {
+    err.chain().any(|e| {
+        e.downcast_ref::<reqwest::Error>()
+            .and_then(|re| re.status())
+            == Some(reqwest::StatusCode::FORBIDDEN)
+    })
+}
+
+fn record_stage(options: &DownloadOptions, stage: Stage, started: std::time::Instant) {
+    if let Some(ref metrics) = options.metrics {
+        metrics.record_since(stage, started);
+    }
+}
+
+/// Control signal for a single running download
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadControl {
+    Running,
+    /// Stop starting new segments but keep the work dir and scraped links
+    Paused,
+    Cancelled,
+}
+
+/// Block while this download is paused; errors once it's cancelled
+async fn wait_while_held(
+    control_rx: &mut Option<tokio::sync::watch::Receiver<DownloadControl>>,
+) -> Result<()> {
+    if let Some(rx) = control_rx {
+        loop {
+            match *rx.borrow_and_update() {
+                DownloadControl::Running => return Ok(()),
+                DownloadControl::Cancelled => return Err(anyhow!("Download cancelled by user")),
+                DownloadControl::Paused => {}
+            }
+            if rx.changed().await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Block until the pause signal clears (or its sender is gone)
+pub async fn wait_while_paused(pause_rx: &mut Option<tokio::sync::watch::Receiver<bool>>) {
+    if let Some(rx) = pause_rx {
+        while *rx.borrow_and_update() {
+            if rx.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
 pub fn set_ffmpeg_path(path: PathBuf) {
     let _ = FFMPEG_PATH.set(path);
 }
 
 pub async fn download_episode(
     anime_name: &str,
-    ep: u32,
+    ep: EpisodeNumber,
     m3u8: &str,
     threads: usize,
     cookie: &str,
     out_base: Option<&Path>,
     host: &str,
     progress: Option<(Arc<AtomicUsize>, Arc<AtomicUsize>)>, // (total, done)
-    cancel_rx: Option<tokio::sync::watch::Receiver<bool>>,
+    segments_done: Option<Arc<AtomicUsize>>,
+    cancel_rx: Option<tokio::sync::watch::Receiver<DownloadControl>>,
+    options: &DownloadOptions,
 ) -> Result<PathBuf> {
-    eprintln!(
+    log_debug!(
         "{} download_episode called: episode={}, threads={}",
         timestamp(),
         ep,
         threads
     );
-    eprintln!("{} Anime title received: {}", timestamp(), anime_name);
-    let base_folder = out_base
-        .map(|p| p.to_path_buf())
-        .unwrap_or_else(|| PathBuf::from("."));
-    eprintln!(
-        "{} Resolved base output directory: {}",
-        timestamp(),
-        base_folder.display()
-    );
-    let sanitized_name = sanitize(anime_name);
-    let out_dir = base_folder.join(&sanitized_name);
-    eprintln!(
+    log_debug!("{} Anime title received: {}", timestamp(), anime_name);
+    let (out_dir, stem) = output_location(out_base, anime_name, ep, host, options);
+    log_debug!(
         "{} Episode output directory: {}",
         timestamp(),
         out_dir.display()
     );
     fs::create_dir_all(&out_dir)?;
-    let out_file = out_dir.join(format!("{}.mp4", ep));
-    eprintln!(
+    let out_file = out_dir.join(format!("{}.{}", stem, options.output_format.extension()));
+    log_info!(
         "{} Target file for episode {}: {}",
         timestamp(),
         ep,
         out_file.display()
     );
 
-    if threads <= 1 {
-        eprintln!(
+    // Keeping HLS output needs the segments on disk, so it always takes the parallel path
+    if threads <= 1 && !options.keep_as_hls && !options.keep_segments {
+        log_info!(
             "{} Using single-threaded download with ffmpeg_hls",
             timestamp()
         );
-        ffmpeg_hls(m3u8, &out_file, cookie, host, progress.clone(), cancel_rx).await?;
+        if options.override_key_hex.is_some() {
+            log_warn!(
+                "{} WARNING: AES key override is ignored on the ffmpeg path; use threads > 1",
+                timestamp()
+            );
+        }
+        // A quick playlist fetch gives us chapters and a real progress total
+        // even when ffmpeg never prints a Duration line, and lets us point
+        // ffmpeg at the right variant of a master playlist
+        let prefetched = if options.chapters || progress.is_some() {
+            match download_bytes(m3u8, cookie, host).await {
+                Ok(bytes) => {
+                    let text = String::from_utf8_lossy(&bytes).to_string();
+                    match resolve_media_playlist(m3u8, text, cookie, host, options.resolution.as_deref())
+                        .await
+                    {
+                        Ok(resolved) => Some(resolved),
+                        Err(e) => {
+                            log_warn!("{} Could not resolve variant playlist: {}", timestamp(), e);
+                            None
+                        }
+                    }
+                }
+                Err(e) => {
+                    log_warn!("{} Could not prefetch playlist: {}", timestamp(), e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let (input_url, playlist_text) = match prefetched {
+            Some((url, text)) => (url, Some(text)),
+            None => (m3u8.to_string(), None),
+        };
+        let chapters_file = match (options.chapters, playlist_text.as_deref()) {
+            (true, Some(text)) => {
+                write_chapters_file(text, &out_dir.join(format!("{}_chapters.txt", stem)))?
+            }
+            _ => None,
+        };
+        let known_duration_ms = playlist_text.as_deref().and_then(playlist_duration_ms);
+        let hwaccel = hwaccel_args(options);
+        let mut result = ffmpeg_hls(
+            &input_url,
+            &out_file,
+            cookie,
+            host,
+            progress.clone(),
+            options.byte_progress.as_ref(),
+            cancel_rx.clone(),
+            chapters_file.as_deref(),
+            known_duration_ms,
+            options.allow_all_extensions,
+            options.ffmpeg_timeout_secs,
+            &hwaccel,
+            &output_args(anime_name, ep, options),
+        )
+        .await;
+        if matches!(&result, Err(e) if e.is::<HwAccelFailed>()) {
+            log_warn!("{} Hardware decoding failed, retrying in software", timestamp());
+            result = ffmpeg_hls(
+                &input_url,
+                &out_file,
+                cookie,
+                host,
+                progress.clone(),
+                options.byte_progress.as_ref(),
+                cancel_rx,
+                chapters_file.as_deref(),
+                known_duration_ms,
+                options.allow_all_extensions,
+                options.ffmpeg_timeout_secs,
+                &[],
+                &output_args(anime_name, ep, options),
+            )
+            .await;
+        }
+        if let Some(path) = chapters_file {
+            let _ = fs::remove_file(path);
+        }
+        result?;
+        verify_duration(&out_file, known_duration_ms).await?;
         return Ok(out_file);
     }
 
-    // Parallel path
-    let work = out_dir.join(format!("{}_work", ep));
-    if work.exists() {
-        fs::remove_dir_all(&work).ok();
-    }
+    // Parallel path. The work dir survives cancellation so a later run only
+    // fetches the segments that are still missing.
+    let work = work_dir_for(out_base, anime_name, ep, host, options);
     fs::create_dir_all(&work)?;
     let playlist_path = work.join("playlist.m3u8");
-    let _ = download_to_file(m3u8, &playlist_path, cookie, host).await?;
-
-    // Parse segments and key
-    let content = tokiofs::read_to_string(&playlist_path).await?;
-    let seg_urls: Vec<String> = content
-        .lines()
-        .filter(|l| l.starts_with("http"))
-        .map(|s| s.to_string())
-        .collect();
-    if seg_urls.is_empty() {
-        return Err(anyhow!("No segments in playlist"));
+    let previous_playlist = fs::read_to_string(&playlist_path).ok();
+    let (mut content, mut seg_urls) =
+        fetch_segment_list(m3u8, &playlist_path, cookie, host, options).await?;
+    if let Some(previous) = previous_playlist {
+        // Segments are matched by index, which only holds for the same cut
+        let previous_count = previous.lines().filter(|l| is_segment_line(l)).count();
+        if previous_count != seg_urls.len() {
+            log_info!(
+                "{} Playlist changed ({} -> {} segments), discarding earlier segments",
+                timestamp(),
+                previous_count,
+                seg_urls.len()
+            );
+            fs::remove_dir_all(&work)?;
+            fs::create_dir_all(&work)?;
+            tokiofs::write(&playlist_path, &content).await?;
+        }
     }
 
-    // Calculate total size by fetching content-length from segments
-    let total_bytes = if progress.is_some() {
-        get_total_segment_size(&seg_urls, cookie, host).await.unwrap_or(0)
+    // Estimate total size from the content-length of the first few segments
+    let total_bytes = if progress.is_some() || options.byte_progress.is_some() {
+        get_total_segment_size(&seg_urls, cookie, host, options.host_limiter.clone())
+            .await
+            .unwrap_or(0)
     } else {
         0
     };
@@ -109,7 +434,10 @@ pub async fn download_episode(
     if let Some((total, _done)) = &progress {
         total.store(total_bytes, Ordering::Relaxed);
     }
-    eprintln!(
+    if let Some(ref bytes) = options.byte_progress {
+        bytes.total.store(total_bytes, Ordering::Relaxed);
+    }
+    log_info!(
         "{} Downloaded playlist with {} segments (total size: {} bytes)",
         timestamp(),
         seg_urls.len(),
@@ -118,190 +446,525 @@ pub async fn download_episode(
 
     // Key
     let key_url = extract_key_uri(&content);
-    let key_hex = if let Some(url) = key_url {
-        let bytes = download_bytes(&url, cookie, host).await?;
+    let key_hex = if let Some(ref override_hex) = options.override_key_hex {
+        let bytes = hex::decode(override_hex.trim()).context("override key is not valid hex")?;
+        if bytes.len() != 16 {
+            return Err(anyhow!("override key must be 16 bytes, got {}", bytes.len()));
+        }
+        log_warn!(
+            "{} !!! AES KEY OVERRIDE ACTIVE: ignoring playlist key {:?} !!!",
+            timestamp(),
+            key_url
+        );
+        hex::encode(bytes)
+    } else if let Some(url) = key_url {
+        let cached = options.extraction_cache.as_ref().and_then(|c| c.key(&url));
+        let bytes = match cached {
+            Some(bytes) => {
+                log_debug!("{} Reusing cached key for {}", timestamp(), url);
+                bytes
+            }
+            None => {
+                let bytes = download_bytes(&url, cookie, host).await.map_err(|e| {
+                    if is_forbidden(&e) {
+                        if let Some(ref c) = options.extraction_cache {
+                            c.invalidate_key(&url);
+                        }
+                    }
+                    e
+                })?;
+                if let Some(ref c) = options.extraction_cache {
+                    c.store_key(&url, &bytes);
+                }
+                bytes
+            }
+        };
         hex::encode(bytes)
     } else {
         String::new()
     };
 
-    // Download segments
-    download_segments(
-        &seg_urls,
-        &work,
-        threads,
-        cookie,
-        host,
-        progress.as_ref().map(|p| p.1.clone()),
-        cancel_rx.clone(),
-    )
-    .await?;
-    eprintln!(
+    // Download segments. Signed segment links can expire part way through a
+    // long batch; refetch the playlist and carry on with what's missing.
+    let stage_start = std::time::Instant::now();
+    let mut refreshes = 0;
+    loop {
+        let result = download_segments(
+            &seg_urls,
+            &work,
+            threads,
+            cookie,
+            host,
+            progress.as_ref().map(|p| p.1.clone()),
+            segments_done.clone(),
+            options,
+            cancel_rx.clone(),
+        )
+        .await;
+        let err = match result {
+            Ok(()) => break,
+            Err(e) => e,
+        };
+        let refresh = match options.refresh_playlist {
+            Some(ref refresh) if is_forbidden(&err) && refreshes < MAX_PLAYLIST_REFRESHES => refresh,
+            _ => return Err(err),
+        };
+        refreshes += 1;
+        log_warn!(
+            "{} Segment links expired, refetching playlist ({}/{})",
+            timestamp(),
+            refreshes,
+            MAX_PLAYLIST_REFRESHES
+        );
+        let fresh_m3u8 = refresh().await.context("refetch expired playlist")?;
+        let (fresh_content, fresh_urls) =
+            fetch_segment_list(&fresh_m3u8, &playlist_path, cookie, host, options).await?;
+        if fresh_urls.len() != seg_urls.len() {
+            return Err(err.context("refetched playlist has a different segment count"));
+        }
+        content = fresh_content;
+        seg_urls = fresh_urls;
+        // The next pass counts the segments already on disk again
+        if let Some((_, done)) = &progress {
+            done.store(0, Ordering::Relaxed);
+        }
+        if let Some(ref count) = segments_done {
+            count.store(0, Ordering::Relaxed);
+        }
+        if let Some(ref bytes) = options.byte_progress {
+            bytes.downloaded.store(0, Ordering::Relaxed);
+        }
+    }
+    record_stage(options, Stage::SegmentDownload, stage_start);
+    log_info!(
         "{} Finished downloading segments to {}",
         timestamp(),
         work.display()
     );
     // Decrypt if key present
     if !key_hex.is_empty() {
-        eprintln!("{} Beginning segment decryption with OpenSSL", timestamp());
-        decrypt_segments(&work, &key_hex, threads).await?;
-        eprintln!("{} Segment decryption complete", timestamp());
+        log_info!("{} Beginning AES-128 segment decryption", timestamp());
+        let stage_start = std::time::Instant::now();
+        decrypt_segments(&work, &key_hex, options.decrypt_concurrency.max(1)).await?;
+        record_stage(options, Stage::Decrypt, stage_start);
+        log_info!("{} Segment decryption complete", timestamp());
     }
-    // Generate concat file list
-    let list_path = work.join("file.list");
-    let mut list_file = File::create(&list_path)?;
-    let mut seg_files: Vec<PathBuf> = fs::read_dir(&work)?
-        .filter_map(|e| e.ok())
-        .map(|e| e.path())
-        .filter(|p| {
-            p.extension().and_then(|s| s.to_str()) == Some("encrypted")
-                || p.extension().and_then(|s| s.to_str()) == Some("ts")
-        })
-        .collect();
-    seg_files.sort();
-    for p in &seg_files {
-        let mut final_path = p.clone();
-        if p.extension().and_then(|s| s.to_str()) == Some("encrypted") {
-            // decrypted file has same name without .encrypted
-            final_path.set_extension("");
+
+    if options.keep_segments {
+        if !key_hex.is_empty() {
+            keep_decrypted_segments(&work, seg_urls.len())?;
         }
-        // Escape single quotes in path for ffmpeg concat file list
-        let path_str = final_path.display().to_string().replace("'", "'\\''");
-        writeln!(list_file, "file '{}'", path_str)?;
+        log_info!("{} Keeping segments in {}", timestamp(), work.display());
+        return Ok(work);
     }
 
+    if options.keep_as_hls {
+        let hls_dir = out_dir.join(format!("{}_hls", stem));
+        let index = write_local_hls(&content, &work, &hls_dir, seg_urls.len(), !key_hex.is_empty())?;
+        if let Err(e) = fs::remove_dir_all(&work) {
+            log_warn!("cleanup failed: {e}");
+        }
+        return Ok(index);
+    }
+
+    let list_path = work.join("file.list");
+    write_concat_list(&list_path, &work, seg_urls.len(), !key_hex.is_empty())?;
+
+    let chapters_file = if options.chapters {
+        write_chapters_file(&content, &work.join("chapters.txt"))?
+    } else {
+        None
+    };
+
     // Concat
-    eprintln!(
+    log_info!(
         "{} Starting ffmpeg concat for {} segments",
         timestamp(),
-        seg_files.len()
+        seg_urls.len()
     );
-    ffmpeg_concat(&list_path, &out_file)?;
-    eprintln!("{} FFmpeg concat finished", timestamp());
+    let stage_start = std::time::Instant::now();
+    ffmpeg_concat(
+        &list_path,
+        &out_file,
+        chapters_file.as_deref(),
+        &hwaccel_args(options),
+        &output_args(anime_name, ep, options),
+        cancel_rx,
+    )
+    .await?;
+    record_stage(options, Stage::Mux, stage_start);
+    log_info!("{} FFmpeg concat finished", timestamp());
     log_output_file(&out_file);
+    let verified = verify_duration(&out_file, playlist_duration_ms(&content)).await;
 
-    // Cleanup
+    // Cleanup. A truncated result also clears the work dir, so a retry
+    // fetches fresh segments instead of reusing whatever caused it.
     if let Err(e) = fs::remove_dir_all(&work) {
-        eprintln!("cleanup failed: {e}");
+        log_warn!("cleanup failed: {e}");
     }
+    verified?;
     Ok(out_file)
 }
 
+/// Outputs shorter than this share of the playlist's length count as truncated
+const MIN_DURATION_RATIO: f64 = 0.9;
+
+/// Check with ffprobe that `out_file` runs about as long as the playlist
+/// says. Skipped with a warning when ffprobe or the expected length is
+/// unavailable, since neither means the file is bad.
+async fn verify_duration(out_file: &Path, expected_ms: Option<u64>) -> Result<()> {
+    let Some(expected_ms) = expected_ms else {
+        return Ok(());
+    };
+    let expected = expected_ms as f64 / 1000.0;
+    let actual = match crate::player::probe_media_info(&out_file.to_string_lossy()).await {
+        Ok(info) => info.duration_seconds,
+        Err(e) => {
+            log_warn!("{} Skipping duration check: {}", timestamp(), e);
+            return Ok(());
+        }
+    };
+    match actual {
+        Some(actual) if actual < expected * MIN_DURATION_RATIO => Err(anyhow!(
+            "Output is {:.0}s long but the playlist runs {:.0}s; the download looks truncated",
+            actual,
+            expected
+        )),
+        Some(actual) => {
+            log_info!(
+                "{} Verified duration {:.1}s (playlist {:.1}s)",
+                timestamp(),
+                actual,
+                expected
+            );
+            Ok(())
+        }
+        None => {
+            log_warn!("{} ffprobe reported no duration for {}", timestamp(), out_file.display());
+            Ok(())
+        }
+    }
+}
+
+/// Scratch directory the parallel path downloads segments into: next to
+/// the output as `{stem}_work`, or under the OS temp dir when `temp_work_key`
+/// is set
+pub fn work_dir_for(
+    out_base: Option<&Path>,
+    anime_name: &str,
+    ep: EpisodeNumber,
+    host: &str,
+    options: &DownloadOptions,
+) -> PathBuf {
+    if let Some(ref dir) = options.work_dir {
+        return dir.clone();
+    }
+    match options.temp_work_key.as_deref() {
+        Some(key) => std::env::temp_dir()
+            .join("animepahe-dl")
+            .join(sanitize(key)),
+        None => {
+            let (out_dir, stem) = output_location(out_base, anime_name, ep, host, options);
+            out_dir.join(format!("{}_work", stem))
+        }
+    }
+}
+
+/// Where the finished episode is written, extension included
+pub fn output_path(
+    out_base: Option<&Path>,
+    anime_name: &str,
+    ep: EpisodeNumber,
+    host: &str,
+    options: &DownloadOptions,
+) -> PathBuf {
+    let (out_dir, stem) = output_location(out_base, anime_name, ep, host, options);
+    out_dir.join(format!("{}.{}", stem, options.output_format.extension()))
+}
+
+/// Folder and extension-less file name the episode lands in
+fn output_location(
+    out_base: Option<&Path>,
+    anime_name: &str,
+    ep: EpisodeNumber,
+    host: &str,
+    options: &DownloadOptions,
+) -> (PathBuf, String) {
+    let template = if options.filename_template.trim().is_empty() {
+        DEFAULT_FILENAME_TEMPLATE
+    } else {
+        options.filename_template.as_str()
+    };
+    let site = reqwest::Url::parse(host)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.to_string()))
+        .unwrap_or_default();
+    let relative = expand_filename_template(
+        template,
+        &TemplateVars {
+            anime: anime_name,
+            episode: ep,
+            resolution: options.resolution.as_deref(),
+            audio: options.audio.as_deref(),
+            host: &site,
+        },
+    );
+
+    let base = out_base.unwrap_or_else(|| Path::new("."));
+    let full = base.join(&relative);
+    let out_dir = full
+        .parent()
+        .map(|p| p.to_path_buf())
+        .unwrap_or_else(|| base.to_path_buf());
+    let name = full
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| ep.to_string());
+    let stem = format!("{}{}", name, options.file_suffix.as_deref().unwrap_or(""));
+    (out_dir, stem)
+}
+
+/// Values for `filename_template` tokens
+pub struct TemplateVars<'a> {
+    pub anime: &'a str,
+    pub episode: EpisodeNumber,
+    pub resolution: Option<&'a str>,
+    pub audio: Option<&'a str>,
+    pub host: &'a str,
+}
+
+/// Expand `{anime}`, `{episode}` (or zero-padded `{episode:02}`),
+/// `{resolution}`, `{audio}` and `{host}` into a relative path without an
+/// extension. Each token value is sanitized on its own, so a `/` in a title
+/// stays part of the name; only slashes written in the template make folders.
+/// Padding applies to the whole part, so special 3.5 becomes `03.5`.
+/// Unknown tokens are left as written.
+pub fn expand_filename_template(template: &str, vars: &TemplateVars) -> PathBuf {
+    let token = Regex::new(r"\{(\w+)(?::0?(\d+))?\}").expect("valid regex");
+    let mut path = PathBuf::new();
+    for part in template.split(['/', '\\']) {
+        let expanded = token.replace_all(part, |caps: &regex::Captures| {
+            let width: usize = caps.get(2).and_then(|w| w.as_str().parse().ok()).unwrap_or(0);
+            let value = match &caps[1] {
+                "anime" => vars.anime.to_string(),
+                "episode" => format!(
+                    "{:0width$}{}",
+                    vars.episode.whole(),
+                    vars.episode.suffix(),
+                    width = width
+                ),
+                "resolution" => vars.resolution.unwrap_or_default().to_string(),
+                "audio" => vars.audio.unwrap_or_default().to_string(),
+                "host" => vars.host.to_string(),
+                _ => return caps[0].to_string(),
+            };
+            sanitize(value)
+        });
+        // Literal text can't escape the download folder either
+        let component = sanitize(expanded.trim());
+        if component.is_empty() || component.chars().all(|c| c == '.') {
+            continue;
+        }
+        path.push(component);
+    }
+    if path.as_os_str().is_empty() {
+        path.push(vars.episode.to_string());
+    }
+    path
+}
+
 async fn ffmpeg_hls(
     m3u8: &str,
     out_file: &Path,
     cookie: &str,
     host: &str,
     progress: Option<(Arc<AtomicUsize>, Arc<AtomicUsize>)>,
-    mut cancel_rx: Option<tokio::sync::watch::Receiver<bool>>,
+    byte_progress: Option<&ByteProgress>,
+    mut cancel_rx: Option<tokio::sync::watch::Receiver<DownloadControl>>,
+    chapters_file: Option<&Path>,
+    known_duration_ms: Option<u64>,
+    allow_all_extensions: bool,
+    stall_timeout_secs: u64,
+    input_args: &[String],
+    output_args: &[String],
 ) -> Result<()> {
-    eprintln!("{} ffmpeg_hls called with m3u8: {}", timestamp(), m3u8);
+    log_debug!("{} ffmpeg_hls called with m3u8: {}", timestamp(), m3u8);
     let ffmpeg = resolve_ffmpeg()?;
+    let allowed_extensions = if allow_all_extensions {
+        log_warn!("{} ffmpeg may open any file extension (compatibility mode)", timestamp());
+        "ALL".to_string()
+    } else {
+        HLS_ALLOWED_EXTENSIONS.join(",")
+    };
     let mut cmd = Command::new(ffmpeg);
-    cmd.arg("-headers")
+    cmd.args(input_args)
+        .arg("-user_agent")
+        .arg(crate::network::user_agent())
+        .arg("-headers")
         .arg(format!("Referer: {}\r\nCookie: {}", host, cookie))
         .arg("-allowed_extensions")
-        .arg("ALL")
+        .arg(allowed_extensions)
         .arg("-protocol_whitelist")
         .arg("file,http,https,tcp,tls,crypto")
         .arg("-i")
-        .arg(m3u8)
-        .arg("-c")
-        .arg("copy")
+        .arg(m3u8);
+    if let Some(meta) = chapters_file {
+        cmd.arg("-i")
+            .arg(meta)
+            .arg("-map")
+            .arg("0")
+            .arg("-map_chapters")
+            .arg("1");
+    }
+    cmd.args(output_args)
         .arg("-y")
         .arg(out_file)
         .stdout(Stdio::null())
         .stderr(Stdio::piped());
 
-    eprintln!("{} Spawning ffmpeg process", timestamp());
+    log_debug!("{} Spawning ffmpeg process", timestamp());
     let mut child = cmd.spawn().context("spawn ffmpeg")?;
 
+    // A zero total tells the UI progress is indeterminate until a duration is known
     if let Some((total, done)) = &progress {
-        total.store(1000, Ordering::Relaxed);
+        total.store(known_duration_ms.unwrap_or(0) as usize, Ordering::Relaxed);
         done.store(0, Ordering::Relaxed);
     }
+    if let Some(bytes) = byte_progress {
+        bytes.downloaded.store(0, Ordering::Relaxed);
+        bytes.total.store(0, Ordering::Relaxed);
+    }
 
-    eprintln!(
-        "{} Starting ffmpeg execution with 900-second timeout",
-        timestamp()
+    let stall_limit = (stall_timeout_secs > 0).then(|| Duration::from_secs(stall_timeout_secs));
+    log_info!(
+        "{} Starting ffmpeg execution (stall timeout: {})",
+        timestamp(),
+        match stall_limit {
+            Some(limit) => format!("{:?}", limit),
+            None => "off".to_string(),
+        }
     );
 
-    // Wrap ffmpeg execution in timeout to prevent hanging (increased from 300s to 900s)
-    let result = timeout(Duration::from_secs(900), async {
-        if let Some(stderr) = child.stderr.take() {
-            eprintln!("{} Begin reading ffmpeg stderr", timestamp());
-            let reader = BufReader::new(stderr);
-            let mut duration_ms: Option<usize> = None;
-            for raw_line in reader.lines() {
-                // Check for cancellation
-                if let Some(ref mut rx) = cancel_rx {
-                    if *rx.borrow() {
-                        eprintln!("{} Cancellation requested, killing ffmpeg", timestamp());
+    // ffmpeg ends its `time=` stats lines with `\r`, so split on that as well
+    // as newlines. Reading happens on its own thread so the loop below can
+    // notice cancellation and stalls while ffmpeg is quiet.
+    let stderr = child.stderr.take().context("ffmpeg stderr")?;
+    let (line_tx, mut line_rx) = tokio::sync::mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        for chunk in BufReader::new(stderr).split(b'\r') {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    let _ = line_tx.send(Err(e));
+                    return;
+                }
+            };
+            for line in String::from_utf8_lossy(&chunk).lines() {
+                if line_tx.send(Ok(line.to_string())).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    let mut duration_ms: Option<usize> = known_duration_ms.map(|ms| ms as usize);
+    let mut last_progress = std::time::Instant::now();
+    let mut hwaccel_failed = false;
+    loop {
+        let next = timeout(Duration::from_secs(1), line_rx.recv()).await;
+
+        // Check for cancellation
+        if let Some(ref mut rx) = cancel_rx {
+            if *rx.borrow() == DownloadControl::Cancelled {
+                log_info!("{} Cancellation requested, killing ffmpeg", timestamp());
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(anyhow!("Download cancelled by user"));
+            }
+        }
+
+        let line = match next {
+            Ok(Some(line)) => line.context("read ffmpeg stderr")?,
+            // stderr closed, so ffmpeg is exiting
+            Ok(None) => break,
+            Err(_) => {
+                // The window restarts on every progress line, so only a
+                // genuinely stuck ffmpeg gets here
+                if let Some(limit) = stall_limit {
+                    if last_progress.elapsed() >= limit {
+                        log_error!(
+                            "{} FFmpeg made no progress for {:?}, killing it",
+                            timestamp(),
+                            limit
+                        );
                         let _ = child.kill();
-                        return Err(anyhow!("Download cancelled by user"));
+                        let _ = child.wait();
+                        return Err(anyhow!(
+                            "FFmpeg made no progress for {} seconds",
+                            stall_timeout_secs
+                        ));
                     }
                 }
+                continue;
+            }
+        };
+        log_debug!("{} ffmpeg stderr: {}", timestamp(), line);
+        if !input_args.is_empty() && is_hwaccel_error(&line) {
+            hwaccel_failed = true;
+        }
 
-                let line = raw_line.context("read ffmpeg stderr")?;
-                eprintln!("{} ffmpeg stderr: {}", timestamp(), line);
-                if let Some((total, done)) = &progress {
-                    if duration_ms.is_none() {
-                        if let Some(idx) = line.find("Duration:") {
-                            let rest = line[idx + "Duration:".len()..].trim();
-                            if let Some(seg) = rest.split(',').next() {
-                                if let Some(ms) = parse_time_to_millis(seg.trim()) {
-                                    let ms_usize = ms as usize;
-                                    duration_ms = Some(ms_usize);
-                                    total.store(ms_usize, Ordering::Relaxed);
-                                }
-                            }
+        if duration_ms.is_none() {
+            if let Some(idx) = line.find("Duration:") {
+                let rest = line[idx + "Duration:".len()..].trim();
+                if let Some(seg) = rest.split(',').next() {
+                    if let Some(ms) = parse_time_to_millis(seg.trim()) {
+                        let ms_usize = ms as usize;
+                        duration_ms = Some(ms_usize);
+                        if let Some((total, _)) = &progress {
+                            total.store(ms_usize, Ordering::Relaxed);
                         }
                     }
+                }
+            }
+        }
 
-                    if let Some(idx) = line.find("time=") {
-                        let rest = &line[idx + "time=".len()..];
-                        if let Some(token) = rest.split_whitespace().next() {
-                            if let Some(ms) = parse_time_to_millis(token) {
-                                let ms_usize = ms as usize;
-                                done.store(ms_usize, Ordering::Relaxed);
-                                if let Some(total_ms) = duration_ms {
-                                    if ms_usize > total_ms {
-                                        total.store(ms_usize, Ordering::Relaxed);
-                                    }
-                                } else {
-                                    let current_total = total.load(Ordering::Relaxed);
-                                    if ms_usize > current_total {
-                                        total.store(ms_usize, Ordering::Relaxed);
-                                    }
-                                }
-                            }
-                        }
+        if let Some(idx) = line.find("time=") {
+            last_progress = std::time::Instant::now();
+            let rest = &line[idx + "time=".len()..];
+            let ms = rest.split_whitespace().next().and_then(parse_time_to_millis);
+            // Stream copy writes what it reads, so output size tracks bytes
+            // downloaded; extrapolate the total from the share of time covered
+            if let (Some(size), Some(bytes)) = (parse_ffmpeg_size(&line), byte_progress) {
+                bytes.downloaded.store(size, Ordering::Relaxed);
+                if let (Some(ms), Some(total_ms)) = (ms, duration_ms) {
+                    if ms > 0 {
+                        let estimate = (size as f64 * total_ms as f64 / ms as f64) as usize;
+                        bytes.total.store(estimate.max(size), Ordering::Relaxed);
+                    }
+                }
+            }
+            if let (Some(ms), Some((total, done))) = (ms, &progress) {
+                let ms_usize = ms as usize;
+                done.store(ms_usize, Ordering::Relaxed);
+                // Without a known duration leave total at zero rather
+                // than chasing `done` and showing a fake 100%
+                if let Some(total_ms) = duration_ms {
+                    if ms_usize > total_ms {
+                        total.store(ms_usize, Ordering::Relaxed);
                     }
                 }
             }
         }
+    }
 
-        let status = child.wait().context("run ffmpeg")?;
-        Ok::<_, anyhow::Error>(status)
-    })
-    .await;
-
-    let status = match result {
-        Ok(Ok(status)) => {
-            eprintln!("{} FFmpeg completed successfully", timestamp());
+    let status = match child.wait() {
+        Ok(status) => {
+            log_info!("{} FFmpeg exited with {}", timestamp(), status);
             status
         }
-        Ok(Err(e)) => {
-            eprintln!("{} FFmpeg failed: {}", timestamp(), e);
-            return Err(e);
-        }
-        Err(_) => {
-            eprintln!(
-                "{} FFmpeg execution timed out after 900 seconds",
-                timestamp()
-            );
-            let _ = child.kill();
-            return Err(anyhow!("FFmpeg execution timed out after 900 seconds"));
+        Err(e) => {
+            log_error!("{} FFmpeg failed: {}", timestamp(), e);
+            return Err(anyhow::Error::new(e).context("run ffmpeg"));
         }
     };
     if let Some((total, done)) = &progress {
@@ -312,13 +975,24 @@ async fn ffmpeg_hls(
             }
         }
     }
+    if let Some(bytes) = byte_progress {
+        if status.success() {
+            if let Ok(meta) = fs::metadata(out_file) {
+                bytes.downloaded.store(meta.len() as usize, Ordering::Relaxed);
+                bytes.total.store(meta.len() as usize, Ordering::Relaxed);
+            }
+        }
+    }
     if !status.success() {
+        if hwaccel_failed {
+            return Err(anyhow!(HwAccelFailed));
+        }
         return Err(anyhow!("ffmpeg failed"));
     }
 
     match std::fs::metadata(out_file) {
         Ok(meta) => {
-            eprintln!(
+            log_info!(
                 "{} Verified output file exists: {} ({} bytes)",
                 timestamp(),
                 out_file.display(),
@@ -326,7 +1000,7 @@ async fn ffmpeg_hls(
             );
         }
         Err(err) => {
-            eprintln!(
+            log_warn!(
                 "{} WARNING: output file missing after ffmpeg: {} ({})",
                 timestamp(),
                 out_file.display(),
@@ -338,6 +1012,38 @@ async fn ffmpeg_hls(
     Ok(())
 }
 
+/// Sum the `#EXTINF` durations of a media playlist
+fn playlist_duration_ms(content: &str) -> Option<u64> {
+    let total: f64 = content
+        .lines()
+        .filter_map(|l| l.trim().strip_prefix("#EXTINF:"))
+        .filter_map(|rest| rest.split(',').next()?.trim().parse::<f64>().ok())
+        .sum();
+    if total > 0.0 {
+        Some((total * 1000.0) as u64)
+    } else {
+        None
+    }
+}
+
+/// `size=` from an ffmpeg progress line, in bytes
+fn parse_ffmpeg_size(line: &str) -> Option<usize> {
+    let idx = line.find("size=")?;
+    let value = line[idx + "size=".len()..].split_whitespace().next()?;
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let number: f64 = value[..split].parse().ok()?;
+    let unit = match &value[split..] {
+        "" | "B" => 1.0,
+        "kB" | "KiB" => 1024.0,
+        "mB" | "MB" | "MiB" => 1024.0 * 1024.0,
+        "gB" | "GB" | "GiB" => 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    Some((number * unit) as usize)
+}
+
 fn parse_time_to_millis(input: &str) -> Option<u64> {
     let parts: Vec<&str> = input.split(':').collect();
     if parts.len() != 3 {
@@ -350,36 +1056,512 @@ fn parse_time_to_millis(input: &str) -> Option<u64> {
     Some(total_ms as u64)
 }
 
-fn ffmpeg_concat(list_path: &Path, out_file: &Path) -> Result<()> {
+/// Add the audio of `extra` to `video` as a second track, tagging both
+/// tracks with their language. `video` is replaced in place.
+pub fn mux_extra_audio(video: &Path, extra: &Path, video_lang: &str, extra_lang: &str) -> Result<()> {
     let ffmpeg = resolve_ffmpeg()?;
+    // Keep the real extension last so ffmpeg picks the same container
+    let ext = video.extension().and_then(|e| e.to_str()).unwrap_or("mp4");
+    let muxed = video.with_extension(format!("muxing.{}", ext));
     let status = Command::new(ffmpeg)
+        .arg("-i")
+        .arg(video)
+        .arg("-i")
+        .arg(extra)
+        .args(["-map", "0", "-map", "1:a", "-c", "copy"])
+        .arg("-metadata:s:a:0")
+        .arg(format!("language={}", video_lang))
+        .arg("-metadata:s:a:1")
+        .arg(format!("language={}", extra_lang))
+        .arg("-y")
+        .arg(&muxed)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .context("run ffmpeg audio mux")?;
+    if !status.success() {
+        let _ = fs::remove_file(&muxed);
+        return Err(anyhow!("ffmpeg audio mux failed"));
+    }
+    fs::rename(&muxed, video).context("replace video with muxed output")?;
+    log_info!(
+        "{} Muxed {} audio into {}",
+        timestamp(),
+        extra_lang,
+        video.display()
+    );
+    Ok(())
+}
+
+/// A subtitle file saved next to an episode
+#[derive(Debug, Clone)]
+pub struct SubtitleFile {
+    pub path: PathBuf,
+    pub language: Option<String>,
+}
+
+/// Soft subtitle renditions listed in `m3u8`, when it's a master playlist
+pub async fn playlist_subtitle_tracks(
+    m3u8: &str,
+    cookie: &str,
+    host: &str,
+) -> Result<Vec<crate::scrape::SubtitleTrack>> {
+    let content = String::from_utf8_lossy(&download_bytes(m3u8, cookie, host).await?).into_owned();
+    Ok(crate::scrape::playlist_subtitle_tracks(&content, m3u8))
+}
+
+/// Save `tracks` next to `video` as `<stem>.<lang>.vtt`. Tracks that fail
+/// are logged and skipped.
+pub async fn download_subtitle_tracks(
+    tracks: &[crate::scrape::SubtitleTrack],
+    video: &Path,
+    cookie: &str,
+    host: &str,
+) -> Vec<SubtitleFile> {
+    let stem = video
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "subtitles".into());
+    let mut saved: Vec<SubtitleFile> = Vec::new();
+    let mut labels: Vec<String> = Vec::new();
+    for (i, track) in tracks.iter().enumerate() {
+        let text = match fetch_subtitle_text(&track.url, cookie, host).await {
+            Ok(text) => text,
+            Err(e) => {
+                log_warn!("{} Subtitle track {} failed: {}", timestamp(), track.url, e);
+                continue;
+            }
+        };
+        let mut label = track
+            .language
+            .as_deref()
+            .or(track.name.as_deref())
+            .map(sanitize)
+            .unwrap_or_default();
+        // Two tracks in the same language (or both unlabelled) mustn't overwrite each other
+        if labels.contains(&label) || (label.is_empty() && tracks.len() > 1) {
+            label = format!("{}{}", label, i + 1);
+        }
+        labels.push(label.clone());
+        let name = if label.is_empty() {
+            format!("{}.vtt", stem)
+        } else {
+            format!("{}.{}.vtt", stem, label)
+        };
+        let path = video.with_file_name(name);
+        if let Err(e) = fs::write(&path, text) {
+            log_warn!("{} Failed to write {}: {}", timestamp(), path.display(), e);
+            continue;
+        }
+        saved.push(SubtitleFile {
+            path,
+            language: track.language.clone(),
+        });
+    }
+    saved
+}
+
+/// A subtitle URL is either the WebVTT file itself or a playlist of WebVTT
+/// segments, which are stitched together under the first one's header
+async fn fetch_subtitle_text(url: &str, cookie: &str, host: &str) -> Result<String> {
+    let body = String::from_utf8_lossy(&download_bytes(url, cookie, host).await?).into_owned();
+    if !body.trim_start().starts_with("#EXTM3U") {
+        return Ok(body);
+    }
+    let mut out = String::new();
+    for segment in absolutize_playlist(&body, url).lines().filter(|l| is_segment_line(l)) {
+        let text = String::from_utf8_lossy(&download_bytes(segment, cookie, host).await?)
+            .trim_start_matches('\u{feff}')
+            .replace("\r\n", "\n");
+        if out.is_empty() {
+            out = text;
+        } else if let Some((_, cues)) = text.split_once("\n\n") {
+            out = format!("{}\n\n{}", out.trim_end(), cues.trim_start());
+        }
+    }
+    if out.is_empty() {
+        return Err(anyhow!("No segments in subtitle playlist"));
+    }
+    Ok(out)
+}
+
+/// Mux `subs` into `video` as soft subtitle tracks, replaced in place.
+/// mp4 only carries mov_text; mkv gets SubRip, which players handle best.
+pub fn mux_subtitles(video: &Path, subs: &[SubtitleFile]) -> Result<()> {
+    let ffmpeg = resolve_ffmpeg()?;
+    let ext = video.extension().and_then(|e| e.to_str()).unwrap_or("mp4");
+    let muxed = video.with_extension(format!("subs.{}", ext));
+    let codec = if ext.eq_ignore_ascii_case("mkv") { "srt" } else { "mov_text" };
+    let mut cmd = Command::new(ffmpeg);
+    cmd.arg("-i").arg(video);
+    for sub in subs {
+        cmd.arg("-i").arg(&sub.path);
+    }
+    cmd.args(["-map", "0"]);
+    for i in 1..=subs.len() {
+        cmd.arg("-map").arg(i.to_string());
+    }
+    cmd.args(["-c", "copy", "-c:s", codec]);
+    for (i, sub) in subs.iter().enumerate() {
+        if let Some(ref lang) = sub.language {
+            cmd.arg(format!("-metadata:s:s:{}", i)).arg(format!("language={}", lang));
+        }
+    }
+    let status = cmd
+        .arg("-y")
+        .arg(&muxed)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .context("run ffmpeg subtitle mux")?;
+    if !status.success() {
+        let _ = fs::remove_file(&muxed);
+        return Err(anyhow!("ffmpeg subtitle mux failed"));
+    }
+    fs::rename(&muxed, video).context("replace video with muxed output")?;
+    log_info!(
+        "{} Muxed {} subtitle track(s) into {}",
+        timestamp(),
+        subs.len(),
+        video.display()
+    );
+    Ok(())
+}
+
+/// Embed `poster` as cover art in `video`, replaced in place: an
+/// `attached_pic` stream for mp4, an attachment tagged with its mimetype for mkv
+pub fn embed_cover_art(video: &Path, poster: &Path) -> Result<()> {
+    let ffmpeg = resolve_ffmpeg()?;
+    let ext = video.extension().and_then(|e| e.to_str()).unwrap_or("mp4");
+    let with_cover = video.with_extension(format!("cover.{}", ext));
+    let mut cmd = Command::new(ffmpeg);
+    cmd.arg("-i").arg(video);
+    if ext.eq_ignore_ascii_case("mkv") {
+        let mimetype = match poster.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("png") => "image/png",
+            Some(e) if e.eq_ignore_ascii_case("webp") => "image/webp",
+            _ => "image/jpeg",
+        };
+        let filename = format!(
+            "cover.{}",
+            poster.extension().and_then(|e| e.to_str()).unwrap_or("jpg")
+        );
+        cmd.args(["-map", "0", "-c", "copy"])
+            .arg("-attach")
+            .arg(poster)
+            .arg("-metadata:s:t")
+            .arg(format!("mimetype={}", mimetype))
+            .arg("-metadata:s:t")
+            .arg(format!("filename={}", filename));
+    } else {
+        cmd.arg("-i")
+            .arg(poster)
+            .args(["-map", "0", "-map", "1", "-c", "copy"])
+            .args(["-disposition:v:1", "attached_pic"]);
+    }
+    let status = cmd
+        .arg("-y")
+        .arg(&with_cover)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .context("run ffmpeg cover art")?;
+    if !status.success() {
+        let _ = fs::remove_file(&with_cover);
+        return Err(anyhow!("ffmpeg cover art embed failed"));
+    }
+    fs::rename(&with_cover, video).context("replace video with cover art output")?;
+    log_info!("{} Embedded cover art into {}", timestamp(), video.display());
+    Ok(())
+}
+
+/// `-c copy` unless transcoding is on, in which case video is re-encoded
+/// and audio is still copied
+fn codec_args(options: &DownloadOptions) -> Vec<String> {
+    let Some(ref transcode) = options.transcode else {
+        return vec!["-c".into(), "copy".into()];
+    };
+    let mut args: Vec<String> = vec![
+        "-c:v".into(),
+        transcode.codec.encoder().into(),
+        "-crf".into(),
+        transcode.crf.to_string(),
+        "-preset".into(),
+        transcode.preset.clone(),
+        "-c:a".into(),
+        "copy".into(),
+    ];
+    // QuickTime and Apple devices only play HEVC in mp4 when tagged hvc1
+    if transcode.codec == crate::settings::VideoCodec::H265
+        && options.output_format == crate::settings::OutputFormat::Mp4
+    {
+        args.extend(["-tag:v".into(), "hvc1".into()]);
+    }
+    args
+}
+
+/// Everything ffmpeg needs after its inputs: codecs plus title tags
+fn output_args(anime_name: &str, ep: EpisodeNumber, options: &DownloadOptions) -> Vec<String> {
+    let mut args = codec_args(options);
+    args.extend(metadata_args(anime_name, ep));
+    args
+}
+
+/// Tags players show instead of the file name. ffmpeg writes them as the
+/// title/show/episode atoms in mp4 and as the matching tags in mkv.
+fn metadata_args(anime_name: &str, ep: EpisodeNumber) -> Vec<String> {
+    vec![
+        "-metadata".into(),
+        format!("title={} - Episode {}", anime_name, ep),
+        "-metadata".into(),
+        format!("show={}", anime_name),
+        "-metadata".into(),
+        format!("episode_id={}", ep),
+    ]
+}
+
+/// The chosen accelerator failed to initialise or decode
+#[derive(Debug)]
+struct HwAccelFailed;
+
+impl std::fmt::Display for HwAccelFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ffmpeg hardware acceleration failed")
+    }
+}
+
+impl std::error::Error for HwAccelFailed {}
+
+/// `-hwaccel` input flags. Stream copy never decodes, so these only apply
+/// while transcoding, and only for accelerators this platform offers.
+fn hwaccel_args(options: &DownloadOptions) -> Vec<String> {
+    if options.transcode.is_none() || !crate::settings::HwAccel::supported().contains(&options.hwaccel) {
+        return Vec::new();
+    }
+    match options.hwaccel.ffmpeg_name() {
+        Some(name) => vec!["-hwaccel".into(), name.into()],
+        None => Vec::new(),
+    }
+}
+
+fn is_hwaccel_error(line: &str) -> bool {
+    let line = line.to_ascii_lowercase();
+    line.contains("hwaccel")
+        || line.contains("device creation failed")
+        || line.contains("no device available")
+        || line.contains("failed to initialise")
+        || line.contains("failed to initialize")
+}
+
+/// Concatenate the segment list, falling back to software decoding once if
+/// the hardware accelerator in `input_args` is what broke
+async fn ffmpeg_concat(
+    list_path: &Path,
+    out_file: &Path,
+    chapters_file: Option<&Path>,
+    input_args: &[String],
+    output_args: &[String],
+    mut cancel_rx: Option<tokio::sync::watch::Receiver<DownloadControl>>,
+) -> Result<()> {
+    let result = run_ffmpeg_concat(
+        list_path,
+        out_file,
+        chapters_file,
+        input_args,
+        output_args,
+        cancel_rx.as_mut(),
+    )
+    .await;
+    match result {
+        Err(e) if e.is::<HwAccelFailed>() => {
+            log_warn!("{} Hardware decoding failed, retrying in software", timestamp());
+            run_ffmpeg_concat(list_path, out_file, chapters_file, &[], output_args, cancel_rx.as_mut())
+                .await
+        }
+        result => result,
+    }
+}
+
+/// Resolves once `cancel_rx` is set to `Cancelled`; never without a receiver
+async fn cancelled(cancel_rx: Option<&mut tokio::sync::watch::Receiver<DownloadControl>>) {
+    let Some(rx) = cancel_rx else {
+        return std::future::pending().await;
+    };
+    while *rx.borrow_and_update() != DownloadControl::Cancelled {
+        if rx.changed().await.is_err() {
+            return std::future::pending().await;
+        }
+    }
+}
+
+/// Runs ffmpeg as an async child, so a long transcode neither holds a
+/// runtime worker nor outlives a cancel
+async fn run_ffmpeg_concat(
+    list_path: &Path,
+    out_file: &Path,
+    chapters_file: Option<&Path>,
+    input_args: &[String],
+    output_args: &[String],
+    cancel_rx: Option<&mut tokio::sync::watch::Receiver<DownloadControl>>,
+) -> Result<()> {
+    let ffmpeg = resolve_ffmpeg()?;
+    let mut cmd = tokio::process::Command::new(ffmpeg);
+    cmd.args(input_args)
         .arg("-f")
         .arg("concat")
         .arg("-safe")
         .arg("0")
         .arg("-i")
-        .arg(list_path)
-        .arg("-c")
-        .arg("copy")
+        .arg(list_path);
+    if let Some(meta) = chapters_file {
+        cmd.arg("-i")
+            .arg(meta)
+            .arg("-map")
+            .arg("0")
+            .arg("-map_chapters")
+            .arg("1");
+    }
+    let run = cmd
+        .args(output_args)
         .arg("-y")
         .arg(out_file)
-        .status()
-        .context("run ffmpeg concat")?;
-    if !status.success() {
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        // Dropping `run` on cancel kills ffmpeg
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::select! {
+        output = run => output.context("run ffmpeg concat")?,
+        _ = cancelled(cancel_rx) => {
+            log_info!("{} Cancellation requested, killing ffmpeg", timestamp());
+            return Err(anyhow!("Download cancelled by user"));
+        }
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log_error!("{} ffmpeg concat failed: {}", timestamp(), stderr.trim());
+        if !input_args.is_empty() && stderr.lines().any(is_hwaccel_error) {
+            return Err(anyhow!(HwAccelFailed));
+        }
         return Err(anyhow!("ffmpeg concat failed"));
     }
     Ok(())
 }
 
+#[derive(Debug, Clone)]
+struct Chapter {
+    start_ms: u64,
+    end_ms: u64,
+    title: String,
+}
+
+/// Build chapters from `#EXT-X-DATERANGE` markers, positioned by the running
+/// `#EXTINF` total. Playlists without markers fall back to one chapter per
+/// `#EXT-X-DISCONTINUITY` block, which is where OP/ED splices usually sit.
+fn parse_chapters(content: &str) -> Vec<Chapter> {
+    let mut elapsed_ms = 0u64;
+    let mut ranges: Vec<(u64, Option<u64>, String)> = Vec::new();
+    let mut boundaries: Vec<u64> = vec![0];
+
+    for line in content.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("#EXTINF:") {
+            let secs: f64 = rest.split(',').next().and_then(|d| d.trim().parse().ok()).unwrap_or(0.0);
+            elapsed_ms += (secs * 1000.0) as u64;
+        } else if let Some(attrs) = line.strip_prefix("#EXT-X-DATERANGE:") {
+            let attr = |name: &str| -> Option<String> {
+                let re = Regex::new(&format!(r#"(?:^|,){}=("[^"]*"|[^,]*)"#, name)).ok()?;
+                re.captures(attrs)
+                    .and_then(|c| c.get(1))
+                    .map(|m| m.as_str().trim_matches('"').to_string())
+            };
+            let duration_ms = attr("DURATION")
+                .or_else(|| attr("PLANNED-DURATION"))
+                .and_then(|d| d.parse::<f64>().ok())
+                .map(|d| (d * 1000.0) as u64);
+            let title = attr("X-TITLE")
+                .or_else(|| attr("CLASS"))
+                .or_else(|| attr("ID"))
+                .unwrap_or_else(|| format!("Chapter {}", ranges.len() + 1));
+            ranges.push((elapsed_ms, duration_ms.map(|d| elapsed_ms + d), title));
+        } else if line == "#EXT-X-DISCONTINUITY" && elapsed_ms > 0 {
+            boundaries.push(elapsed_ms);
+        }
+    }
+
+    let total_ms = elapsed_ms;
+    if !ranges.is_empty() {
+        let starts: Vec<u64> = ranges.iter().map(|r| r.0).collect();
+        return ranges
+            .into_iter()
+            .enumerate()
+            .map(|(i, (start_ms, end_ms, title))| Chapter {
+                start_ms,
+                end_ms: end_ms
+                    .or_else(|| starts.get(i + 1).copied())
+                    .unwrap_or(total_ms)
+                    .max(start_ms),
+                title,
+            })
+            .collect();
+    }
+
+    if boundaries.len() < 2 {
+        return Vec::new();
+    }
+    boundaries.push(total_ms);
+    boundaries
+        .windows(2)
+        .enumerate()
+        .filter(|(_, w)| w[1] > w[0])
+        .map(|(i, w)| Chapter {
+            start_ms: w[0],
+            end_ms: w[1],
+            title: format!("Part {}", i + 1),
+        })
+        .collect()
+}
+
+/// Write an ffmetadata chapter file for the playlist, or return `None` when
+/// the playlist carries no chapter information.
+fn write_chapters_file(playlist: &str, path: &Path) -> Result<Option<PathBuf>> {
+    let chapters = parse_chapters(playlist);
+    if chapters.is_empty() {
+        log_info!("{} No chapter markers found in playlist", timestamp());
+        return Ok(None);
+    }
+
+    let escape = |s: &str| {
+        s.chars()
+            .flat_map(|c| match c {
+                '=' | ';' | '#' | '\\' | '\n' => vec!['\\', c],
+                _ => vec![c],
+            })
+            .collect::<String>()
+    };
+
+    let mut file = File::create(path).context("create chapters file")?;
+    writeln!(file, ";FFMETADATA1")?;
+    for chapter in &chapters {
+        writeln!(file, "[CHAPTER]")?;
+        writeln!(file, "TIMEBASE=1/1000")?;
+        writeln!(file, "START={}", chapter.start_ms)?;
+        writeln!(file, "END={}", chapter.end_ms)?;
+        writeln!(file, "title={}", escape(&chapter.title))?;
+    }
+    log_info!("{} Wrote {} chapter(s) to {}", timestamp(), chapters.len(), path.display());
+    Ok(Some(path.to_path_buf()))
+}
+
 fn log_output_file(out_file: &Path) {
     match fs::metadata(out_file) {
-        Ok(meta) => eprintln!(
+        Ok(meta) => log_info!(
             "{} Verified output file exists: {} ({} bytes)",
             timestamp(),
             out_file.display(),
             meta.len()
         ),
-        Err(err) => eprintln!(
+        Err(err) => log_warn!(
             "{} WARNING: output file missing after processing: {} ({})",
             timestamp(),
             out_file.display(),
@@ -395,9 +1577,25 @@ fn resolve_ffmpeg() -> Result<PathBuf> {
     which::which("ffmpeg").map_err(|_| anyhow!("ffmpeg not found"))
 }
 
-async fn download_with_retry<F, T>(mut operation: F, max_retries: usize) -> Result<T>
+/// Locate ffprobe, preferring the one shipped next to the resolved ffmpeg
+pub fn resolve_ffprobe() -> Result<PathBuf> {
+    if let Some(dir) = FFMPEG_PATH.get().and_then(|p| p.parent()) {
+        let name = if cfg!(target_os = "windows") { "ffprobe.exe" } else { "ffprobe" };
+        let sibling = dir.join(name);
+        if sibling.exists() {
+            return Ok(sibling);
+        }
+    }
+    which::which("ffprobe").map_err(|_| anyhow!("ffprobe not found"))
+}
+
+/// Run `operation` up to `max_retries + 1` times with exponential backoff
+/// (500ms, 1s, 2s, ... capped at 30s). Errors that asking again can't fix,
+/// like a 404, fail immediately. `on_retry` gets the upcoming attempt number.
+async fn download_with_retry<F, T, R>(mut operation: F, max_retries: usize, on_retry: R) -> Result<T>
 where
     F: FnMut() -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<T>> + Send>>,
+    R: Fn(usize),
 {
     let mut last_error = None;
     
@@ -405,11 +1603,16 @@ where
         match operation().await {
             Ok(result) => return Ok(result),
             Err(e) => {
+                if is_permanent(&e) {
+                    return Err(e);
+                }
                 last_error = Some(e);
                 if attempt < max_retries {
-                    let delay = Duration::from_millis(1000 * (2_u64.pow(attempt as u32))); // Exponential backoff
-                    eprintln!("{} Download attempt {} failed, retrying in {:?}: {}", 
+                    let delay = Duration::from_millis(500 * 2_u64.pow(attempt.min(6) as u32))
+                        .min(Duration::from_secs(30)); // Exponential backoff
+                    log_warn!("{} Download attempt {} failed, retrying in {:?}: {}", 
                         timestamp(), attempt + 1, delay, last_error.as_ref().unwrap());
+                    on_retry(attempt + 2);
                     sleep(delay).await;
                 }
             }
@@ -419,20 +1622,43 @@ where
     Err(last_error.unwrap())
 }
 
-async fn download_to_file(url: &str, path: &Path, cookie: &str, host: &str) -> Result<usize> {
+/// HTTP statuses where retrying just repeats the same answer
+fn is_permanent(err: &anyhow::Error) -> bool {
+    err.chain().any(|e| {
+        matches!(
+            e.downcast_ref::<reqwest::Error>().and_then(|re| re.status()),
+            Some(
+                reqwest::StatusCode::NOT_FOUND
+                    | reqwest::StatusCode::GONE
+                    | reqwest::StatusCode::UNAUTHORIZED
+                    | reqwest::StatusCode::FORBIDDEN
+            )
+        )
+    })
+}
+
+async fn download_to_file(
+    url: &str,
+    path: &Path,
+    cookie: &str,
+    host: &str,
+    bandwidth: Option<&BandwidthLimiter>,
+) -> Result<usize> {
     let url = url.to_string();
     let path = path.to_path_buf();
     let cookie = cookie.to_string();
     let host = host.to_string();
+    let bandwidth = bandwidth.cloned();
     
     download_with_retry(|| {
         let url = url.clone();
         let path = path.clone();
         let cookie = cookie.clone();
         let host = host.clone();
+        let bandwidth = bandwidth.clone();
         
         Box::pin(async move {
-            let client = create_client();
+            let client = http_client();
             let resp = client
                 .get(&url)
                 .header(reqwest::header::REFERER, &host)
@@ -442,27 +1668,40 @@ async fn download_to_file(url: &str, path: &Path, cookie: &str, host: &str) -> R
                 .error_for_status()?;
             let content = resp.bytes().await?;
             let bytes_downloaded = content.len();
+            if let Some(ref limiter) = bandwidth {
+                limiter.consume(bytes_downloaded).await;
+            }
             tokiofs::write(&path, content).await?;
             Ok(bytes_downloaded)
         })
-    }, 3).await
+    }, REQUEST_RETRIES, |_| {}).await
 }
 
-async fn get_total_segment_size(seg_urls: &[String], cookie: &str, host: &str) -> Result<usize> {
+async fn get_total_segment_size(
+    seg_urls: &[String],
+    cookie: &str,
+    host: &str,
+    host_limiter: Option<HostLimiter>,
+) -> Result<usize> {
     let mut total = 0usize;
     let mut successful = 0usize;
 
-    // Fetch content-length for all segments in parallel
-    let client = create_client();
+    // Segments of a rendition are close in size, so a handful of HEADs is enough
+    let client = http_client();
     let mut handles = vec![];
 
-    for url in seg_urls.iter() {
+    for url in seg_urls.iter().take(SIZE_SAMPLE_SEGMENTS) {
         let client = client.clone();
         let url = url.clone();
         let cookie = cookie.to_string();
         let host = host.to_string();
+        let host_limiter = host_limiter.clone();
 
         let handle = tokio::spawn(async move {
+            let _host_permit = match host_limiter {
+                Some(ref limiter) => limiter.acquire(&url).await.ok(),
+                None => None,
+            };
             let resp = client
                 .head(&url)
                 .header(reqwest::header::REFERER, &host)
@@ -493,14 +1732,38 @@ async fn get_total_segment_size(seg_urls: &[String], cookie: &str, host: &str) -
         }
     }
 
-    eprintln!(
-        "{} Calculated total size from {} segments: {} bytes",
+    if successful == 0 {
+        return Ok(0);
+    }
+    let estimate = total / successful * seg_urls.len();
+    log_info!(
+        "{} Estimated total size from {} of {} segments: {} bytes",
         timestamp(),
         successful,
-        total
+        seg_urls.len(),
+        estimate
     );
 
-    Ok(total)
+    Ok(estimate)
+}
+
+/// Approximate size of the stream behind `m3u8`, from HEADs on a sample of
+/// its segments; nothing is downloaded. `None` when the CDN sends no lengths.
+pub async fn estimate_stream_size(
+    m3u8: &str,
+    cookie: &str,
+    host: &str,
+    resolution: Option<&str>,
+) -> Result<Option<u64>> {
+    let playlist = String::from_utf8_lossy(&download_bytes(m3u8, cookie, host).await?).into_owned();
+    let (media_url, media) = resolve_media_playlist(m3u8, playlist, cookie, host, resolution).await?;
+    let seg_urls: Vec<String> = absolutize_playlist(&media, &media_url)
+        .lines()
+        .filter(|l| is_segment_line(l))
+        .map(|s| s.to_string())
+        .collect();
+    let total = get_total_segment_size(&seg_urls, cookie, host, None).await?;
+    Ok((total > 0).then_some(total as u64))
 }
 
 async fn download_bytes(url: &str, cookie: &str, host: &str) -> Result<Vec<u8>> {
@@ -514,7 +1777,7 @@ async fn download_bytes(url: &str, cookie: &str, host: &str) -> Result<Vec<u8>>
         let host = host.clone();
         
         Box::pin(async move {
-            let client = create_client();
+            let client = http_client();
             let resp = client
                 .get(&url)
                 .header(reqwest::header::REFERER, &host)
@@ -524,7 +1787,39 @@ async fn download_bytes(url: &str, cookie: &str, host: &str) -> Result<Vec<u8>>
                 .error_for_status()?;
             Ok(resp.bytes().await?.to_vec())
         })
-    }, 3).await
+    }, REQUEST_RETRIES, |_| {}).await
+}
+
+/// Fetch `m3u8` into `playlist_path`, following a master playlist, with
+/// relative URIs resolved so everything downstream can treat them as
+/// absolute. Returns the media playlist and its segment URLs.
+async fn fetch_segment_list(
+    m3u8: &str,
+    playlist_path: &Path,
+    cookie: &str,
+    host: &str,
+    options: &DownloadOptions,
+) -> Result<(String, Vec<String>)> {
+    download_to_file(m3u8, playlist_path, cookie, host, options.bandwidth.as_ref()).await?;
+    let (media_url, media) = resolve_media_playlist(
+        m3u8,
+        tokiofs::read_to_string(playlist_path).await?,
+        cookie,
+        host,
+        options.resolution.as_deref(),
+    )
+    .await?;
+    let content = absolutize_playlist(&media, &media_url);
+    tokiofs::write(playlist_path, &content).await?;
+    let seg_urls: Vec<String> = content
+        .lines()
+        .filter(|l| is_segment_line(l))
+        .map(|s| s.to_string())
+        .collect();
+    if seg_urls.is_empty() {
+        return Err(anyhow!("No segments in playlist"));
+    }
+    Ok((content, seg_urls))
 }
 
 async fn download_segments(
@@ -534,64 +1829,148 @@ async fn download_segments(
     cookie: &str,
     host: &str,
     progress_done: Option<Arc<AtomicUsize>>,
-    mut cancel_rx: Option<tokio::sync::watch::Receiver<bool>>,
+    segments_done: Option<Arc<AtomicUsize>>,
+    options: &DownloadOptions,
+    mut cancel_rx: Option<tokio::sync::watch::Receiver<DownloadControl>>,
 ) -> Result<()> {
     // Use higher concurrency for segment downloads
     let semaphore = Arc::new(tokio::sync::Semaphore::new(threads * 2));
     let mut handles = FuturesUnordered::new();
+    let mut reused = 0usize;
 
     for (i, url) in seg_urls.iter().enumerate() {
+        if let Some(len) = reuse_segment(&segment_path(work_dir, i)) {
+            if let Some(ref done) = progress_done {
+                done.fetch_add(len, Ordering::Relaxed);
+            }
+            if let Some(ref bytes) = options.byte_progress {
+                bytes.downloaded.fetch_add(len, Ordering::Relaxed);
+            }
+            if let Some(ref count) = segments_done {
+                count.fetch_add(1, Ordering::Relaxed);
+            }
+            reused += 1;
+            continue;
+        }
+
         let sem = semaphore.clone();
         let url = url.clone();
         let cookie = cookie.to_string();
         let host = host.to_string();
         let work_dir = work_dir.to_path_buf();
         let progress_done = progress_done.clone();
+        let segments_done = segments_done.clone();
+        let byte_progress = options.byte_progress.clone();
+        let host_limiter = options.host_limiter.clone();
+        let mut pause_rx = options.pause_rx.clone();
+        let mut control_rx = cancel_rx.clone();
+        let on_segment_retry = options.on_segment_retry.clone();
+        let fetch = SegmentFetch {
+            index: i,
+            verify_length: options.verify_segment_length,
+            retries: options.segment_retries,
+            deadline: (options.segment_timeout_secs > 0)
+                .then(|| Duration::from_secs(options.segment_timeout_secs)),
+            bandwidth: options.bandwidth.clone(),
+        };
 
         let handle = tokio::spawn(async move {
             let _permit = sem.acquire().await?;
-            let seg_path = work_dir.join(format!("seg_{:06}.ts", i));
+            wait_while_paused(&mut pause_rx).await;
+            wait_while_held(&mut control_rx).await?;
+            let _host_permit = match host_limiter {
+                Some(ref limiter) => Some(limiter.acquire(&url).await?),
+                None => None,
+            };
+            let seg_path = segment_path(&work_dir, i);
             
             // Use streaming download for better performance
-            let bytes_downloaded = download_segment_streaming(&url, &seg_path, &cookie, &host).await?;
+            let on_retry = move |attempt: usize| {
+                if let Some(ref notify) = on_segment_retry {
+                    notify(i + 1, attempt);
+                }
+            };
+            let bytes_downloaded =
+                download_segment_streaming(&url, &seg_path, &cookie, &host, &fetch, on_retry)
+                    .await
+                    .with_context(|| format!("segment {} of {}", i + 1, url))?;
             if let Some(done) = progress_done {
                 done.fetch_add(bytes_downloaded, Ordering::Relaxed);
             }
+            if let Some(bytes) = byte_progress {
+                bytes.downloaded.fetch_add(bytes_downloaded, Ordering::Relaxed);
+            }
+            if let Some(count) = segments_done {
+                count.fetch_add(1, Ordering::Relaxed);
+            }
             Ok::<(), anyhow::Error>(())
         });
 
         handles.push(handle);
     }
+    if reused > 0 {
+        log_info!(
+            "{} Reusing {} of {} segments from an earlier attempt",
+            timestamp(),
+            reused,
+            seg_urls.len()
+        );
+    }
 
     while let Some(result) = handles.next().await {
         // Check for cancellation
         if let Some(ref mut rx) = cancel_rx {
-            if *rx.borrow() {
-                eprintln!("{} Cancellation requested during segment download", timestamp());
+            if *rx.borrow() == DownloadControl::Cancelled {
+                log_info!("{} Cancellation requested during segment download", timestamp());
                 return Err(anyhow!("Download cancelled by user"));
             }
         }
 
-        result??;
+        // Stop the rest so a retry never races them for the same files
+        if let Err(e) = result.map_err(anyhow::Error::from).and_then(|r| r) {
+            for handle in handles.iter() {
+                handle.abort();
+            }
+            return Err(e);
+        }
     }
 
     Ok(())
 }
 
-async fn download_segment_streaming(url: &str, path: &Path, cookie: &str, host: &str) -> Result<usize> {
+/// What one segment fetch takes from `DownloadOptions`
+struct SegmentFetch {
+    index: usize,
+    verify_length: bool,
+    retries: usize,
+    /// Limit on a single attempt, on top of the client's connect/read timeout
+    deadline: Option<Duration>,
+    bandwidth: Option<BandwidthLimiter>,
+}
+
+async fn download_segment_streaming(
+    url: &str,
+    path: &Path,
+    cookie: &str,
+    host: &str,
+    fetch: &SegmentFetch,
+    on_retry: impl Fn(usize) + Send,
+) -> Result<usize> {
     let url = url.to_string();
     let path = path.to_path_buf();
     let cookie = cookie.to_string();
     let host = host.to_string();
+    let verify_length = fetch.verify_length;
     
     download_with_retry(|| {
         let url = url.clone();
         let path = path.clone();
         let cookie = cookie.clone();
         let host = host.clone();
+        let bandwidth = fetch.bandwidth.clone();
         
-        Box::pin(async move {
-            let client = create_client();
+        let attempt = async move {
+            let client = http_client();
             let mut resp = client
                 .get(&url)
                 .header(reqwest::header::REFERER, &host)
@@ -600,18 +1979,301 @@ async fn download_segment_streaming(url: &str, path: &Path, cookie: &str, host:
                 .await?
                 .error_for_status()?;
             
-            let mut file = tokiofs::File::create(&path).await?;
+            // Absent for chunked responses, in which case there's nothing to check against
+            let expected_len = resp.content_length();
+            // Write under a temporary name so a cancelled download never
+            // leaves a truncated segment that looks finished
+            let part_path = path.with_extension("part");
+            let mut file = tokiofs::File::create(&part_path).await?;
             let mut bytes_downloaded = 0usize;
             
             // Stream the response directly to file for better memory usage
             while let Some(chunk) = resp.chunk().await? {
+                if let Some(ref limiter) = bandwidth {
+                    limiter.consume(chunk.len()).await;
+                }
                 bytes_downloaded += chunk.len();
                 tokio::io::AsyncWriteExt::write_all(&mut file, &chunk).await?;
             }
+            tokio::io::AsyncWriteExt::flush(&mut file).await?;
+
+            if verify_length {
+                check_segment_length(file.metadata().await?.len(), expected_len, &url)?;
+            }
+            drop(file);
+            tokiofs::rename(&part_path, &path).await?;
             
             Ok(bytes_downloaded)
-        })
-    }, 3).await
+        };
+        Box::pin(with_deadline(attempt, fetch.deadline, fetch.index))
+    }, fetch.retries, on_retry).await
+}
+
+/// A segment whose size on disk differs from its `Content-Length` is an
+/// error, which sends it back through download_with_retry. Without the
+/// header there is nothing to compare against.
+fn check_segment_length(on_disk: u64, expected: Option<u64>, url: &str) -> Result<()> {
+    match expected {
+        Some(expected) if on_disk != expected => Err(anyhow!(
+            "Segment truncated: got {} of {} bytes from {}",
+            on_disk,
+            expected,
+            url
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Bound one segment attempt. A stalled connection that keeps trickling
+/// bytes never trips the client timeout; this turns it into an ordinary
+/// error so the retry loop fetches the segment again.
+async fn with_deadline<T>(
+    attempt: impl std::future::Future<Output = Result<T>>,
+    deadline: Option<Duration>,
+    index: usize,
+) -> Result<T> {
+    match deadline {
+        Some(limit) => timeout(limit, attempt)
+            .await
+            .map_err(|_| anyhow!("Segment {} timed out after {:?}", index + 1, limit))?,
+        None => attempt.await,
+    }
+}
+
+/// Follow a master playlist down to the media playlist for `resolution`.
+/// Returns the media playlist's URL and text; a media playlist comes
+/// straight back.
+async fn resolve_media_playlist(
+    url: &str,
+    content: String,
+    cookie: &str,
+    host: &str,
+    resolution: Option<&str>,
+) -> Result<(String, String)> {
+    let mut url = url.to_string();
+    let mut content = content;
+    // Masters shouldn't nest, but don't follow a misbehaving CDN forever
+    for _ in 0..3 {
+        let Some(variant) = pick_variant(&content, &url, resolution) else {
+            break;
+        };
+        log_info!("{} Master playlist, using variant {}", timestamp(), variant);
+        content = String::from_utf8_lossy(&download_bytes(&variant, cookie, host).await?).to_string();
+        url = variant;
+    }
+    Ok((url, content))
+}
+
+/// Pick the `#EXT-X-STREAM-INF` variant whose `RESOLUTION` height matches
+/// `resolution` (e.g. "720"), falling back to the tallest. `None` when
+/// `content` is a media playlist.
+fn pick_variant(content: &str, base_url: &str, resolution: Option<&str>) -> Option<String> {
+    let height_re = Regex::new(r"RESOLUTION=\d+x(\d+)").expect("valid regex");
+    let mut variants: Vec<(u32, String)> = Vec::new();
+    let mut lines = content.lines();
+    while let Some(line) = lines.next() {
+        if let Some(attrs) = line.trim().strip_prefix("#EXT-X-STREAM-INF:") {
+            let height = height_re
+                .captures(attrs)
+                .and_then(|c| c[1].parse().ok())
+                .unwrap_or(0);
+            // The variant's URI is the next line that isn't a tag
+            if let Some(uri) = lines.by_ref().find(|l| is_segment_line(l)) {
+                variants.push((height, uri.trim().to_string()));
+            }
+        }
+    }
+
+    let wanted: Option<u32> = resolution.and_then(|r| r.trim().trim_end_matches('p').parse().ok());
+    let (_, uri) = wanted
+        .and_then(|h| variants.iter().find(|(height, _)| *height == h))
+        .or_else(|| variants.iter().max_by_key(|(height, _)| *height))?;
+    Some(
+        reqwest::Url::parse(base_url)
+            .and_then(|base| base.join(uri))
+            .map(|u| u.to_string())
+            .unwrap_or_else(|_| uri.clone()),
+    )
+}
+
+/// Any non-blank line that isn't a tag or comment is a segment URI
+fn is_segment_line(line: &str) -> bool {
+    let line = line.trim();
+    !line.is_empty() && !line.starts_with('#')
+}
+
+/// Rewrite relative segment and `#EXT-X-KEY` URIs against the playlist URL.
+/// Absolute URIs come through unchanged.
+fn absolutize_playlist(content: &str, playlist_url: &str) -> String {
+    let Ok(base) = reqwest::Url::parse(playlist_url) else {
+        return content.to_string();
+    };
+    let resolve = |uri: &str| {
+        base.join(uri)
+            .map(|u| u.to_string())
+            .unwrap_or_else(|_| uri.to_string())
+    };
+    let key_uri = Regex::new(r#"URI="([^"]+)""#).expect("valid regex");
+
+    let mut out = String::with_capacity(content.len());
+    for line in content.lines() {
+        if line.starts_with("#EXT-X-KEY") {
+            out.push_str(&key_uri.replace(line, |caps: &regex::Captures| {
+                format!("URI=\"{}\"", resolve(&caps[1]))
+            }));
+        } else if is_segment_line(line) {
+            out.push_str(&resolve(line.trim()));
+        } else {
+            out.push_str(line);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Move the downloaded segments into `hls_dir` and write an `index.m3u8`
+/// that points at them, so players that handle HLS can open it directly.
+/// Key tags are dropped when the segments were already decrypted.
+fn write_local_hls(
+    playlist: &str,
+    work: &Path,
+    hls_dir: &Path,
+    segment_count: usize,
+    decrypted: bool,
+) -> Result<PathBuf> {
+    if hls_dir.exists() {
+        fs::remove_dir_all(hls_dir).ok();
+    }
+    fs::create_dir_all(hls_dir)?;
+
+    for i in 0..segment_count {
+        let raw = segment_path(work, i);
+        let src = if decrypted { raw.with_extension("") } else { raw };
+        let dst = hls_dir.join(format!("seg_{:06}.ts", i));
+        move_file(&src, &dst)
+            .with_context(|| format!("move segment {} into HLS folder", src.display()))?;
+    }
+
+    let mut index = 0usize;
+    let mut out = String::new();
+    for line in playlist.lines() {
+        if decrypted && line.starts_with("#EXT-X-KEY") {
+            continue;
+        }
+        if is_segment_line(line) {
+            out.push_str(&format!("seg_{:06}.ts\n", index));
+            index += 1;
+        } else {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+
+    let index_path = hls_dir.join("index.m3u8");
+    fs::write(&index_path, out).context("write local playlist")?;
+    log_info!(
+        "{} Wrote local HLS playlist with {} segments: {}",
+        timestamp(),
+        index,
+        index_path.display()
+    );
+    Ok(index_path)
+}
+
+/// Rename, falling back to copy + delete when the work dir is on another
+/// filesystem (e.g. a tmpfs `/tmp` and a download folder on disk)
+fn move_file(src: &Path, dst: &Path) -> std::io::Result<()> {
+    match fs::rename(src, dst) {
+        Ok(()) => Ok(()),
+        Err(_) => {
+            fs::copy(src, dst)?;
+            fs::remove_file(src)
+        }
+    }
+}
+
+/// `move_file` for a file or a whole directory (a kept HLS folder). A failed
+/// cross-volume copy is cleaned up so the source stays the only copy.
+pub(crate) fn move_path(src: &Path, dst: &Path) -> std::io::Result<()> {
+    if !src.is_dir() {
+        return move_file(src, dst).inspect_err(|_| {
+            if src.exists() {
+                let _ = fs::remove_file(dst);
+            }
+        });
+    }
+    if fs::rename(src, dst).is_ok() {
+        return Ok(());
+    }
+    if let Err(e) = copy_dir(src, dst) {
+        let _ = fs::remove_dir_all(dst);
+        return Err(e);
+    }
+    fs::remove_dir_all(src)
+}
+
+fn copy_dir(src: &Path, dst: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let target = dst.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Put each decrypted segment back under its numbered `.ts` name and drop
+/// the encrypted originals
+fn keep_decrypted_segments(work: &Path, count: usize) -> Result<()> {
+    for i in 0..count {
+        let seg = segment_path(work, i);
+        let decrypted = seg.with_extension("");
+        if !decrypted.exists() {
+            return Err(anyhow!("Missing segment {}", decrypted.display()));
+        }
+        fs::rename(&decrypted, &seg)?;
+        let _ = fs::remove_file(seg.with_extension("encrypted"));
+    }
+    Ok(())
+}
+
+/// Generate the concat file list in playlist order rather than trusting a
+/// directory listing
+fn write_concat_list(list_path: &Path, work: &Path, segment_count: usize, decrypted: bool) -> Result<()> {
+    let mut list_file = File::create(list_path)?;
+    for i in 0..segment_count {
+        let raw = segment_path(work, i);
+        // decrypted file has same name without .ts
+        let p = if decrypted { raw.with_extension("") } else { raw };
+        if !p.exists() {
+            return Err(anyhow!("Missing segment {}", p.display()));
+        }
+        // Escape single quotes in path for ffmpeg concat file list
+        let path_str = p.display().to_string().replace("'", "'\\''");
+        writeln!(list_file, "file '{}'", path_str)?;
+    }
+    Ok(())
+}
+
+/// Where the segment at playlist index `i` is stored in the work directory
+fn segment_path(work_dir: &Path, i: usize) -> PathBuf {
+    work_dir.join(format!("seg_{:06}.ts", i))
+}
+
+/// Size of a segment an earlier attempt already finished. Segments that
+/// were decrypted get their encrypted original moved back, so the decrypt
+/// pass sees the whole set again.
+fn reuse_segment(seg_path: &Path) -> Option<usize> {
+    let encrypted = seg_path.with_extension("encrypted");
+    if !seg_path.exists() && encrypted.exists() {
+        fs::rename(&encrypted, seg_path).ok()?;
+    }
+    let len = fs::metadata(seg_path).ok()?.len();
+    (len > 0).then_some(len as usize)
 }
 
 fn extract_key_uri(content: &str) -> Option<String> {
@@ -619,7 +2281,7 @@ fn extract_key_uri(content: &str) -> Option<String> {
     re.captures(content)?.get(1).map(|m| m.as_str().to_string())
 }
 
-async fn decrypt_segments(work_dir: &Path, key_hex: &str, threads: usize) -> Result<()> {
+async fn decrypt_segments(work_dir: &Path, key_hex: &str, concurrency: usize) -> Result<()> {
     let key_bytes = hex::decode(key_hex)?;
     let mut paths: Vec<PathBuf> = fs::read_dir(work_dir)?
         .filter_map(|entry| entry.ok())
@@ -630,14 +2292,14 @@ async fn decrypt_segments(work_dir: &Path, key_hex: &str, threads: usize) -> Res
     paths.sort();
     let total = paths.len();
 
-    eprintln!(
-        "{} Decrypting {} segment(s) with OpenSSL ({} parallel tasks)",
+    log_info!(
+        "{} Decrypting {} segment(s) ({} parallel tasks)",
         timestamp(),
         total,
-        threads
+        concurrency
     );
 
-    let semaphore = Arc::new(tokio::sync::Semaphore::new(threads));
+    let semaphore = Arc::new(tokio::sync::Semaphore::new(concurrency));
     let mut tasks = FuturesUnordered::new();
 
     for path in paths.into_iter() {
@@ -646,14 +2308,14 @@ async fn decrypt_segments(work_dir: &Path, key_hex: &str, threads: usize) -> Res
 
         let task = tokio::spawn(async move {
             let _permit = permit.acquire().await.expect("semaphore");
-            let content = tokiofs::read(&path).await?;
-            let decrypted = decrypt_aes128_cbc(&content, &key_bytes)?;
-
             let encrypted_path = path.with_extension("encrypted");
             tokiofs::rename(&path, &encrypted_path).await?;
 
             let decrypted_path = encrypted_path.with_extension("");
-            tokiofs::write(&decrypted_path, decrypted).await?;
+            tokio::task::spawn_blocking(move || {
+                decrypt_aes128_cbc_file(&encrypted_path, &decrypted_path, &key_bytes)
+            })
+            .await??;
 
             Ok::<(), anyhow::Error>(())
         });
@@ -667,7 +2329,7 @@ async fn decrypt_segments(work_dir: &Path, key_hex: &str, threads: usize) -> Res
             Ok(Ok(())) => {
                 completed += 1;
                 if completed % 25 == 0 || completed == total {
-                    eprintln!("{} Decrypted {}/{} segments", timestamp(), completed, total);
+                    log_debug!("{} Decrypted {}/{} segments", timestamp(), completed, total);
                 }
             }
             Ok(Err(err)) => return Err(err),
@@ -678,36 +2340,213 @@ async fn decrypt_segments(work_dir: &Path, key_hex: &str, threads: usize) -> Res
     Ok(())
 }
 
-fn decrypt_aes128_cbc(data: &[u8], key: &[u8]) -> Result<Vec<u8>> {
-    use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+/// Decrypt `src` into `dst` a chunk at a time, so memory use per segment
+/// stays at one buffer no matter how large the segment is
+fn decrypt_aes128_cbc_file(src: &Path, dst: &Path, key: &[u8]) -> Result<()> {
+    use aes::cipher::{generic_array::GenericArray, BlockDecryptMut, KeyIvInit};
+    use std::io::Read;
 
-    if data.len() < 16 {
-        return Err(anyhow!("Data too short for AES decryption"));
+    type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+    const BLOCK: usize = 16;
+    const CHUNK: usize = 64 * 1024;
+
+    let mut input = File::open(src)?;
+    let mut iv = [0u8; BLOCK];
+    input
+        .read_exact(&mut iv)
+        .map_err(|_| anyhow!("Data too short for AES decryption"))?;
+    let mut decryptor = Aes128CbcDec::new_from_slices(key, &iv)
+        .map_err(|err| anyhow!("Invalid key/iv length: {err:?}"))?;
+    let mut output = std::io::BufWriter::new(File::create(dst)?);
+
+    let mut buf = vec![0u8; CHUNK];
+    // The final block carries the padding, so the latest one is held back
+    // until we know whether more data follows
+    let mut held: Option<[u8; BLOCK]> = None;
+    loop {
+        let mut n = 0;
+        while n < buf.len() {
+            let read = input.read(&mut buf[n..])?;
+            if read == 0 {
+                break;
+            }
+            n += read;
+        }
+        if n == 0 {
+            break;
+        }
+        if n % BLOCK != 0 {
+            return Err(anyhow!("AES decryption failed: ciphertext is not block aligned"));
+        }
+        for block in buf[..n].chunks_exact_mut(BLOCK) {
+            decryptor.decrypt_block_mut(GenericArray::from_mut_slice(block));
+        }
+        if let Some(prev) = held.take() {
+            output.write_all(&prev)?;
+        }
+        output.write_all(&buf[..n - BLOCK])?;
+        let mut last = [0u8; BLOCK];
+        last.copy_from_slice(&buf[n - BLOCK..n]);
+        held = Some(last);
     }
 
-    type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+    let last = held.ok_or_else(|| anyhow!("Data too short for AES decryption"))?;
+    let pad = last[BLOCK - 1] as usize;
+    if pad == 0 || pad > BLOCK || last[BLOCK - pad..].iter().any(|&b| b as usize != pad) {
+        return Err(anyhow!("AES decryption failed: invalid padding"));
+    }
+    output.write_all(&last[..BLOCK - pad])?;
+    output.flush()?;
+    Ok(())
+}
 
-    let iv = &data[..16];
-    let encrypted = &data[16..];
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn absolutizes_relative_segments_and_key() {
+        let playlist = "#EXTM3U\n\
+#EXT-X-KEY:METHOD=AES-128,URI=\"mon.key\",IV=0x01\n\
+#EXTINF:10.0,\n\
+seg0.ts\n\
+#EXTINF:10.0,\n\
+../other/seg1.ts\n\
+#EXTINF:10.0,\n\
+https://cdn.example/abs/seg2.ts\n\
+#EXT-X-ENDLIST\n";
+        let out = absolutize_playlist(playlist, "https://cdn.example/stream/uwu.m3u8?token=x");
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines[1],
+            "#EXT-X-KEY:METHOD=AES-128,URI=\"https://cdn.example/stream/mon.key\",IV=0x01"
+        );
+        assert_eq!(lines[3], "https://cdn.example/stream/seg0.ts");
+        assert_eq!(lines[5], "https://cdn.example/other/seg1.ts");
+        assert_eq!(lines[7], "https://cdn.example/abs/seg2.ts");
+        assert_eq!(lines[8], "#EXT-X-ENDLIST");
+    }
 
-    let mut buffer = encrypted.to_vec();
-    let decryptor = Aes128CbcDec::new_from_slices(key, iv)
-        .map_err(|err| anyhow!("Invalid key/iv length: {err:?}"))?;
-    let decrypted = decryptor
-        .decrypt_padded_mut::<Pkcs7>(&mut buffer)
-        .map_err(|err| anyhow!("AES decryption failed: {err}"))?;
-
-    Ok(decrypted.to_vec())
-}
-
-fn create_client() -> Client {
-    reqwest::Client::builder()
-        .user_agent("Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/115 Safari/537.36")
-        .timeout(std::time::Duration::from_secs(60)) // Increased from 30
-        .connect_timeout(std::time::Duration::from_secs(15))
-        .pool_max_idle_per_host(32) // Allow more connections per host
-        .http2_adaptive_window(true) // Enable HTTP/2 multiplexing
-        .tcp_keepalive(std::time::Duration::from_secs(30))
-        .build()
-        .expect("Failed to create HTTP client")
+    /// `iv` followed by `data` encrypted block by block; `data` must already be padded
+    fn encrypt_segment(key: &[u8], iv: &[u8; 16], data: &[u8]) -> Vec<u8> {
+        use aes::cipher::{generic_array::GenericArray, BlockEncryptMut, KeyIvInit};
+        let mut encryptor = cbc::Encryptor::<aes::Aes128>::new_from_slices(key, iv).unwrap();
+        let mut out = iv.to_vec();
+        for block in data.chunks_exact(16) {
+            let mut block = GenericArray::clone_from_slice(block);
+            encryptor.encrypt_block_mut(&mut block);
+            out.extend_from_slice(&block);
+        }
+        out
+    }
+
+    fn decrypt_bytes(name: &str, key: &[u8], encrypted: &[u8]) -> Result<Vec<u8>> {
+        let dir = std::env::temp_dir().join(format!("decrypt-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (src, dst) = (dir.join("seg.encrypted"), dir.join("seg.ts"));
+        fs::write(&src, encrypted).unwrap();
+        let result = decrypt_aes128_cbc_file(&src, &dst, key).map(|()| fs::read(&dst).unwrap());
+        let _ = fs::remove_dir_all(&dir);
+        result
+    }
+
+    #[test]
+    fn decrypts_multi_chunk_segment() {
+        // NIST SP 800-38A F.2.1 (CBC-AES128), first block
+        let key = hex::decode("2b7e151628aed2a6abf7158809cf4f3c").unwrap();
+        let iv: [u8; 16] = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap().try_into().unwrap();
+        let nist_plain = hex::decode("6bc1bee22e409f96e93d7e117393172a").unwrap();
+        assert_eq!(
+            encrypt_segment(&key, &iv, &nist_plain)[16..],
+            hex::decode("7649abac8119b246cee98e9b12e9197d").unwrap()[..]
+        );
+
+        // Spans two 64 KiB read chunks, with a partial final block
+        let plain: Vec<u8> = (0..64 * 1024 + 37).map(|i| (i % 251) as u8).collect();
+        let pad = 16 - plain.len() % 16;
+        let mut padded = plain.clone();
+        padded.extend(std::iter::repeat(pad as u8).take(pad));
+        let encrypted = encrypt_segment(&key, &iv, &padded);
+
+        assert_eq!(decrypt_bytes("ok", &key, &encrypted).unwrap(), plain);
+    }
+
+    #[test]
+    fn rejects_bad_padding() {
+        let key = [7u8; 16];
+        let iv = [9u8; 16];
+        // Last byte 0 is never valid PKCS7
+        let mut data = [0x41u8; 32];
+        data[31] = 0;
+        let encrypted = encrypt_segment(&key, &iv, &data);
+        let err = decrypt_bytes("pad", &key, &encrypted).unwrap_err();
+        assert!(err.to_string().contains("invalid padding"));
+
+        let err = decrypt_bytes("short", &key, &iv).unwrap_err();
+        assert!(err.to_string().contains("too short"));
+    }
+
+    #[test]
+    fn keeps_segments_with_the_same_basename_in_order() {
+        let work = std::env::temp_dir().join(format!("concat-{}", std::process::id()));
+        fs::create_dir_all(&work).unwrap();
+        let seg_urls = [
+            "https://cdn-a.example/stream/1080/segment.ts",
+            "https://cdn-b.example/stream/1080/segment.ts",
+        ];
+        for (i, url) in seg_urls.iter().enumerate() {
+            fs::write(segment_path(&work, i), url).unwrap();
+        }
+
+        let list_path = work.join("file.list");
+        write_concat_list(&list_path, &work, seg_urls.len(), false).unwrap();
+        let listed: Vec<PathBuf> = fs::read_to_string(&list_path)
+            .unwrap()
+            .lines()
+            .map(|l| PathBuf::from(l.trim_start_matches("file '").trim_end_matches('\'')))
+            .collect();
+        assert_eq!(listed, vec![segment_path(&work, 0), segment_path(&work, 1)]);
+        let contents: Vec<String> = listed.iter().map(|p| fs::read_to_string(p).unwrap()).collect();
+        assert_eq!(contents, seg_urls);
+
+        let _ = fs::remove_dir_all(&work);
+    }
+
+    #[test]
+    fn short_segment_fails_length_check() {
+        let err = check_segment_length(1000, Some(4096), "https://cdn.example/seg0.ts").unwrap_err();
+        assert!(err.to_string().contains("got 1000 of 4096 bytes"));
+        assert!(check_segment_length(4097, Some(4096), "https://cdn.example/seg0.ts").is_err());
+        assert!(check_segment_length(4096, Some(4096), "https://cdn.example/seg0.ts").is_ok());
+    }
+
+    #[test]
+    fn missing_content_length_passes_length_check() {
+        assert!(check_segment_length(1000, None, "https://cdn.example/seg0.ts").is_ok());
+        assert!(check_segment_length(0, None, "https://cdn.example/seg0.ts").is_ok());
+    }
+
+    #[test]
+    fn picks_requested_variant_from_master_playlist() {
+        let master = "#EXTM3U\n\
+#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360\n\
+360/index.m3u8\n\
+#EXT-X-STREAM-INF:BANDWIDTH=2800000,RESOLUTION=1280x720\n\
+720/index.m3u8\n";
+        let base = "https://cdn.example/stream/master.m3u8";
+        assert_eq!(
+            pick_variant(master, base, Some("720")).as_deref(),
+            Some("https://cdn.example/stream/720/index.m3u8")
+        );
+        assert_eq!(
+            pick_variant(master, base, Some("360p")).as_deref(),
+            Some("https://cdn.example/stream/360/index.m3u8")
+        );
+        // Unknown height falls back to the tallest
+        assert_eq!(
+            pick_variant(master, base, Some("1080")).as_deref(),
+            Some("https://cdn.example/stream/720/index.m3u8")
+        );
+        assert_eq!(pick_variant("#EXTM3U\n#EXTINF:10.0,\nseg0.ts\n", base, Some("720")), None);
+    }
 }
@@ -1,24 +1,70 @@
+use crate::api::EpisodeNumber;
 use chrono::Utc;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension, Row};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use sha2::{Digest, Sha256};
 use std::fs;
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// How often `flush` is run to persist progress-only changes
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum DownloadStatus {
+    Queued,
     InProgress,
     Completed,
     Failed,
     Cancelled,
+    /// Held mid-download with its segments kept for a resume
+    Paused,
+}
+
+impl DownloadStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DownloadStatus::Queued => "queued",
+            DownloadStatus::InProgress => "inprogress",
+            DownloadStatus::Completed => "completed",
+            DownloadStatus::Failed => "failed",
+            DownloadStatus::Cancelled => "cancelled",
+            DownloadStatus::Paused => "paused",
+        }
+    }
+}
+
+// Stored as the same lowercase names the frontend sees
+impl ToSql for DownloadStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for DownloadStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        Ok(match value.as_str()? {
+            "queued" => DownloadStatus::Queued,
+            "inprogress" => DownloadStatus::InProgress,
+            "completed" => DownloadStatus::Completed,
+            "failed" => DownloadStatus::Failed,
+            "cancelled" => DownloadStatus::Cancelled,
+            "paused" => DownloadStatus::Paused,
+            other => return Err(FromSqlError::Other(format!("unknown download status {}", other).into())),
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DownloadRecord {
     pub id: String,
     pub anime_name: String,
-    pub episode: i32,
+    pub episode: EpisodeNumber,
     pub slug: String,
     pub status: DownloadStatus,
     pub file_path: String,
@@ -30,12 +76,151 @@ pub struct DownloadRecord {
     pub error_message: Option<String>,
     pub audio_type: Option<String>,
     pub resolution: Option<String>,
+    /// Lower runs first; ties fall back to the order items were queued
+    #[serde(default)]
+    pub priority: i32,
+    /// Segment scratch directory, so a resume can pick up where this left off
+    #[serde(default)]
+    pub work_dir: Option<String>,
+    /// Unix time before which a failed record isn't retried in bulk
+    #[serde(default)]
+    pub retry_after: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeepValidation {
+    pub valid: bool,
+    pub bytes_read: u64,
+    pub expected_size: Option<u64>,
+    pub sha256: Option<String>,
+}
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS downloads (
+        id TEXT PRIMARY KEY,
+        anime_name TEXT NOT NULL,
+        episode INTEGER NOT NULL,
+        slug TEXT NOT NULL,
+        status TEXT NOT NULL,
+        file_path TEXT NOT NULL,
+        downloaded_bytes INTEGER NOT NULL DEFAULT 0,
+        file_size INTEGER,
+        started_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL,
+        completed_at INTEGER,
+        error_message TEXT,
+        audio_type TEXT,
+        resolution TEXT,
+        priority INTEGER NOT NULL DEFAULT 0,
+        work_dir TEXT,
+        retry_after INTEGER
+    );
+    CREATE INDEX IF NOT EXISTS downloads_status ON downloads(status);
+";
+
+/// How long a failed download waits before a bulk retry picks it up again,
+/// so an outage isn't hammered with the same batch
+const FAILED_RETRY_BACKOFF_SECS: i64 = 60;
+
+const COLUMNS: &str = "id, anime_name, episode, slug, status, file_path, downloaded_bytes, file_size,
+    started_at, updated_at, completed_at, error_message, audio_type, resolution, priority, work_dir, retry_after";
+
+fn record_from_row(row: &Row) -> rusqlite::Result<DownloadRecord> {
+    Ok(DownloadRecord {
+        id: row.get(0)?,
+        anime_name: row.get(1)?,
+        episode: row.get(2)?,
+        slug: row.get(3)?,
+        status: row.get(4)?,
+        file_path: row.get(5)?,
+        downloaded_bytes: row.get::<_, i64>(6)? as u64,
+        file_size: row.get::<_, Option<i64>>(7)?.map(|s| s as u64),
+        started_at: row.get(8)?,
+        updated_at: row.get(9)?,
+        completed_at: row.get(10)?,
+        error_message: row.get(11)?,
+        audio_type: row.get(12)?,
+        resolution: row.get(13)?,
+        priority: row.get(14)?,
+        work_dir: row.get(15)?,
+        retry_after: row.get(16)?,
+    })
+}
+
+fn insert(conn: &Connection, record: &DownloadRecord) -> rusqlite::Result<usize> {
+    conn.execute(
+        &format!("INSERT OR REPLACE INTO downloads ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)", COLUMNS),
+        params![
+            record.id,
+            record.anime_name,
+            record.episode,
+            record.slug,
+            record.status,
+            record.file_path,
+            record.downloaded_bytes as i64,
+            record.file_size.map(|s| s as i64),
+            record.started_at,
+            record.updated_at,
+            record.completed_at,
+            record.error_message,
+            record.audio_type,
+            record.resolution,
+            record.priority,
+            record.work_dir,
+            record.retry_after,
+        ],
+    )
+}
+
+/// Databases created before failed downloads had a backoff lack the column
+fn add_retry_after_column(conn: &Connection) -> rusqlite::Result<()> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info('downloads') WHERE name = 'retry_after')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        conn.execute_batch("ALTER TABLE downloads ADD COLUMN retry_after INTEGER;")?;
+    }
+    Ok(())
+}
+
+/// Bring records over from the JSON file earlier versions kept, then
+/// rename it so the import happens once
+fn import_json_state(conn: &mut Connection, json_file: &Path) -> Result<(), String> {
+    let content = fs::read_to_string(json_file)
+        .map_err(|e| format!("Failed to read download state: {}", e))?;
+    let records: HashMap<String, DownloadRecord> = serde_json::from_str(&content).unwrap_or_else(|e| {
+        log_warn!("Ignoring unreadable {}: {}", json_file.display(), e);
+        HashMap::new()
+    });
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    for record in records.values() {
+        insert(&tx, record).map_err(|e| format!("Failed to import download {}: {}", record.id, e))?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+
+    fs::rename(json_file, json_file.with_extension("json.migrated"))
+        .map_err(|e| format!("Failed to retire download state file: {}", e))?;
+    log_info!("Imported {} downloads from {}", records.len(), json_file.display());
+    Ok(())
+}
+
+/// Latest progress tick for a download that isn't written yet
+#[derive(Debug, Clone, Copy)]
+struct PendingProgress {
+    downloaded_bytes: u64,
+    file_size: Option<u64>,
+    updated_at: i64,
 }
 
 #[derive(Debug, Clone)]
 pub struct DownloadTracker {
-    state_file: PathBuf,
-    records: Arc<Mutex<HashMap<String, DownloadRecord>>>,
+    conn: Arc<Mutex<Connection>>,
+    db_path: PathBuf,
+    /// Progress ticks since the last write, keyed by download id
+    pending: Arc<Mutex<HashMap<String, PendingProgress>>>,
 }
 
 impl DownloadTracker {
@@ -46,35 +231,156 @@ impl DownloadTracker {
                 .map_err(|e| format!("Failed to create config directory: {}", e))?;
         }
 
-        let state_file = config_dir.join("download_state.json");
+        let db_path = config_dir.join("downloads.db");
+        let mut conn = Connection::open(&db_path)
+            .map_err(|e| format!("Failed to open download database: {}", e))?;
+        // Cleared records give their pages back to the OS; only takes effect
+        // before the first table is created
+        conn.execute_batch("PRAGMA auto_vacuum = FULL;")
+            .map_err(|e| format!("Failed to configure download database: {}", e))?;
+        // WAL keeps a crash mid-write from taking earlier rows with it
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")
+            .map_err(|e| format!("Failed to configure download database: {}", e))?;
+        conn.execute_batch(SCHEMA)
+            .map_err(|e| format!("Failed to create download table: {}", e))?;
+        add_retry_after_column(&conn)
+            .map_err(|e| format!("Failed to add retry_after column: {}", e))?;
+
+        let json_file = config_dir.join("download_state.json");
+        if json_file.exists() {
+            import_json_state(&mut conn, &json_file)?;
+        }
 
-        // Load existing state or create new
-        let records = if state_file.exists() {
-            let content = fs::read_to_string(&state_file)
-                .map_err(|e| format!("Failed to read download state: {}", e))?;
+        Ok(DownloadTracker {
+            conn: Arc::new(Mutex::new(conn)),
+            db_path,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
 
-            let map: HashMap<String, DownloadRecord> = serde_json::from_str(&content)
-                .unwrap_or_else(|_| HashMap::new());
+    /// Fold the WAL back in and rebuild the file to drop free pages.
+    /// Returns bytes reclaimed across the database and its WAL.
+    pub fn compact(&self) -> Result<u64, String> {
+        let conn = self.lock()?;
+        let wal_path = PathBuf::from(format!("{}-wal", self.db_path.display()));
+        let disk_size = || {
+            [&self.db_path, &wal_path]
+                .iter()
+                .filter_map(|p| fs::metadata(p).ok())
+                .map(|m| m.len())
+                .sum::<u64>()
+        };
+        let before = disk_size();
+        let checkpoint = || {
+            conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+                .map_err(|e| format!("Failed to checkpoint download database: {}", e))
+        };
+        checkpoint()?;
+        conn.execute_batch("VACUUM;")
+            .map_err(|e| format!("Failed to vacuum download database: {}", e))?;
+        // VACUUM goes through the WAL too
+        checkpoint()?;
+        Ok(before.saturating_sub(disk_size()))
+    }
 
-            Arc::new(Mutex::new(map))
-        } else {
-            Arc::new(Mutex::new(HashMap::new()))
+    /// The connection, with buffered progress written first so reads and
+    /// status changes see it
+    fn lock(&self) -> Result<MutexGuard<'_, Connection>, String> {
+        let mut conn = self.conn.lock().unwrap();
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        if pending.is_empty() {
+            return Ok(conn);
+        }
+        let write = |conn: &mut Connection| -> rusqlite::Result<()> {
+            let tx = conn.transaction()?;
+            for (id, progress) in &pending {
+                tx.execute(
+                    "UPDATE downloads SET downloaded_bytes = ?1, file_size = COALESCE(?2, file_size), updated_at = ?3
+                     WHERE id = ?4",
+                    params![
+                        progress.downloaded_bytes as i64,
+                        progress.file_size.map(|s| s as i64),
+                        progress.updated_at,
+                        id
+                    ],
+                )?;
+            }
+            tx.commit()
         };
+        if let Err(e) = write(&mut conn) {
+            // Keep the ticks for the next attempt unless newer ones arrived
+            let mut buffered = self.pending.lock().unwrap();
+            for (id, progress) in pending {
+                buffered.entry(id).or_insert(progress);
+            }
+            return Err(format!("Failed to write download progress: {}", e));
+        }
+        Ok(conn)
+    }
 
-        Ok(DownloadTracker {
-            state_file,
-            records,
-        })
+    /// Run one UPDATE against a download row
+    fn update(&self, sql: &str, params: impl rusqlite::Params) -> Result<usize, String> {
+        self.lock()?
+            .execute(sql, params)
+            .map_err(|e| format!("Failed to update download: {}", e))
     }
 
     pub fn add_download(
         &self,
         anime_name: String,
-        episode: i32,
+        episode: EpisodeNumber,
+        slug: String,
+        file_path: String,
+        audio_type: Option<String>,
+        resolution: Option<String>,
+    ) -> Result<String, String> {
+        self.insert_record(
+            anime_name,
+            episode,
+            slug,
+            file_path,
+            audio_type,
+            resolution,
+            DownloadStatus::InProgress,
+            0,
+        )
+    }
+
+    /// Record an episode that is waiting its turn in a batch, so the batch
+    /// can be rebuilt in order if the app exits before reaching it
+    pub fn add_queued(
+        &self,
+        anime_name: String,
+        episode: EpisodeNumber,
         slug: String,
         file_path: String,
         audio_type: Option<String>,
         resolution: Option<String>,
+        priority: i32,
+    ) -> Result<String, String> {
+        self.insert_record(
+            anime_name,
+            episode,
+            slug,
+            file_path,
+            audio_type,
+            resolution,
+            DownloadStatus::Queued,
+            priority,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn insert_record(
+        &self,
+        anime_name: String,
+        episode: EpisodeNumber,
+        slug: String,
+        file_path: String,
+        audio_type: Option<String>,
+        resolution: Option<String>,
+        status: DownloadStatus,
+        priority: i32,
     ) -> Result<String, String> {
         let id = format!("{}-ep{}-{}", slug, episode, Utc::now().timestamp());
         let now = Utc::now().timestamp();
@@ -84,7 +390,7 @@ impl DownloadTracker {
             anime_name,
             episode,
             slug,
-            status: DownloadStatus::InProgress,
+            status,
             file_path,
             downloaded_bytes: 0,
             file_size: None,
@@ -94,102 +400,161 @@ impl DownloadTracker {
             error_message: None,
             audio_type,
             resolution,
+            priority,
+            work_dir: None,
+            retry_after: None,
         };
 
-        let mut records = self.records.lock().unwrap();
-        records.insert(id.clone(), record);
-        drop(records);
-
-        self.save_to_disk()?;
+        insert(&self.lock()?, &record).map_err(|e| format!("Failed to save download: {}", e))?;
         Ok(id)
     }
 
+    /// Progress ticks are buffered; `flush` (or the next status change) writes them
     pub fn update_progress(&self, id: &str, downloaded_bytes: u64, file_size: Option<u64>) -> Result<(), String> {
-        let mut records = self.records.lock().unwrap();
+        let mut pending = self.pending.lock().unwrap();
+        let file_size = file_size.or_else(|| pending.get(id).and_then(|p| p.file_size));
+        pending.insert(
+            id.to_string(),
+            PendingProgress {
+                downloaded_bytes,
+                file_size,
+                updated_at: Utc::now().timestamp(),
+            },
+        );
+        Ok(())
+    }
 
-        if let Some(record) = records.get_mut(id) {
-            record.downloaded_bytes = downloaded_bytes;
-            if file_size.is_some() {
-                record.file_size = file_size;
-            }
-            record.updated_at = Utc::now().timestamp();
-        }
-        drop(records);
+    /// Write buffered progress to disk, if there is any. Status changes are
+    /// written as they happen; this is for the periodic task and shutdown.
+    pub fn flush(&self) -> Result<(), String> {
+        self.lock().map(|_| ())
+    }
 
-        self.save_to_disk()
+    /// A queued record has reached the front of its batch
+    pub fn mark_started(&self, id: &str) -> Result<(), String> {
+        self.update(
+            "UPDATE downloads SET status = ?1, updated_at = ?2 WHERE id = ?3",
+            params![DownloadStatus::InProgress, Utc::now().timestamp(), id],
+        )
+        .map(|_| ())
     }
 
-    pub fn mark_completed(&self, id: &str) -> Result<(), String> {
-        let mut records = self.records.lock().unwrap();
+    pub fn set_file_path(&self, id: &str, file_path: String) -> Result<(), String> {
+        self.update("UPDATE downloads SET file_path = ?1 WHERE id = ?2", params![file_path, id])
+            .map(|_| ())
+    }
 
-        if let Some(record) = records.get_mut(id) {
-            record.status = DownloadStatus::Completed;
-            record.updated_at = Utc::now().timestamp();
-            record.completed_at = Some(Utc::now().timestamp());
+    pub fn set_work_dir(&self, id: &str, work_dir: String) -> Result<(), String> {
+        self.update("UPDATE downloads SET work_dir = ?1 WHERE id = ?2", params![work_dir, id])
+            .map(|_| ())
+    }
 
-            // Set downloaded_bytes to file_size if available
-            if let Some(size) = record.file_size {
-                record.downloaded_bytes = size;
-            }
+    pub fn set_priority(&self, id: &str, priority: i32) -> Result<(), String> {
+        let changed = self.update(
+            "UPDATE downloads SET priority = ?1, updated_at = ?2 WHERE id = ?3",
+            params![priority, Utc::now().timestamp(), id],
+        )?;
+        if changed == 0 {
+            return Err("Download record not found".to_string());
         }
-        drop(records);
+        Ok(())
+    }
 
-        self.save_to_disk()
+    /// `output_size` is the finished file's size on disk. It replaces the
+    /// pre-download estimate in `file_size`, which validation compares against.
+    pub fn mark_completed(&self, id: &str, output_size: Option<u64>) -> Result<(), String> {
+        let now = Utc::now().timestamp();
+        // downloaded_bytes becomes file_size when that is known
+        self.update(
+            "UPDATE downloads SET status = ?1, updated_at = ?2, completed_at = ?2,
+             file_size = COALESCE(?3, file_size),
+             downloaded_bytes = COALESCE(?3, file_size, downloaded_bytes) WHERE id = ?4",
+            params![DownloadStatus::Completed, now, output_size.map(|s| s as i64), id],
+        )
+        .map(|_| ())
     }
 
     pub fn mark_failed(&self, id: &str, error: String) -> Result<(), String> {
-        let mut records = self.records.lock().unwrap();
-
-        if let Some(record) = records.get_mut(id) {
-            record.status = DownloadStatus::Failed;
-            record.error_message = Some(error);
-            record.updated_at = Utc::now().timestamp();
-        }
-        drop(records);
+        let now = Utc::now().timestamp();
+        self.update(
+            "UPDATE downloads SET status = ?1, error_message = ?2, updated_at = ?3, retry_after = ?4 WHERE id = ?5",
+            params![DownloadStatus::Failed, error, now, now + FAILED_RETRY_BACKOFF_SECS, id],
+        )
+        .map(|_| ())
+    }
 
-        self.save_to_disk()
+    pub fn mark_paused(&self, id: &str) -> Result<(), String> {
+        self.update(
+            "UPDATE downloads SET status = ?1, updated_at = ?2 WHERE id = ?3",
+            params![DownloadStatus::Paused, Utc::now().timestamp(), id],
+        )
+        .map(|_| ())
     }
 
     pub fn mark_cancelled(&self, id: &str) -> Result<(), String> {
-        let mut records = self.records.lock().unwrap();
-
-        if let Some(record) = records.get_mut(id) {
-            record.status = DownloadStatus::Cancelled;
-            record.updated_at = Utc::now().timestamp();
-        }
-        drop(records);
-
-        self.save_to_disk()
+        self.update(
+            "UPDATE downloads SET status = ?1, updated_at = ?2 WHERE id = ?3",
+            params![DownloadStatus::Cancelled, Utc::now().timestamp(), id],
+        )
+        .map(|_| ())
     }
 
+    /// Unfinished work in queue order: by priority, then by when it was queued
     pub fn get_incomplete_downloads(&self) -> Vec<DownloadRecord> {
-        let records = self.records.lock().unwrap();
-        records
-            .values()
-            .filter(|r| r.status == DownloadStatus::InProgress || r.status == DownloadStatus::Failed)
-            .cloned()
-            .collect()
+        let query = || -> Result<Vec<DownloadRecord>, String> {
+            let conn = self.lock()?;
+            let mut stmt = conn
+                .prepare(&format!(
+                    "SELECT {} FROM downloads WHERE status IN ('queued', 'inprogress', 'failed', 'paused')
+                     ORDER BY priority, started_at, episode",
+                    COLUMNS
+                ))
+                .map_err(|e| e.to_string())?;
+            let records = stmt
+                .query_map([], record_from_row)
+                .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+                .map_err(|e| e.to_string());
+            records
+        };
+        query().unwrap_or_else(|e| {
+            log_warn!("Failed to read incomplete downloads: {}", e);
+            Vec::new()
+        })
     }
 
     pub fn get_download(&self, id: &str) -> Option<DownloadRecord> {
-        let records = self.records.lock().unwrap();
-        records.get(id).cloned()
+        let conn = self.lock().ok()?;
+        conn.query_row(
+            &format!("SELECT {} FROM downloads WHERE id = ?1", COLUMNS),
+            params![id],
+            record_from_row,
+        )
+        .optional()
+        .unwrap_or_else(|e| {
+            log_warn!("Failed to read download {}: {}", id, e);
+            None
+        })
     }
 
     pub fn remove_download(&self, id: &str) -> Result<(), String> {
-        let mut records = self.records.lock().unwrap();
-        records.remove(id);
-        drop(records);
-
-        self.save_to_disk()
+        self.update("DELETE FROM downloads WHERE id = ?1", params![id]).map(|_| ())
     }
 
-    pub fn clear_completed(&self) -> Result<(), String> {
-        let mut records = self.records.lock().unwrap();
-        records.retain(|_, r| r.status != DownloadStatus::Completed);
-        drop(records);
-
-        self.save_to_disk()
+    /// Ids of the records whose status is in `statuses`
+    pub fn ids_with_status(&self, statuses: &[DownloadStatus]) -> Result<Vec<String>, String> {
+        if statuses.is_empty() {
+            return Ok(Vec::new());
+        }
+        let conn = self.lock()?;
+        let placeholders = vec!["?"; statuses.len()].join(", ");
+        let mut stmt = conn
+            .prepare(&format!("SELECT id FROM downloads WHERE status IN ({})", placeholders))
+            .map_err(|e| e.to_string())?;
+        let ids = stmt
+            .query_map(rusqlite::params_from_iter(statuses), |row| row.get(0))
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| e.to_string());
+        ids
     }
 
     pub fn validate_file(&self, id: &str) -> Result<bool, String> {
@@ -220,14 +585,69 @@ impl DownloadTracker {
         Ok(true)
     }
 
-    fn save_to_disk(&self) -> Result<(), String> {
-        let records = self.records.lock().unwrap();
-        let json = serde_json::to_string_pretty(&*records)
-            .map_err(|e| format!("Failed to serialize download state: {}", e))?;
+    /// Read the whole file in chunks, optionally hashing it, and report
+    /// `(bytes_read, total_bytes)` through `on_progress` as it goes.
+    /// This is blocking; call it from a blocking task for large files.
+    pub fn validate_file_deep<F>(
+        &self,
+        id: &str,
+        hash: bool,
+        mut on_progress: F,
+    ) -> Result<DeepValidation, String>
+    where
+        F: FnMut(u64, u64),
+    {
+        let record = self.get_download(id)
+            .ok_or_else(|| "Download record not found".to_string())?;
+
+        let path = PathBuf::from(&record.file_path);
+        let expected_size = if record.status == DownloadStatus::Completed {
+            record.file_size
+        } else {
+            None
+        };
+
+        if !path.exists() {
+            return Ok(DeepValidation {
+                valid: false,
+                bytes_read: 0,
+                expected_size,
+                sha256: None,
+            });
+        }
+
+        let mut file = fs::File::open(&path)
+            .map_err(|e| format!("Failed to open file: {}", e))?;
+        let total = file
+            .metadata()
+            .map_err(|e| format!("Failed to get file metadata: {}", e))?
+            .len();
+
+        let mut hasher = if hash { Some(Sha256::new()) } else { None };
+        let mut buf = vec![0u8; 1024 * 1024];
+        let mut bytes_read = 0u64;
+
+        loop {
+            let n = file
+                .read(&mut buf)
+                .map_err(|e| format!("Failed to read file: {}", e))?;
+            if n == 0 {
+                break;
+            }
+            if let Some(ref mut h) = hasher {
+                h.update(&buf[..n]);
+            }
+            bytes_read += n as u64;
+            on_progress(bytes_read, total);
+        }
 
-        fs::write(&self.state_file, json)
-            .map_err(|e| format!("Failed to write download state: {}", e))?;
+        let size_ok = expected_size.map_or(true, |expected| expected == bytes_read);
 
-        Ok(())
+        Ok(DeepValidation {
+            valid: bytes_read > 0 && size_ok,
+            bytes_read,
+            expected_size,
+            sha256: hasher.map(|h| hex::encode(h.finalize())),
+        })
     }
 }
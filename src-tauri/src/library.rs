@@ -1,16 +1,41 @@
 use anyhow::{Context, Result};
 use chrono::Utc;
-use rusqlite::{params, Connection};
+use crate::api::EpisodeNumber;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
+// Regular episodes stay INTEGER in the `episode` column; specials like 12.5
+// are stored as REAL, which the column's affinity keeps as-is
+impl ToSql for EpisodeNumber {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(if self.is_whole() {
+            ToSqlOutput::from(self.whole() as i64)
+        } else {
+            ToSqlOutput::from(self.as_f64())
+        })
+    }
+}
+
+impl FromSql for EpisodeNumber {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let n = match value {
+            ValueRef::Integer(i) => i as f64,
+            ValueRef::Real(f) => f,
+            _ => return Err(FromSqlError::InvalidType),
+        };
+        EpisodeNumber::from_f64(n).ok_or(FromSqlError::OutOfRange(n as i64))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LibraryEntry {
     pub id: i64,
     pub anime_name: String,
     pub slug: String,
-    pub episode: i32,
+    pub episode: EpisodeNumber,
     pub resolution: Option<String>,
     pub audio: Option<String>,
     pub file_path: String,
@@ -21,6 +46,12 @@ pub struct LibraryEntry {
     pub watch_count: i64,
     pub duration_seconds: Option<i64>,
     pub host: String,
+    /// Played at least once (`watch_count > 0` or `last_watched` set)
+    #[serde(default)]
+    pub watched: bool,
+    /// Where playback stopped last time; cleared once the episode is watched
+    #[serde(default)]
+    pub playback_position_seconds: Option<f64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,6 +62,90 @@ pub struct AnimeStats {
     pub total_size: i64,
     pub thumbnail_url: Option<String>,
     pub last_downloaded: i64,
+    /// The user's 1-10 score, if they've rated the show
+    #[serde(default)]
+    pub rating: Option<i64>,
+}
+
+/// Order for `get_anime_library`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LibrarySort {
+    #[default]
+    Recent,
+    /// Highest rated first, unrated shows last
+    Rating,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EpisodeGaps {
+    /// Every downloaded episode, specials included, in order
+    pub downloaded: Vec<EpisodeNumber>,
+    /// Regular episodes missing between the first and last downloaded one
+    pub missing: Vec<EpisodeNumber>,
+}
+
+/// Order for `query_library`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntrySort {
+    #[default]
+    Date,
+    Name,
+    Size,
+    Episode,
+}
+
+/// Filters and ordering for `query_library`; every field is optional
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LibraryQuery {
+    pub sort: EntrySort,
+    /// Defaults to newest/largest first for date and size, A–Z and first
+    /// episode first for name and episode
+    pub descending: Option<bool>,
+    pub resolution: Option<String>,
+    pub audio: Option<String>,
+    pub host: Option<String>,
+    pub watched: Option<bool>,
+    pub offset: u32,
+    /// Page size; everything from `offset` on when unset
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LibraryPage {
+    pub entries: Vec<LibraryEntry>,
+    /// Matching entries across all pages
+    pub total: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnimeRating {
+    pub slug: String,
+    pub rating: i64,
+}
+
+/// A show's tag, as stored in `tags` and carried through export/import
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnimeTag {
+    pub slug: String,
+    pub tag: String,
+}
+
+/// `export_library` output. Older exports were a bare array of entries,
+/// which `import_library` still accepts.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum LibraryExport {
+    Full {
+        entries: Vec<LibraryEntry>,
+        #[serde(default)]
+        tags: Vec<AnimeTag>,
+        #[serde(default)]
+        ratings: Vec<AnimeRating>,
+    },
+    Entries(Vec<LibraryEntry>),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,63 +155,231 @@ pub struct LibraryStats {
     pub total_size: i64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtendedLibraryStats {
+    pub avg_file_size: i64,
+    pub largest_anime: Option<String>,
+    pub largest_anime_size: i64,
+    pub bytes_this_month: i64,
+    pub downloads_this_week: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NameVariant {
+    pub anime_name: String,
+    pub episode_count: i64,
+}
+
+/// A slug whose episodes were filed under more than one name
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateAnime {
+    pub slug: String,
+    pub names: Vec<NameVariant>,
+}
+
+/// The same episode of the same show stored in several rows (e.g. under different slugs)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateEpisode {
+    pub anime_name: String,
+    pub episode: EpisodeNumber,
+    pub entry_ids: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateReport {
+    pub anime: Vec<DuplicateAnime>,
+    pub episodes: Vec<DuplicateEpisode>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RelocatedEntry {
+    pub id: i64,
+    pub old_path: String,
+    pub new_path: String,
+}
+
+/// Kept HLS downloads are a folder around `index.m3u8`; the folder is what moves
+fn files_to_move(file_path: &Path) -> &Path {
+    match file_path.extension() {
+        Some(ext) if ext == "m3u8" => file_path.parent().unwrap_or(file_path),
+        _ => file_path,
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Library {
     conn: Arc<Mutex<Connection>>,
+    db_path: PathBuf,
+    /// `library_fts` is available; false on SQLite builds without FTS5
+    fts: bool,
+}
+
+/// Schema steps in order; `PRAGMA user_version` counts how many have run.
+/// Append new steps and never edit shipped ones.
+const MIGRATIONS: &[&str] = &[
+    // 1: the original table, UNIQUE on (slug, episode). IF NOT EXISTS lets
+    // databases from before versioning (user_version 0) pass straight through.
+    "CREATE TABLE IF NOT EXISTS library (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        anime_name TEXT NOT NULL,
+        slug TEXT NOT NULL,
+        episode INTEGER NOT NULL,
+        resolution TEXT,
+        audio TEXT,
+        file_path TEXT NOT NULL UNIQUE,
+        file_size INTEGER NOT NULL,
+        thumbnail_url TEXT,
+        downloaded_at INTEGER NOT NULL,
+        last_watched INTEGER,
+        watch_count INTEGER DEFAULT 0,
+        duration_seconds INTEGER,
+        host TEXT NOT NULL,
+        UNIQUE(slug, episode)
+    )",
+    // 2: resume point for partly watched episodes
+    "ALTER TABLE library ADD COLUMN playback_position_seconds REAL",
+    // 3: user tags per show
+    "CREATE TABLE IF NOT EXISTS tags (
+        slug TEXT NOT NULL,
+        tag TEXT NOT NULL COLLATE NOCASE,
+        added_at INTEGER NOT NULL,
+        PRIMARY KEY (slug, tag)
+    )",
+    // 4: per-show rating; shows span many library rows, so it lives apart
+    "CREATE TABLE IF NOT EXISTS ratings (
+        slug TEXT PRIMARY KEY,
+        rating INTEGER CHECK (rating BETWEEN 1 AND 10)
+    )",
+];
+
+/// Offered by `list_tags` even before anything is tagged with them
+pub const DEFAULT_TAGS: &[&str] = &["Watching", "Plan to watch", "Favorites"];
+
+/// Full-text index over show names, kept in step with `library` by
+/// triggers. Outside `MIGRATIONS` because it needs FTS5, which not every
+/// SQLite build has; without it search falls back to `LIKE`.
+fn enable_fts(conn: &Connection) -> Result<()> {
+    let existed: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'library_fts')",
+        [],
+        |row| row.get(0),
+    )?;
+    // INSERT OR REPLACE only fires delete triggers with this on
+    conn.execute_batch(
+        "PRAGMA recursive_triggers = ON;
+        CREATE VIRTUAL TABLE IF NOT EXISTS library_fts
+            USING fts5(anime_name, content='library', content_rowid='id');
+        CREATE TRIGGER IF NOT EXISTS library_fts_insert AFTER INSERT ON library BEGIN
+            INSERT INTO library_fts(rowid, anime_name) VALUES (new.id, new.anime_name);
+        END;
+        CREATE TRIGGER IF NOT EXISTS library_fts_delete AFTER DELETE ON library BEGIN
+            INSERT INTO library_fts(library_fts, rowid, anime_name) VALUES ('delete', old.id, old.anime_name);
+        END;
+        CREATE TRIGGER IF NOT EXISTS library_fts_update AFTER UPDATE OF anime_name ON library BEGIN
+            INSERT INTO library_fts(library_fts, rowid, anime_name) VALUES ('delete', old.id, old.anime_name);
+            INSERT INTO library_fts(rowid, anime_name) VALUES (new.id, new.anime_name);
+        END;",
+    )?;
+    if !existed {
+        conn.execute_batch("INSERT INTO library_fts(library_fts) VALUES ('rebuild');")?;
+    }
+    Ok(())
+}
+
+/// Every word of `query` as a quoted prefix term, so `attack tit` matches
+/// "Attack on Titan". `None` when there are no words to search for.
+fn fts_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| format!("\"{}\"*", t))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Bring the schema up to date, one transaction per step
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let version: i64 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .context("Failed to read library schema version")?;
+    let version = version.max(0) as usize;
+    if version > MIGRATIONS.len() {
+        log_warn!(
+            "Library schema version {} is newer than this build knows ({})",
+            version,
+            MIGRATIONS.len()
+        );
+        return Ok(());
+    }
+    for (i, step) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction().context("Failed to start library migration")?;
+        tx.execute_batch(step)
+            .with_context(|| format!("Library migration {} failed", i + 1))?;
+        tx.execute_batch(&format!("PRAGMA user_version = {}", i + 1))
+            .context("Failed to record library schema version")?;
+        tx.commit().context("Failed to commit library migration")?;
+        log_info!("Library schema migrated to version {}", i + 1);
+    }
+    Ok(())
 }
 
 impl Library {
     pub fn new(db_path: PathBuf) -> Result<Self> {
-        let conn = Connection::open(&db_path)
+        let mut conn = Connection::open(&db_path)
             .context("Failed to open library database")?;
 
-        // Create table with UNIQUE constraint on (slug, episode)
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS library (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                anime_name TEXT NOT NULL,
-                slug TEXT NOT NULL,
-                episode INTEGER NOT NULL,
-                resolution TEXT,
-                audio TEXT,
-                file_path TEXT NOT NULL UNIQUE,
-                file_size INTEGER NOT NULL,
-                thumbnail_url TEXT,
-                downloaded_at INTEGER NOT NULL,
-                last_watched INTEGER,
-                watch_count INTEGER DEFAULT 0,
-                duration_seconds INTEGER,
-                host TEXT NOT NULL,
-                UNIQUE(slug, episode)
-            )",
-            [],
-        ).context("Failed to create library table")?;
+        // Deleted rows give their pages back to the OS at commit. Only takes
+        // effect on a fresh database; existing ones pick it up on the next VACUUM
+        conn.execute_batch("PRAGMA auto_vacuum = FULL;")
+            .context("Failed to set auto_vacuum")?;
+
+        migrate(&mut conn)?;
+        let fts = match enable_fts(&conn) {
+            Ok(()) => true,
+            Err(e) => {
+                log_warn!("Library full-text search unavailable, using LIKE: {}", e);
+                false
+            }
+        };
 
         Ok(Library {
             conn: Arc::new(Mutex::new(conn)),
+            db_path,
+            fts,
         })
     }
 
+    /// Rebuild the database file to drop free pages. Returns bytes reclaimed.
+    pub fn compact(&self) -> Result<u64> {
+        let conn = self.conn.lock().unwrap();
+        let before = std::fs::metadata(&self.db_path).map(|m| m.len()).unwrap_or(0);
+        conn.execute_batch("VACUUM;").context("Failed to vacuum library database")?;
+        let after = std::fs::metadata(&self.db_path).map(|m| m.len()).unwrap_or(0);
+        Ok(before.saturating_sub(after))
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn add_download(
         &self,
         anime_name: &str,
         slug: &str,
-        episode: i32,
+        episode: EpisodeNumber,
         resolution: Option<&str>,
         audio: Option<&str>,
         file_path: &str,
         file_size: i64,
         thumbnail_url: Option<&str>,
         host: &str,
+        duration_seconds: Option<i64>,
     ) -> Result<i64> {
         let conn = self.conn.lock().unwrap();
         let now = Utc::now().timestamp();
 
         conn.execute(
             "INSERT OR REPLACE INTO library
-            (anime_name, slug, episode, resolution, audio, file_path, file_size, thumbnail_url, downloaded_at, host)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
-            params![anime_name, slug, episode, resolution, audio, file_path, file_size, thumbnail_url, now, host],
+            (anime_name, slug, episode, resolution, audio, file_path, file_size, thumbnail_url, downloaded_at, host, duration_seconds)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![anime_name, slug, episode, resolution, audio, file_path, file_size, thumbnail_url, now, host, duration_seconds],
         ).context("Failed to insert library entry")?;
 
         Ok(conn.last_insert_rowid())
@@ -106,7 +389,8 @@ impl Library {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
             "SELECT id, anime_name, slug, episode, resolution, audio, file_path, file_size,
-             thumbnail_url, downloaded_at, last_watched, watch_count, duration_seconds, host
+             thumbnail_url, downloaded_at, last_watched, watch_count, duration_seconds, host,
+             (watch_count > 0 OR last_watched IS NOT NULL) AS watched, playback_position_seconds
              FROM library ORDER BY downloaded_at DESC"
         )?;
 
@@ -126,23 +410,114 @@ impl Library {
                 watch_count: row.get(11)?,
                 duration_seconds: row.get(12)?,
                 host: row.get(13)?,
+                watched: row.get(14)?,
+                playback_position_seconds: row.get(15)?,
             })
         })?.collect::<Result<Vec<_>, _>>()?;
 
         Ok(entries)
     }
 
-    pub fn get_anime_library(&self) -> Result<Vec<AnimeStats>> {
+    /// One page of entries matching `query`. Only the ORDER BY is spliced
+    /// into the SQL, and only from fixed strings; filter values are bound.
+    pub fn query_library(&self, query: &LibraryQuery) -> Result<LibraryPage> {
+        let limit = query.limit.map(i64::from).unwrap_or(-1);
+        let offset = i64::from(query.offset);
+        let mut conditions = Vec::new();
+        let mut values: Vec<&dyn ToSql> = Vec::new();
+        for (column, value) in [
+            ("resolution", &query.resolution),
+            ("audio", &query.audio),
+            ("host", &query.host),
+        ] {
+            if let Some(value) = value {
+                values.push(value);
+                conditions.push(format!("{} = ?{}", column, values.len()));
+            }
+        }
+        match query.watched {
+            Some(true) => conditions.push("(watch_count > 0 OR last_watched IS NOT NULL)".into()),
+            Some(false) => conditions.push("NOT (watch_count > 0 OR last_watched IS NOT NULL)".into()),
+            None => {}
+        }
+        let filter = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+
+        let (column, natural_desc) = match query.sort {
+            EntrySort::Date => ("downloaded_at", true),
+            EntrySort::Name => ("anime_name COLLATE NOCASE", false),
+            EntrySort::Size => ("file_size", true),
+            EntrySort::Episode => ("episode", false),
+        };
+        let direction = if query.descending.unwrap_or(natural_desc) { "DESC" } else { "ASC" };
+
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
+        let total: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM library {}", filter),
+            values.as_slice(),
+            |row| row.get(0),
+        )?;
+
+        values.push(&limit);
+        values.push(&offset);
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, anime_name, slug, episode, resolution, audio, file_path, file_size,
+             thumbnail_url, downloaded_at, last_watched, watch_count, duration_seconds, host,
+             (watch_count > 0 OR last_watched IS NOT NULL) AS watched, playback_position_seconds
+             FROM library {} ORDER BY {} {}, slug, episode LIMIT ?{} OFFSET ?{}",
+            filter,
+            column,
+            direction,
+            values.len() - 1,
+            values.len()
+        ))?;
+
+        let entries = stmt.query_map(values.as_slice(), |row| {
+            Ok(LibraryEntry {
+                id: row.get(0)?,
+                anime_name: row.get(1)?,
+                slug: row.get(2)?,
+                episode: row.get(3)?,
+                resolution: row.get(4)?,
+                audio: row.get(5)?,
+                file_path: row.get(6)?,
+                file_size: row.get(7)?,
+                thumbnail_url: row.get(8)?,
+                downloaded_at: row.get(9)?,
+                last_watched: row.get(10)?,
+                watch_count: row.get(11)?,
+                duration_seconds: row.get(12)?,
+                host: row.get(13)?,
+                watched: row.get(14)?,
+                playback_position_seconds: row.get(15)?,
+            })
+        })?.collect::<Result<Vec<_>, _>>()?;
+
+        Ok(LibraryPage { entries, total })
+    }
+
+    /// Every show in the library, or only those tagged `tag`
+    pub fn get_anime_library(&self, tag: Option<&str>, sort: LibrarySort) -> Result<Vec<AnimeStats>> {
+        let conn = self.conn.lock().unwrap();
+        let order = match sort {
+            LibrarySort::Recent => "last_downloaded DESC",
+            LibrarySort::Rating => "rating IS NULL, rating DESC, last_downloaded DESC",
+        };
+        let mut stmt = conn.prepare(&format!(
             "SELECT slug, anime_name, COUNT(*) as episode_count, SUM(file_size) as total_size,
-             MAX(thumbnail_url) as thumbnail_url, MAX(downloaded_at) as last_downloaded
+             MAX(thumbnail_url) as thumbnail_url, MAX(downloaded_at) as last_downloaded,
+             (SELECT rating FROM ratings WHERE ratings.slug = library.slug) as rating
              FROM library
+             WHERE ?1 IS NULL OR slug IN (SELECT slug FROM tags WHERE tag = ?1)
              GROUP BY slug, anime_name
-             ORDER BY last_downloaded DESC"
-        )?;
+             ORDER BY {}",
+            order
+        ))?;
 
-        let stats = stmt.query_map([], |row| {
+        let stats = stmt.query_map(params![tag], |row| {
             Ok(AnimeStats {
                 slug: row.get(0)?,
                 anime_name: row.get(1)?,
@@ -150,6 +525,7 @@ impl Library {
                 total_size: row.get(3)?,
                 thumbnail_url: row.get(4)?,
                 last_downloaded: row.get(5)?,
+                rating: row.get(6)?,
             })
         })?.collect::<Result<Vec<_>, _>>()?;
 
@@ -160,7 +536,8 @@ impl Library {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
             "SELECT id, anime_name, slug, episode, resolution, audio, file_path, file_size,
-             thumbnail_url, downloaded_at, last_watched, watch_count, duration_seconds, host
+             thumbnail_url, downloaded_at, last_watched, watch_count, duration_seconds, host,
+             (watch_count > 0 OR last_watched IS NOT NULL) AS watched, playback_position_seconds
              FROM library WHERE slug = ?1 ORDER BY episode ASC"
         )?;
 
@@ -180,13 +557,33 @@ impl Library {
                 watch_count: row.get(11)?,
                 duration_seconds: row.get(12)?,
                 host: row.get(13)?,
+                watched: row.get(14)?,
+                playback_position_seconds: row.get(15)?,
             })
         })?.collect::<Result<Vec<_>, _>>()?;
 
         Ok(entries)
     }
 
-    pub fn check_episode_downloaded(&self, slug: &str, episode: i32) -> Result<bool> {
+    /// Episodes of `slug` that haven't been played yet
+    pub fn get_unwatched(&self, slug: &str) -> Result<Vec<LibraryEntry>> {
+        self.episodes_by_watched(slug, false)
+    }
+
+    /// Episodes of `slug` that have been played at least once
+    pub fn get_watched(&self, slug: &str) -> Result<Vec<LibraryEntry>> {
+        self.episodes_by_watched(slug, true)
+    }
+
+    fn episodes_by_watched(&self, slug: &str, watched: bool) -> Result<Vec<LibraryEntry>> {
+        Ok(self
+            .get_anime_episodes(slug)?
+            .into_iter()
+            .filter(|entry| entry.watched == watched)
+            .collect())
+    }
+
+    pub fn check_episode_downloaded(&self, slug: &str, episode: EpisodeNumber) -> Result<bool> {
         let conn = self.conn.lock().unwrap();
         let count: i64 = conn.query_row(
             "SELECT COUNT(*) FROM library WHERE slug = ?1 AND episode = ?2",
@@ -197,11 +594,12 @@ impl Library {
         Ok(count > 0)
     }
 
-    pub fn get_library_entry(&self, slug: &str, episode: i32) -> Result<Option<LibraryEntry>> {
+    pub fn get_library_entry(&self, slug: &str, episode: EpisodeNumber) -> Result<Option<LibraryEntry>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare(
             "SELECT id, anime_name, slug, episode, resolution, audio, file_path, file_size,
-             thumbnail_url, downloaded_at, last_watched, watch_count, duration_seconds, host
+             thumbnail_url, downloaded_at, last_watched, watch_count, duration_seconds, host,
+             (watch_count > 0 OR last_watched IS NOT NULL) AS watched, playback_position_seconds
              FROM library WHERE slug = ?1 AND episode = ?2"
         )?;
 
@@ -221,6 +619,45 @@ impl Library {
                 watch_count: row.get(11)?,
                 duration_seconds: row.get(12)?,
                 host: row.get(13)?,
+                watched: row.get(14)?,
+                playback_position_seconds: row.get(15)?,
+            })
+        });
+
+        match result {
+            Ok(entry) => Ok(Some(entry)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn get_entry_by_id(&self, id: i64) -> Result<Option<LibraryEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, anime_name, slug, episode, resolution, audio, file_path, file_size,
+             thumbnail_url, downloaded_at, last_watched, watch_count, duration_seconds, host,
+             (watch_count > 0 OR last_watched IS NOT NULL) AS watched, playback_position_seconds
+             FROM library WHERE id = ?1"
+        )?;
+
+        let result = stmt.query_row(params![id], |row| {
+            Ok(LibraryEntry {
+                id: row.get(0)?,
+                anime_name: row.get(1)?,
+                slug: row.get(2)?,
+                episode: row.get(3)?,
+                resolution: row.get(4)?,
+                audio: row.get(5)?,
+                file_path: row.get(6)?,
+                file_size: row.get(7)?,
+                thumbnail_url: row.get(8)?,
+                downloaded_at: row.get(9)?,
+                last_watched: row.get(10)?,
+                watch_count: row.get(11)?,
+                duration_seconds: row.get(12)?,
+                host: row.get(13)?,
+                watched: row.get(14)?,
+                playback_position_seconds: row.get(15)?,
             })
         });
 
@@ -236,13 +673,163 @@ impl Library {
         let now = Utc::now().timestamp();
 
         conn.execute(
-            "UPDATE library SET last_watched = ?1, watch_count = watch_count + 1 WHERE id = ?2",
+            "UPDATE library SET last_watched = ?1, watch_count = watch_count + 1,
+             playback_position_seconds = NULL WHERE id = ?2",
             params![now, id],
         )?;
 
         Ok(())
     }
 
+    /// Downloaded episodes of `slug` and the regular episodes missing in
+    /// between. Specials like 12.5 are listed but never count as a gap.
+    pub fn get_episode_gaps(&self, slug: &str) -> Result<EpisodeGaps> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT episode FROM library WHERE slug = ?1 ORDER BY episode")?;
+        let downloaded = stmt
+            .query_map(params![slug], |row| row.get::<_, EpisodeNumber>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let whole: std::collections::BTreeSet<u32> = downloaded
+            .iter()
+            .filter(|ep| ep.is_whole())
+            .map(|ep| ep.whole())
+            .collect();
+        let missing = match (whole.first(), whole.last()) {
+            (Some(&first), Some(&last)) => (first..=last)
+                .filter(|n| !whole.contains(n))
+                .map(EpisodeNumber::from)
+                .collect(),
+            _ => Vec::new(),
+        };
+        Ok(EpisodeGaps { downloaded, missing })
+    }
+
+    /// Point an entry at a file that moved
+    pub fn update_file_path(&self, id: i64, file_path: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE library SET file_path = ?1 WHERE id = ?2",
+            params![file_path, id],
+        )
+        .context("Failed to update file path")?;
+        Ok(())
+    }
+
+    /// Move one entry's file to `new_path` and repoint it. For a kept HLS
+    /// folder `new_path` is where its `index.m3u8` should end up.
+    pub fn relocate_entry(&self, id: i64, new_path: &Path) -> Result<RelocatedEntry> {
+        let entry = self
+            .get_entry_by_id(id)?
+            .ok_or_else(|| anyhow::anyhow!("Library entry {} not found", id))?;
+        let mut moved = self.relocate(vec![(entry, new_path.to_path_buf())])?;
+        Ok(moved.remove(0))
+    }
+
+    /// Move every episode of `slug` into `new_dir`, keeping file (or HLS folder) names
+    pub fn relocate_anime(&self, slug: &str, new_dir: &Path) -> Result<Vec<RelocatedEntry>> {
+        let targets = self
+            .get_anime_episodes(slug)?
+            .into_iter()
+            .map(|entry| {
+                let old = Path::new(&entry.file_path);
+                let moving = files_to_move(old);
+                let mut dst = new_dir.join(moving.file_name().unwrap_or_default());
+                if moving != old {
+                    dst = dst.join(old.file_name().unwrap_or_default());
+                }
+                (entry, dst)
+            })
+            .collect::<Vec<_>>();
+        if targets.is_empty() {
+            anyhow::bail!("No library entries for {}", slug);
+        }
+        self.relocate(targets)
+    }
+
+    /// Move files on disk and update their paths in one transaction. Any
+    /// failure moves back what was already moved and leaves the rows alone.
+    fn relocate(&self, targets: Vec<(LibraryEntry, PathBuf)>) -> Result<Vec<RelocatedEntry>> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().context("Failed to start relocation")?;
+        let mut moved: Vec<(PathBuf, PathBuf)> = Vec::new();
+        let undo = |moved: &[(PathBuf, PathBuf)]| {
+            for (src, dst) in moved.iter().rev() {
+                if let Err(e) = crate::download::move_path(dst, src) {
+                    log_warn!("Could not move {} back to {}: {}", dst.display(), src.display(), e);
+                }
+            }
+        };
+
+        let mut relocated = Vec::with_capacity(targets.len());
+        for (entry, new_path) in targets {
+            let old_path = Path::new(&entry.file_path);
+            let (src, dst) = (files_to_move(old_path), files_to_move(&new_path));
+            let step = || -> Result<()> {
+                if src == dst {
+                    return Ok(());
+                }
+                if !src.exists() {
+                    anyhow::bail!("{} does not exist", src.display());
+                }
+                if dst.exists() {
+                    anyhow::bail!("{} already exists", dst.display());
+                }
+                tx.execute(
+                    "UPDATE library SET file_path = ?1 WHERE id = ?2",
+                    params![new_path.to_string_lossy(), entry.id],
+                )
+                .context("Failed to update file path")?;
+                if let Some(parent) = dst.parent() {
+                    std::fs::create_dir_all(parent)
+                        .with_context(|| format!("Failed to create {}", parent.display()))?;
+                }
+                crate::download::move_path(src, dst)
+                    .with_context(|| format!("Failed to move {} to {}", src.display(), dst.display()))
+            };
+            if let Err(e) = step() {
+                undo(&moved);
+                return Err(e);
+            }
+            if src != dst {
+                moved.push((src.to_path_buf(), dst.to_path_buf()));
+            }
+            relocated.push(RelocatedEntry {
+                id: entry.id,
+                old_path: entry.file_path,
+                new_path: new_path.to_string_lossy().to_string(),
+            });
+        }
+
+        if let Err(e) = tx.commit() {
+            undo(&moved);
+            return Err(e).context("Failed to save relocated paths");
+        }
+        Ok(relocated)
+    }
+
+    /// Remember where playback of an entry stopped
+    pub fn update_playback_position(&self, id: i64, seconds: f64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE library SET playback_position_seconds = ?1 WHERE id = ?2",
+            params![seconds.max(0.0), id],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_playback_position(&self, id: i64) -> Result<Option<f64>> {
+        let conn = self.conn.lock().unwrap();
+        let position = conn
+            .query_row(
+                "SELECT playback_position_seconds FROM library WHERE id = ?1",
+                params![id],
+                |row| row.get::<_, Option<f64>>(0),
+            )
+            .optional()?;
+        Ok(position.flatten())
+    }
+
     pub fn delete_library_entry(&self, id: i64) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute("DELETE FROM library WHERE id = ?1", params![id])?;
@@ -252,9 +839,101 @@ impl Library {
     pub fn delete_anime(&self, slug: &str) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         conn.execute("DELETE FROM library WHERE slug = ?1", params![slug])?;
+        conn.execute("DELETE FROM tags WHERE slug = ?1", params![slug])?;
+        conn.execute("DELETE FROM ratings WHERE slug = ?1", params![slug])?;
+        Ok(())
+    }
+
+    pub fn add_tag(&self, slug: &str, tag: &str) -> Result<()> {
+        let tag = tag.trim();
+        if tag.is_empty() {
+            anyhow::bail!("Tag can't be empty");
+        }
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR IGNORE INTO tags (slug, tag, added_at) VALUES (?1, ?2, ?3)",
+            params![slug, tag, Utc::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_tag(&self, slug: &str, tag: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM tags WHERE slug = ?1 AND tag = ?2",
+            params![slug, tag.trim()],
+        )?;
         Ok(())
     }
 
+    /// Tags on one show, or with no `slug` every tag in use plus `DEFAULT_TAGS`
+    pub fn list_tags(&self, slug: Option<&str>) -> Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT tag FROM tags WHERE ?1 IS NULL OR slug = ?1
+             GROUP BY tag ORDER BY MIN(added_at)"
+        )?;
+        let used = stmt
+            .query_map(params![slug], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        if slug.is_some() {
+            return Ok(used);
+        }
+        let mut tags: Vec<String> = DEFAULT_TAGS.iter().map(|t| t.to_string()).collect();
+        for tag in used {
+            if !tags.iter().any(|t| t.eq_ignore_ascii_case(&tag)) {
+                tags.push(tag);
+            }
+        }
+        Ok(tags)
+    }
+
+    pub fn get_anime_by_tag(&self, tag: &str) -> Result<Vec<AnimeStats>> {
+        self.get_anime_library(Some(tag), LibrarySort::Recent)
+    }
+
+    /// Rate a show 1-10, or clear its rating with `None`
+    pub fn set_anime_rating(&self, slug: &str, rating: Option<i64>) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        match rating {
+            Some(r) if !(1..=10).contains(&r) => anyhow::bail!("Rating must be between 1 and 10, got {}", r),
+            Some(r) => conn.execute(
+                "INSERT OR REPLACE INTO ratings (slug, rating) VALUES (?1, ?2)",
+                params![slug, r],
+            )?,
+            None => conn.execute("DELETE FROM ratings WHERE slug = ?1", params![slug])?,
+        };
+        Ok(())
+    }
+
+    fn all_ratings(&self) -> Result<Vec<AnimeRating>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT slug, rating FROM ratings")?;
+        let ratings = stmt
+            .query_map([], |row| {
+                Ok(AnimeRating {
+                    slug: row.get(0)?,
+                    rating: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ratings)
+    }
+
+    fn all_tags(&self) -> Result<Vec<AnimeTag>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT slug, tag FROM tags ORDER BY added_at")?;
+        let tags = stmt
+            .query_map([], |row| {
+                Ok(AnimeTag {
+                    slug: row.get(0)?,
+                    tag: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tags)
+    }
+
     pub fn get_library_stats(&self) -> Result<LibraryStats> {
         let conn = self.conn.lock().unwrap();
 
@@ -275,13 +954,182 @@ impl Library {
         })
     }
 
+    pub fn get_extended_stats(&self) -> Result<ExtendedLibraryStats> {
+        use chrono::{Datelike, Duration, TimeZone};
+
+        let conn = self.conn.lock().unwrap();
+        let now = Utc::now();
+        let month_start = Utc
+            .with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+            .single()
+            .map(|d| d.timestamp())
+            .unwrap_or(0);
+        let week_start = (now - Duration::days(7)).timestamp();
+
+        let avg_file_size: i64 = conn.query_row(
+            "SELECT CAST(COALESCE(AVG(file_size), 0) AS INTEGER) FROM library",
+            [],
+            |row| row.get(0),
+        )?;
+
+        let largest = conn.query_row(
+            "SELECT anime_name, SUM(file_size) as total_size
+             FROM library
+             GROUP BY slug
+             ORDER BY total_size DESC
+             LIMIT 1",
+            [],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)),
+        );
+        let (largest_anime, largest_anime_size) = match largest {
+            Ok((name, size)) => (Some(name), size),
+            Err(rusqlite::Error::QueryReturnedNoRows) => (None, 0),
+            Err(e) => return Err(e.into()),
+        };
+
+        let bytes_this_month: i64 = conn.query_row(
+            "SELECT COALESCE(SUM(file_size), 0) FROM library WHERE downloaded_at >= ?1",
+            params![month_start],
+            |row| row.get(0),
+        )?;
+
+        let downloads_this_week: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM library WHERE downloaded_at >= ?1",
+            params![week_start],
+            |row| row.get(0),
+        )?;
+
+        Ok(ExtendedLibraryStats {
+            avg_file_size,
+            largest_anime,
+            largest_anime_size,
+            bytes_this_month,
+            downloads_this_week,
+        })
+    }
+
+    /// Distinct resolutions present in the library, for filter dropdowns
+    pub fn distinct_resolutions(&self) -> Result<Vec<String>> {
+        self.distinct_values("resolution")
+    }
+
+    /// Distinct audio types present in the library, for filter dropdowns
+    pub fn distinct_audio(&self) -> Result<Vec<String>> {
+        self.distinct_values("audio")
+    }
+
+    // `column` is always one of our own literals, never user input
+    fn distinct_values(&self, column: &str) -> Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT DISTINCT {col} FROM library
+             WHERE {col} IS NOT NULL AND {col} != ''
+             ORDER BY {col}",
+            col = column
+        ))?;
+        let values = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(values)
+    }
+
+    /// Find slugs grouped under several names, and episodes stored more than
+    /// once for what is the same show once names are normalised.
+    pub fn find_duplicate_anime(&self) -> Result<DuplicateReport> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn.prepare(
+            "SELECT slug, anime_name, COUNT(*) FROM library
+             WHERE slug IN (
+                SELECT slug FROM library GROUP BY slug HAVING COUNT(DISTINCT anime_name) > 1
+             )
+             GROUP BY slug, anime_name
+             ORDER BY slug, COUNT(*) DESC",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut anime: Vec<DuplicateAnime> = Vec::new();
+        for (slug, anime_name, episode_count) in rows {
+            let variant = NameVariant { anime_name, episode_count };
+            match anime.last_mut() {
+                Some(last) if last.slug == slug => last.names.push(variant),
+                _ => anime.push(DuplicateAnime { slug, names: vec![variant] }),
+            }
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT MIN(anime_name), episode, GROUP_CONCAT(id) FROM library
+             GROUP BY LOWER(TRIM(anime_name)), episode
+             HAVING COUNT(*) > 1
+             ORDER BY LOWER(TRIM(anime_name)), episode",
+        )?;
+        let episodes = stmt
+            .query_map([], |row| {
+                let ids: String = row.get(2)?;
+                Ok(DuplicateEpisode {
+                    anime_name: row.get(0)?,
+                    episode: row.get(1)?,
+                    entry_ids: ids.split(',').filter_map(|id| id.parse().ok()).collect(),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(DuplicateReport { anime, episodes })
+    }
+
+    /// File every episode of `slug` under `canonical_name`. Returns rows changed.
+    pub fn merge_duplicate_anime(&self, slug: &str, canonical_name: &str) -> Result<usize> {
+        let canonical_name = canonical_name.trim();
+        if canonical_name.is_empty() {
+            anyhow::bail!("Canonical name cannot be empty");
+        }
+        let conn = self.conn.lock().unwrap();
+        let changed = conn.execute(
+            "UPDATE library SET anime_name = ?2 WHERE slug = ?1 AND anime_name != ?2",
+            params![slug, canonical_name],
+        )?;
+        Ok(changed)
+    }
+
+    /// Shows whose name contains every word of `query`, best match first
     pub fn search_library(&self, query: &str) -> Result<Vec<AnimeStats>> {
         let conn = self.conn.lock().unwrap();
+        if let Some(terms) = fts_query(query).filter(|_| self.fts) {
+            let mut stmt = conn.prepare(
+                "WITH hits AS (
+                    SELECT rowid AS id, rank AS score FROM library_fts WHERE library_fts MATCH ?1
+                 )
+                 SELECT l.slug, l.anime_name, COUNT(*) as episode_count, SUM(l.file_size) as total_size,
+                 l.thumbnail_url, MAX(l.downloaded_at) as last_downloaded,
+                 (SELECT rating FROM ratings WHERE ratings.slug = l.slug) as rating, MIN(hits.score) as score
+                 FROM hits JOIN library l ON l.id = hits.id
+                 GROUP BY l.slug, l.anime_name
+                 ORDER BY score, last_downloaded DESC"
+            )?;
+            let stats = stmt.query_map(params![terms], |row| {
+                Ok(AnimeStats {
+                    slug: row.get(0)?,
+                    anime_name: row.get(1)?,
+                    episode_count: row.get(2)?,
+                    total_size: row.get(3)?,
+                    thumbnail_url: row.get(4)?,
+                    last_downloaded: row.get(5)?,
+                    rating: row.get(6)?,
+                })
+            })?.collect::<Result<Vec<_>, _>>()?;
+            return Ok(stats);
+        }
+
         let search_pattern = format!("%{}%", query);
 
         let mut stmt = conn.prepare(
             "SELECT slug, anime_name, COUNT(*) as episode_count, SUM(file_size) as total_size,
-             thumbnail_url, MAX(downloaded_at) as last_downloaded
+             thumbnail_url, MAX(downloaded_at) as last_downloaded,
+             (SELECT rating FROM ratings WHERE ratings.slug = library.slug) as rating
              FROM library
              WHERE anime_name LIKE ?1
              GROUP BY slug, anime_name
@@ -296,6 +1144,7 @@ impl Library {
                 total_size: row.get(3)?,
                 thumbnail_url: row.get(4)?,
                 last_downloaded: row.get(5)?,
+                rating: row.get(6)?,
             })
         })?.collect::<Result<Vec<_>, _>>()?;
 
@@ -303,32 +1152,69 @@ impl Library {
     }
 
     pub fn export_library(&self) -> Result<String> {
-        let entries = self.get_library_entries()?;
-        serde_json::to_string_pretty(&entries).context("Failed to serialize library")
+        let export = LibraryExport::Full {
+            entries: self.get_library_entries()?,
+            tags: self.all_tags()?,
+            ratings: self.all_ratings()?,
+        };
+        serde_json::to_string_pretty(&export).context("Failed to serialize library")
     }
 
     pub fn import_library(&self, json: &str) -> Result<usize> {
-        let entries: Vec<LibraryEntry> = serde_json::from_str(json)
-            .context("Failed to parse library JSON")?;
+        self.import_library_with(json, |_, _, _| true)
+    }
+
+    /// `import_library`, calling `on_entry(done, total, anime_name)` after
+    /// each entry. Returning false stops the import; tags and ratings are
+    /// then skipped too.
+    pub fn import_library_with<F>(&self, json: &str, mut on_entry: F) -> Result<usize>
+    where
+        F: FnMut(usize, usize, &str) -> bool,
+    {
+        let (entries, tags, ratings) = match serde_json::from_str::<LibraryExport>(json)
+            .context("Failed to parse library JSON")?
+        {
+            LibraryExport::Full { entries, tags, ratings } => (entries, tags, ratings),
+            LibraryExport::Entries(entries) => (entries, Vec::new(), Vec::new()),
+        };
 
         let conn = self.conn.lock().unwrap();
         let mut imported = 0;
+        let total = entries.len();
 
-        for entry in entries {
+        for (i, entry) in entries.into_iter().enumerate() {
             let result = conn.execute(
                 "INSERT OR REPLACE INTO library
-                (anime_name, slug, episode, resolution, audio, file_path, file_size, thumbnail_url, downloaded_at, last_watched, watch_count, duration_seconds, host)
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                (anime_name, slug, episode, resolution, audio, file_path, file_size, thumbnail_url, downloaded_at, last_watched, watch_count, duration_seconds, host, playback_position_seconds)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
                 params![
                     entry.anime_name, entry.slug, entry.episode, entry.resolution, entry.audio,
                     entry.file_path, entry.file_size, entry.thumbnail_url, entry.downloaded_at,
-                    entry.last_watched, entry.watch_count, entry.duration_seconds, entry.host
+                    entry.last_watched, entry.watch_count, entry.duration_seconds, entry.host,
+                    entry.playback_position_seconds
                 ],
             );
 
             if result.is_ok() {
                 imported += 1;
             }
+            if !on_entry(i + 1, total, &entry.anime_name) {
+                return Ok(imported);
+            }
+        }
+
+        let now = Utc::now().timestamp();
+        for tag in tags {
+            conn.execute(
+                "INSERT OR IGNORE INTO tags (slug, tag, added_at) VALUES (?1, ?2, ?3)",
+                params![tag.slug, tag.tag, now],
+            )?;
+        }
+        for rating in ratings.iter().filter(|r| (1..=10).contains(&r.rating)) {
+            conn.execute(
+                "INSERT OR REPLACE INTO ratings (slug, rating) VALUES (?1, ?2)",
+                params![rating.slug, rating.rating],
+            )?;
         }
 
         Ok(imported)
@@ -343,3 +1229,47 @@ impl Library {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrates_unversioned_database() {
+        let dir = std::env::temp_dir().join(format!("library-migrate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("library.db");
+        let _ = std::fs::remove_file(&db_path);
+
+        // A database from before versioning: the original table at user_version 0
+        {
+            let conn = Connection::open(&db_path).unwrap();
+            conn.execute_batch(MIGRATIONS[0]).unwrap();
+            conn.execute(
+                "INSERT INTO library (anime_name, slug, episode, file_path, file_size, downloaded_at, host)
+                VALUES ('Frieren', 'frieren', 10, '/videos/Frieren/10.mp4', 1024, 0, 'https://animepahe.ru')",
+                [],
+            )
+            .unwrap();
+        }
+
+        drop(Library::new(db_path.clone()).unwrap());
+
+        let conn = Connection::open(&db_path).unwrap();
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(version as usize, MIGRATIONS.len());
+        let (name, file_path, position): (String, String, Option<f64>) = conn
+            .query_row(
+                "SELECT anime_name, file_path, playback_position_seconds FROM library WHERE slug = 'frieren'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(name, "Frieren");
+        assert_eq!(file_path, "/videos/Frieren/10.mp4");
+        assert_eq!(position, None);
+
+        drop(conn);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
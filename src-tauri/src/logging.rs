@@ -0,0 +1,194 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use chrono::Local;
+
+/// Daily log files older than this many days are pruned on rotation
+const KEEP_DAYS: usize = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 0,
+    Warn = 1,
+    Info = 2,
+    Debug = 3,
+}
+
+impl Level {
+    pub fn parse(value: &str) -> Option<Level> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "error" => Some(Level::Error),
+            "warn" | "warning" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+        }
+    }
+
+    fn from_u8(value: u8) -> Level {
+        match value {
+            0 => Level::Error,
+            1 => Level::Warn,
+            2 => Level::Info,
+            _ => Level::Debug,
+        }
+    }
+}
+
+struct Logger {
+    dir: PathBuf,
+    level: AtomicU8,
+    // Date the open file belongs to, so we know when to roll over
+    file: Mutex<Option<(String, File)>>,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Start writing diagnostics to `<config_dir>/logs/`. Until this runs,
+/// messages only go to stderr.
+pub fn init(config_dir: &Path, level: &str) {
+    let dir = config_dir.join("logs");
+    if let Err(e) = fs::create_dir_all(&dir) {
+        eprintln!("Failed to create log directory {}: {}", dir.display(), e);
+        return;
+    }
+    let level = Level::parse(level).unwrap_or(Level::Info);
+    let _ = LOGGER.set(Logger {
+        dir,
+        level: AtomicU8::new(level as u8),
+        file: Mutex::new(None),
+    });
+}
+
+pub fn set_level(level: &str) {
+    if let (Some(logger), Some(level)) = (LOGGER.get(), Level::parse(level)) {
+        logger.level.store(level as u8, Ordering::Relaxed);
+    }
+}
+
+pub fn log_dir() -> Option<PathBuf> {
+    LOGGER.get().map(|logger| logger.dir.clone())
+}
+
+/// Path of today's log file
+pub fn current_log_path() -> Option<PathBuf> {
+    LOGGER
+        .get()
+        .map(|logger| logger.dir.join(file_name(&today())))
+}
+
+/// Echo to stderr as before, and append to the log file when `level`
+/// passes the configured threshold. Use the `log_*!` macros instead.
+pub fn write(level: Level, args: fmt::Arguments) {
+    let message = args.to_string();
+    eprintln!("{}", message);
+
+    let Some(logger) = LOGGER.get() else {
+        return;
+    };
+    if level > Level::from_u8(logger.level.load(Ordering::Relaxed)) {
+        return;
+    }
+
+    let now = Local::now();
+    let date = now.format("%Y-%m-%d").to_string();
+    let mut guard = logger.file.lock().unwrap();
+
+    let needs_rotation = !matches!(guard.as_ref(), Some((open_date, _)) if *open_date == date);
+    if needs_rotation {
+        let path = logger.dir.join(file_name(&date));
+        match OpenOptions::new().create(true).append(true).open(&path) {
+            Ok(file) => {
+                *guard = Some((date, file));
+                prune_old_logs(&logger.dir);
+            }
+            Err(e) => {
+                eprintln!("Failed to open log file {}: {}", path.display(), e);
+                *guard = None;
+                return;
+            }
+        }
+    }
+
+    if let Some((_, file)) = guard.as_mut() {
+        let _ = writeln!(
+            file,
+            "{} {:<5} {}",
+            now.format("%Y-%m-%d %H:%M:%S%.3f"),
+            level.as_str(),
+            message
+        );
+    }
+}
+
+fn today() -> String {
+    Local::now().format("%Y-%m-%d").to_string()
+}
+
+fn file_name(date: &str) -> String {
+    format!("animepahe-dl-{}.log", date)
+}
+
+fn prune_old_logs(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    // Dated names sort chronologically
+    let mut logs: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .map(|n| n.starts_with("animepahe-dl-") && n.ends_with(".log"))
+                .unwrap_or(false)
+        })
+        .collect();
+    logs.sort();
+    if logs.len() > KEEP_DAYS {
+        for old in &logs[..logs.len() - KEEP_DAYS] {
+            let _ = fs::remove_file(old);
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        $crate::logging::write($crate::logging::Level::Error, format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => {
+        $crate::logging::write($crate::logging::Level::Warn, format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        $crate::logging::write($crate::logging::Level::Info, format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => {
+        $crate::logging::write($crate::logging::Level::Debug, format_args!($($arg)*))
+    };
+}
@@ -1,22 +1,89 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+#[macro_use]
+mod logging;
+
 mod api;
 mod commands;
 mod download;
 mod download_tracker;
 mod library;
+mod metrics;
+mod network;
+mod nfo;
+mod notifications;
 mod player;
 mod scrape;
 mod settings;
+mod subtitles;
 mod video_server;
 
 use crate::settings::AppState;
-use crate::commands::DownloadState;
+use crate::commands::{BulkOpState, DownloadState};
 use crate::download_tracker::DownloadTracker;
 use crate::library::Library;
+use crate::metrics::Metrics;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tauri::{Manager, menu::{Menu, MenuItem}, tray::{TrayIconBuilder, TrayIconEvent}};
+use tauri::{AppHandle, Emitter, Manager, WindowEvent, Wry, menu::{Menu, MenuItem, PredefinedMenuItem, Submenu}, tray::{TrayIconBuilder, TrayIconEvent}};
+
+/// Tray menu ids for per-download cancel entries: `cancel:<download id>`
+const TRAY_CANCEL_PREFIX: &str = "cancel:";
+
+/// Show/Hide/Quit, preceded by an "Active downloads" submenu with one
+/// cancel entry per running episode. Returns the menu and those entries.
+fn tray_menu(
+    app: &AppHandle,
+    active: &[commands::ActiveSummary],
+) -> tauri::Result<(Menu<Wry>, Vec<MenuItem<Wry>>)> {
+    let show_item = MenuItem::with_id(app, "show", "Show", true, None::<&str>)?;
+    let hide_item = MenuItem::with_id(app, "hide", "Hide", true, None::<&str>)?;
+    let quit_item = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
+    let menu = Menu::with_items(app, &[&show_item, &hide_item, &quit_item])?;
+
+    let cancel_items = active
+        .iter()
+        .map(|item| {
+            let id = format!("{}{}", TRAY_CANCEL_PREFIX, item.download_id);
+            MenuItem::with_id(app, id, format!("Cancel {}", item.label), true, None::<&str>)
+        })
+        .collect::<tauri::Result<Vec<_>>>()?;
+    if !cancel_items.is_empty() {
+        let submenu = Submenu::with_id(app, "active", "Active downloads", true)?;
+        for item in &cancel_items {
+            submenu.append(item)?;
+        }
+        menu.insert(&submenu, 0)?;
+        menu.insert(&PredefinedMenuItem::separator(app)?, 1)?;
+    }
+    Ok((menu, cancel_items))
+}
+
+/// Exit, asking first if downloads are still running
+fn confirm_quit(app: &AppHandle) {
+    let active = app.state::<DownloadState>().active_count();
+    if active == 0 {
+        app.exit(0);
+        return;
+    }
+
+    use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+    let app_handle = app.clone();
+    app.dialog()
+        .message(format!(
+            "{} download{} still running and will be interrupted. Quit anyway?",
+            active,
+            if active == 1 { " is" } else { "s are" }
+        ))
+        .title("Quit Animepahe DL Desktop")
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancel)
+        .show(move |confirmed| {
+            if confirmed {
+                app_handle.exit(0);
+            }
+        });
+}
 
 // Video server state
 pub struct VideoServerState {
@@ -41,15 +108,39 @@ fn main() {
         server_url: Arc::new(RwLock::new(None)),
     };
 
+    let app_state = AppState::init();
+    logging::init(&config_dir, &app_state.settings.lock().unwrap().log_level);
+    let max_connections_per_host = app_state.settings.lock().unwrap().max_connections_per_host;
+    {
+        let settings = app_state.settings.lock().unwrap();
+        if let Err(e) = network::configure(&settings.proxy_url, &settings.user_agent) {
+            log_warn!("Ignoring proxy setting: {:#}", e);
+        }
+    }
+    let metrics = Metrics::new();
+
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_notification::init())
-        .manage(AppState::init())
-        .manage(DownloadState::new())
+        .manage(app_state)
+        .manage(DownloadState::new(max_connections_per_host, metrics.clone()))
+        .manage(metrics)
+        .manage(BulkOpState::new())
         .manage(download_tracker)
         .manage(library)
         .manage(video_server_state)
+        .on_window_event(|window, event| {
+            if let WindowEvent::CloseRequested { api, .. } = event {
+                api.prevent_close();
+                let close_to_tray = window.state::<AppState>().settings.lock().unwrap().close_to_tray;
+                if close_to_tray {
+                    let _ = window.hide();
+                } else {
+                    confirm_quit(window.app_handle());
+                }
+            }
+        })
         .setup(|app| {
             // Start video streaming server
             let server_state = app.state::<VideoServerState>();
@@ -65,24 +156,118 @@ fn main() {
 
                 match video_server::start_video_server(ffmpeg_path).await {
                     Ok(url) => {
-                        println!("Video streaming server started at: {}", url);
+                        log_info!("Video streaming server started at: {}", url);
                         *server_url_clone.write().await = Some(url);
                     }
                     Err(e) => {
-                        eprintln!("Failed to start video server: {}", e);
+                        log_error!("Failed to start video server: {}", e);
                     }
                 }
             });
 
-            // Setup system tray
-            let show_item = MenuItem::with_id(app, "show", "Show", true, None::<&str>)?;
-            let hide_item = MenuItem::with_id(app, "hide", "Hide", true, None::<&str>)?;
-            let quit_item = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
+            // Persist download progress at most once per FLUSH_INTERVAL
+            let tracker = app.state::<DownloadTracker>().inner().clone();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    tokio::time::sleep(download_tracker::FLUSH_INTERVAL).await;
+                    if let Err(e) = tracker.flush() {
+                        log_warn!("{}", e);
+                    }
+                }
+            });
 
-            let menu = Menu::with_items(app, &[&show_item, &hide_item, &quit_item])?;
+            tauri::async_runtime::spawn(commands::auto_resume_downloads(app.handle().clone()));
+
+            // Summarize active downloads in the tray tooltip, and list them in
+            // the menu: rebuilt when the set changes, relabelled in between
+            let tray_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut shown = commands::TRAY_TOOLTIP.to_string();
+                let mut listed: Vec<commands::ActiveSummary> = Vec::new();
+                let mut cancel_items: Vec<MenuItem<Wry>> = Vec::new();
+                loop {
+                    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                    let Some(tray) = tray_handle.tray_by_id("main") else {
+                        continue;
+                    };
+                    let download_state = tray_handle.state::<DownloadState>();
+
+                    let summary = download_state.tray_summary();
+                    if summary != shown && tray.set_tooltip(Some(&summary)).is_ok() {
+                        shown = summary;
+                    }
+
+                    let active = download_state.active_summaries().await;
+                    let same_set = active.len() == listed.len()
+                        && active.iter().zip(&listed).all(|(a, b)| a.download_id == b.download_id);
+                    if !same_set {
+                        match tray_menu(&tray_handle, &active) {
+                            Ok((menu, items)) => {
+                                if tray.set_menu(Some(menu)).is_ok() {
+                                    cancel_items = items;
+                                    listed = active;
+                                }
+                            }
+                            Err(e) => log_warn!("Failed to rebuild tray menu: {}", e),
+                        }
+                    } else if active != listed {
+                        for (item, summary) in cancel_items.iter().zip(&active) {
+                            let _ = item.set_text(format!("Cancel {}", summary.label));
+                        }
+                        listed = active;
+                    }
+                }
+            });
+
+            // Pause downloads while on a metered connection, if enabled
+            let metered_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                if !network::metered_detection_supported() {
+                    return;
+                }
+                let mut paused_for_metered = false;
+                loop {
+                    tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+
+                    let enabled = metered_handle
+                        .state::<AppState>()
+                        .settings
+                        .lock()
+                        .unwrap()
+                        .pause_on_metered;
+                    let metered = enabled
+                        && tauri::async_runtime::spawn_blocking(network::is_metered)
+                            .await
+                            .ok()
+                            .flatten()
+                            .unwrap_or(false);
+
+                    if metered != paused_for_metered {
+                        paused_for_metered = metered;
+                        let download_state = metered_handle.state::<DownloadState>();
+                        download_state.set_metered_paused(metered);
+                        log_info!(
+                            "{} downloads (metered connection: {})",
+                            if metered { "Pausing" } else { "Resuming" },
+                            metered
+                        );
+                        let _ = metered_handle.emit(
+                            "downloads-paused",
+                            commands::PausedPayload {
+                                // A user pause outlives the metered connection ending
+                                paused: download_state.is_paused(),
+                                reason: download_state.pause_reason().unwrap_or("metered").into(),
+                            },
+                        );
+                    }
+                }
+            });
+
+            // Setup system tray
+            let (menu, _) = tray_menu(app.handle(), &[])?;
 
             let _tray = TrayIconBuilder::with_id("main")
-                .tooltip("Animepahe DL Desktop")
+                .tooltip(commands::TRAY_TOOLTIP)
                 .icon(app.default_window_icon().unwrap().clone())
                 .menu(&menu)
                 .on_menu_event(|app, event| match event.id.as_ref() {
@@ -97,10 +282,15 @@ fn main() {
                             let _ = window.hide();
                         }
                     }
-                    "quit" => {
-                        app.exit(0);
+                    "quit" => confirm_quit(app),
+                    id => {
+                        if let Some(download_id) = id.strip_prefix(TRAY_CANCEL_PREFIX) {
+                            tauri::async_runtime::spawn(commands::cancel_from_tray(
+                                app.clone(),
+                                download_id.to_string(),
+                            ));
+                        }
                     }
-                    _ => {}
                 })
                 .on_tray_icon_event(|tray, event| {
                     if let TrayIconEvent::Click { .. } = event {
@@ -121,33 +311,82 @@ fn main() {
             commands::fetch_featured_anime,
             commands::fetch_latest_releases,
             commands::fetch_episodes,
+            commands::fetch_episode_pages,
+            commands::fetch_anime_details,
+            commands::select_episodes_by_air_date,
+            commands::validate_episode_selection,
             commands::preview_sources,
             commands::resolve_video_url,
+            commands::supported_providers,
+            commands::test_extraction,
             commands::start_download,
             commands::check_requirements,
+            commands::metered_detection_supported,
+            commands::supported_hwaccels,
             commands::open_path,
             commands::get_app_version,
             commands::cancel_download,
+            commands::cancel_all_downloads,
+            commands::pause_download,
+            commands::resume_paused_download,
             commands::get_incomplete_downloads,
+            commands::set_download_priority,
             commands::resume_download,
+            commands::retry_all_failed,
             commands::remove_download_record,
             commands::clear_completed_downloads,
+            commands::clear_downloads,
             commands::validate_download_integrity,
+            commands::validate_download_integrity_deep,
+            commands::scan_library,
+            commands::relocate_entry,
+            commands::relocate_anime,
+            commands::import_local_files,
             commands::check_episode_downloaded,
             commands::get_library_entry,
             commands::get_library_entries,
+            commands::query_library,
+            commands::get_episode_gaps,
             commands::get_anime_library,
+            commands::add_tag,
+            commands::remove_tag,
+            commands::list_tags,
+            commands::get_anime_by_tag,
+            commands::set_anime_rating,
             commands::get_anime_episodes,
+            commands::get_unwatched_episodes,
+            commands::get_watched_episodes,
             commands::mark_episode_watched,
+            commands::update_playback_position,
+            commands::get_playback_position,
             commands::delete_library_entry,
             commands::delete_anime_from_library,
             commands::get_library_stats,
+            commands::get_extended_stats,
+            commands::get_performance_metrics,
+            commands::get_cookie_state,
+            commands::add_host_profile,
+            commands::remove_host_profile,
+            commands::select_host_profile,
+            commands::get_log_path,
+            commands::open_logs,
+            commands::convert_subtitles,
+            commands::get_distinct_resolutions,
+            commands::get_distinct_audio,
+            commands::find_duplicate_anime,
+            commands::merge_duplicate_anime,
             commands::search_library,
             commands::export_library,
             commands::import_library,
             commands::export_library_to_file,
             commands::import_library_from_file,
+            commands::compact_databases,
             commands::migrate_library_posters,
+            commands::download_poster,
+            commands::cancel_bulk_op,
+            commands::pause_queue,
+            commands::resume_queue,
+            commands::get_queue_state,
             commands::fetch_image_as_base64,
             commands::play_notification_sound,
             commands::update_tray_title,
@@ -158,8 +397,17 @@ fn main() {
             commands::get_video_stream_url,
             commands::get_compatible_video_path,
             commands::validate_video_file,
-            commands::get_video_metadata
+            commands::get_video_metadata,
+            commands::probe_media_info,
+            commands::get_media_info
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                if let Err(e) = app.state::<DownloadTracker>().flush() {
+                    log_warn!("{}", e);
+                }
+            }
+        });
 }
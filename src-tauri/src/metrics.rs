@@ -0,0 +1,88 @@
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How many recent samples are kept per stage
+const MAX_SAMPLES: usize = 200;
+
+/// Pipeline stages we time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Search,
+    SessionLookup,
+    CandidateScrape,
+    Extraction,
+    SegmentDownload,
+    Decrypt,
+    Mux,
+    Episode,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StageMetrics {
+    pub stage: Stage,
+    pub count: usize,
+    pub p50_ms: u64,
+    pub p90_ms: u64,
+    pub p99_ms: u64,
+    pub max_ms: u64,
+}
+
+/// Bounded ring of recent stage durations, shared across commands
+#[derive(Clone, Default)]
+pub struct Metrics {
+    samples: Arc<Mutex<HashMap<Stage, VecDeque<u64>>>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, stage: Stage, elapsed: Duration) {
+        let mut samples = self.samples.lock().unwrap();
+        let ring = samples.entry(stage).or_default();
+        if ring.len() == MAX_SAMPLES {
+            ring.pop_front();
+        }
+        ring.push_back(elapsed.as_millis() as u64);
+    }
+
+    /// Record the time since `started`
+    pub fn record_since(&self, stage: Stage, started: Instant) {
+        self.record(stage, started.elapsed());
+    }
+
+    pub fn summary(&self) -> Vec<StageMetrics> {
+        let samples = self.samples.lock().unwrap();
+        let mut summary: Vec<StageMetrics> = samples
+            .iter()
+            .filter(|(_, ring)| !ring.is_empty())
+            .map(|(stage, ring)| {
+                let mut sorted: Vec<u64> = ring.iter().copied().collect();
+                sorted.sort_unstable();
+                StageMetrics {
+                    stage: *stage,
+                    count: sorted.len(),
+                    p50_ms: percentile(&sorted, 50),
+                    p90_ms: percentile(&sorted, 90),
+                    p99_ms: percentile(&sorted, 99),
+                    max_ms: *sorted.last().unwrap_or(&0),
+                }
+            })
+            .collect();
+        summary.sort_by_key(|m| m.stage);
+        summary
+    }
+}
+
+// Nearest-rank percentile over already sorted samples
+fn percentile(sorted: &[u64], pct: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    sorted[rank.min(sorted.len()) - 1]
+}
@@ -0,0 +1,161 @@
+use anyhow::{bail, Context, Result};
+use reqwest::{Client, Proxy};
+use std::sync::RwLock;
+use std::time::Duration;
+
+static HTTP_CLIENT: RwLock<Option<Client>> = RwLock::new(None);
+static USER_AGENT: RwLock<String> = RwLock::new(String::new());
+
+const PROXY_SCHEMES: &[&str] = &["http", "https", "socks5", "socks5h"];
+
+/// Sent until `user_agent` is set; the site rejects some stale agents
+pub const DEFAULT_USER_AGENT: &str =
+    "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/115 Safari/537.36";
+
+fn build_client(proxy: Option<Proxy>, user_agent: &str) -> Result<Client> {
+    let mut builder = Client::builder()
+        .user_agent(user_agent)
+        .timeout(Duration::from_secs(60))
+        .connect_timeout(Duration::from_secs(15))
+        .pool_max_idle_per_host(32)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .http2_adaptive_window(true)
+        .tcp_keepalive(Duration::from_secs(30));
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(proxy);
+    }
+    builder.build().context("Failed to create HTTP client")
+}
+
+/// Parse the `proxy_url` setting; empty means a direct connection
+pub fn parse_proxy(proxy_url: &str) -> Result<Option<Proxy>> {
+    let proxy_url = proxy_url.trim();
+    if proxy_url.is_empty() {
+        return Ok(None);
+    }
+    let parsed = reqwest::Url::parse(proxy_url).with_context(|| format!("Invalid proxy URL '{}'", proxy_url))?;
+    if !PROXY_SCHEMES.contains(&parsed.scheme()) {
+        bail!("Unsupported proxy scheme '{}'; use http, https, socks5 or socks5h", parsed.scheme());
+    }
+    if parsed.host_str().is_none() {
+        bail!("Proxy URL '{}' has no host", proxy_url);
+    }
+    let proxy = Proxy::all(parsed).with_context(|| format!("Invalid proxy URL '{}'", proxy_url))?;
+    Ok(Some(proxy))
+}
+
+/// Use `proxy_url` (none when empty) and `user_agent` for every request
+/// from now on. Requests already in flight finish on the old client.
+pub fn configure(proxy_url: &str, user_agent: &str) -> Result<()> {
+    let user_agent = match user_agent.trim() {
+        "" => DEFAULT_USER_AGENT,
+        ua => ua,
+    };
+    let client = build_client(parse_proxy(proxy_url)?, user_agent)?;
+    *USER_AGENT.write().unwrap() = user_agent.to_string();
+    *HTTP_CLIENT.write().unwrap() = Some(client);
+    Ok(())
+}
+
+/// The User-Agent requests go out with, for tools like ffmpeg that fetch on their own
+pub fn user_agent() -> String {
+    let user_agent = USER_AGENT.read().unwrap();
+    if user_agent.is_empty() {
+        DEFAULT_USER_AGENT.to_string()
+    } else {
+        user_agent.clone()
+    }
+}
+
+/// Process-wide HTTP client. Clones share one connection pool, so API calls
+/// and segment fetches reuse kept-alive (and, where offered, HTTP/2)
+/// connections instead of handshaking per request.
+pub fn http_client() -> Client {
+    if let Some(client) = HTTP_CLIENT.read().unwrap().as_ref() {
+        return client.clone();
+    }
+    HTTP_CLIENT
+        .write()
+        .unwrap()
+        .get_or_insert_with(|| build_client(None, DEFAULT_USER_AGENT).expect("Failed to create HTTP client"))
+        .clone()
+}
+
+/// Reach `host` through the current client, so a dead proxy shows up as a
+/// clear error instead of every request timing out
+pub async fn check_proxy(proxy_url: &str, host: &str) -> Result<()> {
+    http_client()
+        .head(host)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await
+        .with_context(|| format!("Proxy {} is unreachable or refused the connection", proxy_url.trim()))?;
+    Ok(())
+}
+
+/// Whether this platform can tell us if the active connection is metered
+pub fn metered_detection_supported() -> bool {
+    cfg!(any(target_os = "linux", target_os = "windows"))
+}
+
+/// Ask the OS whether the active connection is metered.
+/// Returns `None` when detection isn't available or the query failed.
+/// Blocking: runs a short-lived system command.
+#[cfg(target_os = "linux")]
+pub fn is_metered() -> Option<bool> {
+    use std::process::Command;
+
+    // NetworkManager's NMMetered: 0 unknown, 1 yes, 2 no, 3 guess-yes, 4 guess-no
+    let output = Command::new("busctl")
+        .args([
+            "get-property",
+            "org.freedesktop.NetworkManager",
+            "/org/freedesktop/NetworkManager",
+            "org.freedesktop.NetworkManager",
+            "Metered",
+        ])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    // Output looks like "u 4"
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let value: u32 = stdout.split_whitespace().nth(1)?.parse().ok()?;
+    match value {
+        1 | 3 => Some(true),
+        2 | 4 => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub fn is_metered() -> Option<bool> {
+    use std::process::Command;
+
+    let output = Command::new("powershell")
+        .args([
+            "-NoProfile",
+            "-Command",
+            "[Windows.Networking.Connectivity.NetworkInformation,Windows.Networking.Connectivity,ContentType=WindowsRuntime] | Out-Null; \
+             $p = [Windows.Networking.Connectivity.NetworkInformation]::GetInternetConnectionProfile(); \
+             if ($p) { $p.GetConnectionCost().NetworkCostType }",
+        ])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    match String::from_utf8_lossy(&output.stdout).trim() {
+        "Unrestricted" => Some(false),
+        "Fixed" | "Variable" => Some(true),
+        _ => None,
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+pub fn is_metered() -> Option<bool> {
+    None
+}
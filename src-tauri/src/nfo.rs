@@ -0,0 +1,92 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::api::{AnimeMetadata, EpisodeNumber};
+
+/// Write media-server sidecars for a finished episode:
+/// `<file>.nfo` next to the episode, plus `tvshow.nfo` and `folder.jpg`
+/// in the series folder if they don't exist yet.
+pub fn write_sidecars(
+    episode_path: &Path,
+    anime_name: &str,
+    episode: EpisodeNumber,
+    metadata: Option<&AnimeMetadata>,
+    poster_path: Option<&str>,
+) -> Result<()> {
+    let episode_nfo = episode_path.with_extension("nfo");
+    fs::write(&episode_nfo, episode_nfo_xml(anime_name, episode))
+        .context("write episode nfo")?;
+
+    let Some(show_dir) = episode_path.parent() else {
+        return Ok(());
+    };
+
+    let tvshow_nfo = show_dir.join("tvshow.nfo");
+    if !tvshow_nfo.exists() {
+        fs::write(&tvshow_nfo, tvshow_nfo_xml(anime_name, metadata))
+            .context("write tvshow nfo")?;
+    }
+
+    if let Some(poster) = poster_path {
+        let folder_jpg = show_dir.join("folder.jpg");
+        if !folder_jpg.exists() && Path::new(poster).exists() {
+            fs::copy(poster, &folder_jpg).context("copy poster to folder.jpg")?;
+        }
+    }
+
+    Ok(())
+}
+
+fn episode_nfo_xml(anime_name: &str, episode: EpisodeNumber) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <episodedetails>\n\
+         \x20 <title>Episode {episode}</title>\n\
+         \x20 <showtitle>{show}</showtitle>\n\
+         \x20 <season>1</season>\n\
+         \x20 <episode>{episode}</episode>\n\
+         </episodedetails>\n",
+        show = escape_xml(anime_name),
+    )
+}
+
+fn tvshow_nfo_xml(anime_name: &str, metadata: Option<&AnimeMetadata>) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<tvshow>\n");
+    xml.push_str(&format!("  <title>{}</title>\n", escape_xml(anime_name)));
+
+    if let Some(meta) = metadata {
+        if let Some(ref synopsis) = meta.synopsis {
+            xml.push_str(&format!("  <plot>{}</plot>\n", escape_xml(synopsis)));
+        }
+        if let Some(year) = meta.year {
+            xml.push_str(&format!("  <year>{}</year>\n", year));
+        }
+        if let Some(ref status) = meta.status {
+            xml.push_str(&format!("  <status>{}</status>\n", escape_xml(status)));
+        }
+        for genre in &meta.genres {
+            xml.push_str(&format!("  <genre>{}</genre>\n", escape_xml(genre)));
+        }
+        // MAL links look like https://myanimelist.net/anime/12345
+        if let Some(mal_id) = meta
+            .mal_link
+            .as_deref()
+            .and_then(|link| link.split('/').find(|part| part.parse::<u64>().is_ok()))
+        {
+            xml.push_str(&format!("  <uniqueid type=\"mal\">{}</uniqueid>\n", mal_id));
+        }
+    }
+
+    xml.push_str("</tvshow>\n");
+    xml
+}
+
+fn escape_xml(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
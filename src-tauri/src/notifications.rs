@@ -0,0 +1,69 @@
+use std::path::Path;
+use tauri::AppHandle;
+
+use crate::api::EpisodeNumber;
+
+/// Native "Download Complete" popup with "Open folder" and "Play" actions.
+/// Sent from the backend so the actions still work while the window is hidden.
+pub fn download_complete(app: &AppHandle, anime_name: &str, episode: EpisodeNumber, file_path: &Path) {
+    let body = format!("{} - Episode {}", anime_name, episode);
+    if let Err(e) = show(app, &body, file_path) {
+        log_warn!("Completion notification for {} failed: {:#}", body, e);
+    }
+}
+
+/// The plugin's desktop backend has no action buttons, so Linux talks to
+/// the notification daemon directly and waits for the click on its own thread.
+/// Windows and macOS fall back to a plain popup (see `enable_desktop`).
+#[cfg(target_os = "linux")]
+fn show(app: &AppHandle, body: &str, file_path: &Path) -> anyhow::Result<()> {
+    use tauri::Manager;
+
+    const ACTION_OPEN_FOLDER: &str = "open-folder";
+    const ACTION_PLAY: &str = "play";
+
+    let handle = notify_rust::Notification::new()
+        .appname("Animepahe DL Desktop")
+        .summary("Download Complete")
+        .body(body)
+        .action(ACTION_OPEN_FOLDER, "Open folder")
+        .action(ACTION_PLAY, "Play")
+        .show()?;
+
+    let app = app.clone();
+    let file_path = file_path.to_path_buf();
+    std::thread::spawn(move || {
+        handle.wait_for_action(|action| {
+            let target = match action {
+                ACTION_OPEN_FOLDER => file_path.parent(),
+                ACTION_PLAY => Some(file_path.as_path()),
+                "default" => {
+                    if let Some(window) = app.get_webview_window("main") {
+                        let _ = window.show();
+                        let _ = window.set_focus();
+                    }
+                    None
+                }
+                _ => None,
+            };
+            if let Some(target) = target {
+                if let Err(e) = open::that(target) {
+                    log_warn!("Opening {} from notification failed: {}", target.display(), e);
+                }
+            }
+        });
+    });
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn show(app: &AppHandle, body: &str, _file_path: &Path) -> anyhow::Result<()> {
+    use tauri_plugin_notification::NotificationExt;
+
+    app.notification()
+        .builder()
+        .title("Download Complete")
+        .body(body)
+        .show()?;
+    Ok(())
+}
@@ -18,6 +18,9 @@ pub fn get_local_video_url(_app: &AppHandle, file_path: &str) -> Result<String>
     Ok(url)
 }
 
+/// Extensions the player (and local import) treat as video files
+pub const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mkv", "avi", "mov", "webm", "m4v"];
+
 /// Validate that a video file exists and is accessible
 pub fn validate_video_file(file_path: &str) -> Result<()> {
     let path = PathBuf::from(file_path);
@@ -31,10 +34,9 @@ pub fn validate_video_file(file_path: &str) -> Result<()> {
     }
 
     // Check if file has video extension
-    let valid_extensions = ["mp4", "mkv", "avi", "mov", "webm", "m4v"];
     if let Some(ext) = path.extension() {
         let ext_str = ext.to_string_lossy().to_lowercase();
-        if !valid_extensions.contains(&ext_str.as_str()) {
+        if !VIDEO_EXTENSIONS.contains(&ext_str.as_str()) {
             anyhow::bail!("Invalid video file extension: {}", ext_str);
         }
     } else {
@@ -66,3 +68,60 @@ pub struct VideoMetadata {
     pub file_size: u64,
     pub file_path: String,
 }
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct MediaInfo {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    pub duration_seconds: Option<f64>,
+    pub bitrate: Option<u64>,
+}
+
+/// Probe the actual streams of a file with ffprobe
+pub async fn probe_media_info(file_path: &str) -> Result<MediaInfo> {
+    let path = PathBuf::from(file_path);
+    if !path.exists() {
+        anyhow::bail!("Video file does not exist: {}", file_path);
+    }
+
+    let ffprobe = crate::download::resolve_ffprobe()?;
+    let output = tokio::process::Command::new(ffprobe)
+        .args([
+            "-v", "error",
+            "-print_format", "json",
+            "-show_format",
+            "-show_streams",
+        ])
+        .arg(&path)
+        .output()
+        .await
+        .context("Failed to run ffprobe")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "ffprobe failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .context("Failed to parse ffprobe output")?;
+
+    let streams = json["streams"].as_array().cloned().unwrap_or_default();
+    let video = streams.iter().find(|s| s["codec_type"] == "video");
+    let audio = streams.iter().find(|s| s["codec_type"] == "audio");
+
+    // ffprobe reports numeric format fields as strings
+    let format_number = |key: &str| json["format"][key].as_str().and_then(|v| v.parse::<f64>().ok());
+
+    Ok(MediaInfo {
+        width: video.and_then(|v| v["width"].as_u64()).map(|w| w as u32),
+        height: video.and_then(|v| v["height"].as_u64()).map(|h| h as u32),
+        video_codec: video.and_then(|v| v["codec_name"].as_str()).map(|s| s.to_string()),
+        audio_codec: audio.and_then(|a| a["codec_name"].as_str()).map(|s| s.to_string()),
+        duration_seconds: format_number("duration"),
+        bitrate: format_number("bit_rate").map(|b| b as u64),
+    })
+}
@@ -1,23 +1,153 @@
 use anyhow::{anyhow, Context, Result};
 use boa_engine::{context::Context as JsContext, Source};
 use regex::Regex;
-use reqwest::Client;
 use scraper::{Html, Selector};
 use serde::Serialize;
 use serde_json;
-use std::time::Duration;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::time::timeout;
 
+use crate::api::EpisodeNumber;
+use crate::network::http_client;
+
 #[derive(Debug, Clone, Serialize)]
 pub struct Candidate {
     pub src: String,
     pub audio: Option<String>,
     pub resolution: Option<String>,
     pub av1: Option<String>,
+    /// Rough download size, filled in only when a preview asks for estimates
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub approx_bytes: Option<u64>,
+}
+
+/// How long an extracted playlist URL or key is reused before re-extracting
+const EXTRACTION_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Short-lived cache of extracted m3u8 URLs (per slug, episode and source)
+/// and downloaded AES keys, so a retry that only failed at the download
+/// step skips the JS unpack. Entries are dropped on 403 (expired token).
+/// Also holds each series' episode -> session map (per slug and host) so a
+/// batch pages through the release list once rather than per episode.
+#[derive(Clone, Default)]
+pub struct ExtractionCache {
+    playlists: Arc<Mutex<HashMap<(String, EpisodeNumber, String), (String, Instant)>>>,
+    keys: Arc<Mutex<HashMap<String, (Vec<u8>, Instant)>>>,
+    sessions: Arc<Mutex<HashMap<(String, String), (BTreeMap<EpisodeNumber, String>, Instant)>>>,
+}
+
+impl ExtractionCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn playlist(&self, slug: &str, episode: EpisodeNumber, src: &str) -> Option<String> {
+        let mut playlists = self.playlists.lock().unwrap();
+        let key = (slug.to_string(), episode, src.to_string());
+        match playlists.get(&key) {
+            Some((url, at)) if at.elapsed() < EXTRACTION_TTL => Some(url.clone()),
+            Some(_) => {
+                playlists.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn store_playlist(&self, slug: &str, episode: EpisodeNumber, src: &str, m3u8: &str) {
+        self.playlists.lock().unwrap().insert(
+            (slug.to_string(), episode, src.to_string()),
+            (m3u8.to_string(), Instant::now()),
+        );
+    }
+
+    /// Forget everything cached for an episode, e.g. after its token expired
+    pub fn invalidate_episode(&self, slug: &str, episode: EpisodeNumber) {
+        self.playlists
+            .lock()
+            .unwrap()
+            .retain(|(s, e, _), _| !(s == slug && *e == episode));
+    }
+
+    pub fn key(&self, url: &str) -> Option<Vec<u8>> {
+        let mut keys = self.keys.lock().unwrap();
+        match keys.get(url) {
+            Some((bytes, at)) if at.elapsed() < EXTRACTION_TTL => Some(bytes.clone()),
+            Some(_) => {
+                keys.remove(url);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn store_key(&self, url: &str, bytes: &[u8]) {
+        self.keys
+            .lock()
+            .unwrap()
+            .insert(url.to_string(), (bytes.to_vec(), Instant::now()));
+    }
+
+    pub fn invalidate_key(&self, url: &str) {
+        self.keys.lock().unwrap().remove(url);
+    }
+
+    pub fn sessions(&self, slug: &str, host: &str) -> Option<BTreeMap<EpisodeNumber, String>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let key = (slug.to_string(), host.to_string());
+        match sessions.get(&key) {
+            Some((map, at)) if at.elapsed() < EXTRACTION_TTL => Some(map.clone()),
+            Some(_) => {
+                sessions.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn store_sessions(&self, slug: &str, host: &str, map: &BTreeMap<EpisodeNumber, String>) {
+        self.sessions.lock().unwrap().insert(
+            (slug.to_string(), host.to_string()),
+            (map.clone(), Instant::now()),
+        );
+    }
+}
+
+/// An embed host the extractor knows about
+#[derive(Debug, Clone, Serialize)]
+pub struct Provider {
+    pub name: &'static str,
+    pub host_patterns: &'static [&'static str],
+    pub supported: bool,
+}
+
+/// Every provider this build recognises. `select_candidate` and
+/// `extract_m3u8_from_link` both consult this list, so new providers are
+/// registered here once.
+pub const PROVIDERS: &[Provider] = &[Provider {
+    name: "kwik",
+    host_patterns: &["kwik"],
+    supported: true,
+}];
+
+pub fn provider_for(url: &str) -> Option<&'static Provider> {
+    let host = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.to_ascii_lowercase()))
+        .unwrap_or_else(|| url.to_ascii_lowercase());
+    PROVIDERS
+        .iter()
+        .find(|p| p.host_patterns.iter().any(|pat| host.contains(pat)))
+}
+
+fn is_supported(url: &str) -> bool {
+    provider_for(url).map_or(false, |p| p.supported)
 }
 
 pub async fn extract_candidates(play_url: &str, cookie: &str) -> Result<Vec<Candidate>> {
-    let client = client();
+    let client = http_client();
     let html = client
         .get(play_url)
         .header(reqwest::header::COOKIE, cookie)
@@ -41,12 +171,166 @@ pub async fn extract_candidates(play_url: &str, cookie: &str) -> Result<Vec<Cand
                 audio,
                 resolution,
                 av1,
+                approx_bytes: None,
             });
         }
     }
     Ok(out)
 }
 
+/// A soft subtitle track offered by a player page or master playlist
+#[derive(Debug, Clone, Serialize)]
+pub struct SubtitleTrack {
+    pub url: String,
+    pub language: Option<String>,
+    pub name: Option<String>,
+}
+
+fn resolve_url(base_url: &str, uri: &str) -> String {
+    reqwest::Url::parse(base_url)
+        .and_then(|base| base.join(uri))
+        .map(|u| u.to_string())
+        .unwrap_or_else(|_| uri.to_string())
+}
+
+/// `#EXT-X-MEDIA:TYPE=SUBTITLES` renditions in a master playlist, with
+/// URIs resolved against `base_url`
+pub fn playlist_subtitle_tracks(content: &str, base_url: &str) -> Vec<SubtitleTrack> {
+    let attr_re = Regex::new(r#"([A-Z0-9-]+)=("[^"]*"|[^,]*)"#).expect("valid regex");
+    content
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("#EXT-X-MEDIA:"))
+        .filter_map(|attrs| {
+            let attr = |name: &str| {
+                attr_re
+                    .captures_iter(attrs)
+                    .find(|c| &c[1] == name)
+                    .map(|c| c[2].trim_matches('"').to_string())
+            };
+            if attr("TYPE").as_deref() != Some("SUBTITLES") {
+                return None;
+            }
+            Some(SubtitleTrack {
+                url: resolve_url(base_url, &attr("URI")?),
+                language: attr("LANGUAGE"),
+                name: attr("NAME"),
+            })
+        })
+        .collect()
+}
+
+/// `<track>` elements and bare `.vtt`/`.srt` links in a player page
+pub fn page_subtitle_tracks(html: &str, base_url: &str) -> Vec<SubtitleTrack> {
+    let doc = Html::parse_document(html);
+    let track_sel = Selector::parse("track[src]").unwrap();
+    let mut tracks: Vec<SubtitleTrack> = doc
+        .select(&track_sel)
+        .filter(|el| el.value().attr("kind").map_or(true, |k| k == "subtitles" || k == "captions"))
+        .filter_map(|el| {
+            Some(SubtitleTrack {
+                url: resolve_url(base_url, el.value().attr("src")?),
+                language: el.value().attr("srclang").map(|s| s.to_string()),
+                name: el.value().attr("label").map(|s| s.to_string()),
+            })
+        })
+        .collect();
+    let link_re = Regex::new(r#"https?://[^\s"'<>]+?\.(?:vtt|srt)(?:\?[^\s"'<>]*)?"#).expect("valid regex");
+    for m in link_re.find_iter(html) {
+        if !tracks.iter().any(|t| t.url == m.as_str()) {
+            tracks.push(SubtitleTrack {
+                url: m.as_str().to_string(),
+                language: None,
+                name: None,
+            });
+        }
+    }
+    tracks
+}
+
+/// Subtitle tracks linked from an embed page, fetched with `host` as referer
+pub async fn extract_subtitle_tracks(embed_url: &str, cookie: &str, host: &str) -> Result<Vec<SubtitleTrack>> {
+    let html = http_client()
+        .get(embed_url)
+        .header(reqwest::header::REFERER, host)
+        .header(reqwest::header::COOKIE, cookie)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    Ok(page_subtitle_tracks(&html, embed_url))
+}
+
+/// How the requested resolution should be matched against candidates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResolutionMode {
+    Best,
+    Worst,
+    Exact(u32),
+}
+
+impl ResolutionMode {
+    /// `"best"`/`"worst"` pick the numerically highest/lowest available;
+    /// anything with a leading number (`"1080"`, `"1080p"`) is an exact match.
+    pub fn parse(input: &str) -> Option<Self> {
+        match input.trim().to_ascii_lowercase().as_str() {
+            "best" | "highest" => Some(ResolutionMode::Best),
+            "worst" | "lowest" => Some(ResolutionMode::Worst),
+            other => parse_resolution(other).map(ResolutionMode::Exact),
+        }
+    }
+}
+
+/// Numeric height from labels like `"720"` or `"1080p"`
+pub fn parse_resolution(label: &str) -> Option<u32> {
+    let digits: String = label.trim().chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+fn height(c: &Candidate) -> Option<u32> {
+    c.resolution.as_deref().and_then(parse_resolution)
+}
+
+/// Sort key for picking among equally good matches: supported provider,
+/// then highest resolution, then kwik
+fn preference(c: &Candidate) -> (bool, u32, bool) {
+    (
+        is_supported(&c.src),
+        height(c).unwrap_or(0),
+        provider_for(&c.src).is_some_and(|p| p.name == "kwik"),
+    )
+}
+
+/// Candidates matching `resolution`. An exact height that isn't offered
+/// falls back to the closest lower one, or the closest higher if nothing
+/// is lower.
+fn filter_resolution<'a>(pool: &[&'a Candidate], resolution: &str) -> Vec<&'a Candidate> {
+    let target = match ResolutionMode::parse(resolution) {
+        Some(ResolutionMode::Exact(n)) => {
+            let heights = || pool.iter().filter_map(|c| height(c));
+            heights()
+                .filter(|h| *h <= n)
+                .max()
+                .or_else(|| heights().filter(|h| *h > n).min())
+        }
+        Some(ResolutionMode::Best) => pool.iter().filter_map(|c| height(c)).max(),
+        Some(ResolutionMode::Worst) => pool.iter().filter_map(|c| height(c)).min(),
+        // Non-numeric labels fall back to a plain string comparison
+        None => {
+            return pool
+                .iter()
+                .copied()
+                .filter(|c| c.resolution.as_deref() == Some(resolution))
+                .collect();
+        }
+    };
+    pool.iter()
+        .copied()
+        .filter(|c| target.is_some() && height(c) == target)
+        .collect()
+}
+
 pub fn select_candidate<'a>(
     candidates: &'a [Candidate],
     audio: Option<&str>,
@@ -67,27 +351,122 @@ pub fn select_candidate<'a>(
         }
     }
     if let Some(r) = resolution {
-        let tmp: Vec<&Candidate> = filtered
-            .iter()
-            .copied()
-            .filter(|c| c.resolution.as_deref() == Some(r))
-            .collect();
+        let tmp = filter_resolution(&filtered, r);
         if !tmp.is_empty() {
             filtered = tmp;
         }
     }
-    // prefer kwik host
-    if let Some(c) = filtered.iter().rfind(|c| c.src.contains("kwik")) {
-        return Some(*c);
+    // Button order isn't quality order; on a full tie the later button wins
+    filtered.into_iter().max_by_key(|c| preference(c))
+}
+
+/// Best candidate in a different audio language from `used_audio`,
+/// for downloading a second track alongside the first.
+pub fn alternate_audio<'a>(
+    candidates: &'a [Candidate],
+    used_audio: Option<&str>,
+    resolution: Option<&str>,
+) -> Option<&'a Candidate> {
+    let used = used_audio?;
+    let other = candidates
+        .iter()
+        .filter_map(|c| c.audio.as_deref())
+        .find(|a| !a.eq_ignore_ascii_case(used))?;
+    rank_candidates(candidates, Some(other), resolution)
+        .into_iter()
+        .find(|c| c.audio.as_deref() == Some(other))
+}
+
+/// All usable candidates in the order they should be tried: the
+/// `select_candidate` pick first, then the rest of the audio/resolution
+/// matches, then any other non-AV1 source. Within each group, supported
+/// providers and then higher resolutions go first.
+pub fn rank_candidates<'a>(
+    candidates: &'a [Candidate],
+    audio: Option<&str>,
+    resolution: Option<&str>,
+) -> Vec<&'a Candidate> {
+    let mut ranked: Vec<&Candidate> = Vec::new();
+    if let Some(first) = select_candidate(candidates, audio, resolution) {
+        ranked.push(first);
     }
-    filtered.last().copied()
+
+    let non_av1: Vec<&Candidate> = candidates
+        .iter()
+        .filter(|c| c.av1.as_deref() != Some("1"))
+        .collect();
+    let audio_matches: Vec<&Candidate> = non_av1
+        .iter()
+        .copied()
+        .filter(|c| audio.map_or(true, |a| c.audio.as_deref() == Some(a)))
+        .collect();
+    let preferred = match resolution {
+        Some(r) => filter_resolution(&audio_matches, r),
+        None => audio_matches,
+    };
+
+    let (matching, others): (Vec<&Candidate>, Vec<&Candidate>) = non_av1
+        .into_iter()
+        .partition(|c| preferred.iter().any(|p| std::ptr::eq(*p, *c)));
+    for group in [matching, others] {
+        let mut group: Vec<&Candidate> = group.into_iter().rev().collect();
+        group.sort_by_key(|c| std::cmp::Reverse(preference(c)));
+        for c in group {
+            if !ranked.iter().any(|r| std::ptr::eq(*r, c)) {
+                ranked.push(c);
+            }
+        }
+    }
+    ranked
+}
+
+/// Steps of playlist extraction, in the order they run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractionStage {
+    #[default]
+    Provider,
+    Fetch,
+    EvalScriptNotFound,
+    JsEval,
+    RegexMatch,
+}
+
+/// What a traced extraction got through before it stopped
+#[derive(Debug, Clone, Default)]
+pub struct ExtractionTrace {
+    /// The stage running when extraction returned; the failing one on error
+    pub stage: ExtractionStage,
+    pub page_len: Option<usize>,
+    pub unpacked_len: Option<usize>,
 }
 
 pub async fn extract_m3u8_from_link(ep_link: &str, cookie: &str, host: &str) -> Result<String> {
-    eprintln!("Extracting m3u8 from: {}", ep_link);
+    extract_m3u8_traced(ep_link, cookie, host, &mut ExtractionTrace::default()).await
+}
 
-    let client = client();
+/// Same as `extract_m3u8_from_link`, recording progress in `trace` so
+/// diagnostics can tell which step failed.
+pub async fn extract_m3u8_traced(
+    ep_link: &str,
+    cookie: &str,
+    host: &str,
+    trace: &mut ExtractionTrace,
+) -> Result<String> {
+    log_info!("Extracting m3u8 from: {}", ep_link);
 
+    trace.stage = ExtractionStage::Provider;
+    match provider_for(ep_link) {
+        Some(p) if !p.supported => {
+            return Err(anyhow!("Provider '{}' is not supported by this build", p.name));
+        }
+        None => log_warn!("Unknown provider for {}, attempting generic extraction", ep_link),
+        _ => {}
+    }
+
+    let client = http_client();
+
+    trace.stage = ExtractionStage::Fetch;
     // Add timeout to HTTP request
     let text = timeout(Duration::from_secs(30), async {
         client
@@ -104,8 +483,10 @@ pub async fn extract_m3u8_from_link(ep_link: &str, cookie: &str, host: &str) ->
     .context("HTTP request timed out after 30 seconds")?
     .context("Failed to fetch page content")?;
 
-    eprintln!("Downloaded page content, length: {} bytes", text.len());
+    log_debug!("Downloaded page content, length: {} bytes", text.len());
+    trace.page_len = Some(text.len());
 
+    trace.stage = ExtractionStage::EvalScriptNotFound;
     // Find script with eval(
     let re = Regex::new(r"<script>eval\((?s).*?</script>").unwrap();
     let caps = re
@@ -113,7 +494,7 @@ pub async fn extract_m3u8_from_link(ep_link: &str, cookie: &str, host: &str) ->
         .ok_or_else(|| anyhow!("No eval script found in page content"))?;
     let mut script = &text[caps.start()..caps.end()];
 
-    eprintln!("Found eval script, length: {} bytes", script.len());
+    log_debug!("Found eval script, length: {} bytes", script.len());
 
     // Trim <script> and </script>
     if let Some(pos) = script.find("<script>") {
@@ -128,7 +509,8 @@ pub async fn extract_m3u8_from_link(ep_link: &str, cookie: &str, host: &str) ->
     js = js.replace("querySelector", "exit");
     js = js.replace("eval(", "console.log(");
 
-    eprintln!("Executing JavaScript to extract m3u8...");
+    log_debug!("Executing JavaScript to extract m3u8...");
+    trace.stage = ExtractionStage::JsEval;
 
     let js_literal = serde_json::to_string(&js).context("escape script for JS evaluation")?;
     let wrapper = format!(
@@ -183,28 +565,63 @@ pub async fn extract_m3u8_from_link(ep_link: &str, cookie: &str, host: &str) ->
     .await
     .context("JavaScript execution timed out after 20 seconds")??;
 
-    eprintln!("JavaScript output length: {} bytes", printed.len());
+    log_debug!("JavaScript output length: {} bytes", printed.len());
+    trace.unpacked_len = Some(printed.len());
+
+    trace.stage = ExtractionStage::RegexMatch;
 
     // Extract m3u8 URL from printed code
     let re2 = Regex::new(r#"source=['\"]([^'\"]+?\.m3u8)"#).unwrap();
     if let Some(c) = re2.captures(&printed) {
         let url = c.get(1).unwrap().as_str().to_string();
-        eprintln!("Successfully extracted m3u8 URL: {}", url);
+        log_info!("Successfully extracted m3u8 URL: {}", url);
         return Ok(url);
     }
 
-    eprintln!("Failed to find m3u8 URL in output: {}", printed);
+    log_warn!("Failed to find m3u8 URL in output: {}", printed);
     Err(anyhow!("m3u8 source not found in unpacked JavaScript"))
 }
 
-fn client() -> Client {
-    reqwest::Client::builder()
-        .user_agent("Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/115 Safari/537.36")
-        .timeout(Duration::from_secs(60)) // Increased from 30
-        .connect_timeout(Duration::from_secs(15)) // Increased from 10
-        .pool_max_idle_per_host(16) // Allow more connections per host
-        .http2_adaptive_window(true) // Enable HTTP/2 multiplexing
-        .tcp_keepalive(Duration::from_secs(30))
-        .build()
-        .expect("client")
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(resolution: &str) -> Candidate {
+        Candidate {
+            src: format!("https://kwik.si/e/{}", resolution),
+            audio: Some("jpn".into()),
+            resolution: Some(resolution.into()),
+            av1: None,
+            approx_bytes: None,
+        }
+    }
+
+    fn picked(candidates: &[Candidate], resolution: &str) -> Option<String> {
+        select_candidate(candidates, None, Some(resolution)).and_then(|c| c.resolution.clone())
+    }
+
+    #[test]
+    fn best_picks_highest_resolution() {
+        let candidates = [candidate("360"), candidate("720"), candidate("1080p")];
+        assert_eq!(picked(&candidates, "best").as_deref(), Some("1080p"));
+        assert_eq!(picked(&candidates, "worst").as_deref(), Some("360"));
+        assert_eq!(ResolutionMode::parse("1080p"), Some(ResolutionMode::Exact(1080)));
+    }
+
+    #[test]
+    fn exact_resolution_ignores_candidate_order() {
+        let candidates = [candidate("720"), candidate("1080"), candidate("360")];
+        assert_eq!(picked(&candidates, "1080").as_deref(), Some("1080"));
+        assert_eq!(picked(&candidates, "360p").as_deref(), Some("360"));
+        assert_eq!(picked(&candidates, "best").as_deref(), Some("1080"));
+    }
+
+    #[test]
+    fn missing_resolution_falls_back_to_closest_lower() {
+        let candidates = [candidate("1080"), candidate("360"), candidate("720")];
+        assert_eq!(picked(&candidates, "900").as_deref(), Some("720"));
+        assert_eq!(picked(&candidates, "2160").as_deref(), Some("1080"));
+        // Nothing lower: take the closest higher instead
+        assert_eq!(picked(&candidates, "240").as_deref(), Some("360"));
+    }
 }
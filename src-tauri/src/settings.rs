@@ -4,29 +4,361 @@ use anyhow::Context;
 use rand::{distributions::Alphanumeric, Rng};
 use serde::{Deserialize, Serialize};
 
+/// Site used when no host is configured. The frontend's `DEFAULT_HOST_URL`
+/// mirrors this for its placeholders.
+pub const DEFAULT_HOST: &str = "https://animepahe.si";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppSettings {
     pub download_dir: Option<String>,
     pub theme_dark: bool,
+    /// The active host; always one of `host_profiles`
     pub host_url: String,
+    /// Saved hosts to switch between when the site moves domains
+    #[serde(default = "default_host_profiles")]
+    pub host_profiles: Vec<String>,
     #[serde(default)]
     pub tour_completed: bool,
     #[serde(default = "default_max_threads")]
     pub max_threads: usize,
+    /// How many episodes of a batch download at the same time
+    #[serde(default = "default_max_concurrent_episodes")]
+    pub max_concurrent_episodes: usize,
+    /// Cap on simultaneous connections to one CDN host across all downloads
+    #[serde(default = "default_max_connections_per_host")]
+    pub max_connections_per_host: usize,
+    /// Total download bandwidth cap in kilobits per second across all
+    /// downloads; 0 means unlimited. Single-threaded downloads hand the
+    /// transfer to ffmpeg and aren't covered.
+    #[serde(default)]
+    pub max_bandwidth_kbps: usize,
+    /// Give up on a single-threaded ffmpeg download after this many seconds
+    /// without progress output; 0 disables the timeout
+    #[serde(default = "default_ffmpeg_timeout_secs")]
+    pub ffmpeg_timeout_secs: u64,
+    /// Extra attempts for a failed HLS segment before the episode fails
+    #[serde(default = "default_segment_retries")]
+    pub segment_retries: usize,
+    /// Deadline in seconds for one attempt at a segment before it is retried;
+    /// 0 disables it. Raise it along with a low `max_bandwidth_kbps`, since
+    /// waiting on the cap counts toward the deadline.
+    #[serde(default = "default_segment_timeout_secs")]
+    pub segment_timeout_secs: u64,
+    /// Segments decrypted at once, independent of download threads. Each
+    /// holds one small buffer, so lower this on memory-constrained machines.
+    #[serde(default = "default_max_decrypt_concurrency")]
+    pub max_decrypt_concurrency: usize,
+    /// Write Kodi/Jellyfin `.nfo` sidecars and `folder.jpg` next to downloads
+    #[serde(default)]
+    pub write_nfo: bool,
+    /// Drop tracker records as soon as the episode lands in the library
+    #[serde(default)]
+    pub auto_clear_completed: bool,
+    /// Requeue downloads left queued, in progress or failed when the app
+    /// last closed, a few seconds after launch
+    #[serde(default)]
+    pub auto_resume_on_startup: bool,
+    /// Closing the window hides it to the tray so downloads keep running;
+    /// the tray's Quit is then the only way to exit
+    #[serde(default = "default_true")]
+    pub close_to_tray: bool,
+    /// Proxy for every outbound request, e.g. `socks5://127.0.0.1:1080` or
+    /// `http://proxy:8080`; empty connects directly
+    #[serde(default)]
+    pub proxy_url: String,
+    /// User-Agent sent on every request; empty uses the built-in one
+    #[serde(default = "default_user_agent")]
+    pub user_agent: String,
+    /// Resolution (e.g. "1080") used when a download doesn't pick one
+    #[serde(default)]
+    pub default_resolution: Option<String>,
+    /// Audio language (e.g. "jpn") used when a download doesn't pick one
+    #[serde(default)]
+    pub default_audio: Option<String>,
+    /// Which download events notify, and how
+    #[serde(default)]
+    pub notifications: NotificationSettings,
+    /// Pause segment downloads while the OS reports a metered connection
+    #[serde(default)]
+    pub pause_on_metered: bool,
+    /// Keep segment scratch space in the OS temp dir instead of the download folder
+    #[serde(default)]
+    pub work_in_temp_dir: bool,
+    /// Re-download segments whose size doesn't match their `Content-Length`
+    #[serde(default)]
+    pub verify_segment_length: bool,
+    /// What to do when an episode has both sub and dub sources
+    #[serde(default)]
+    pub dual_audio: DualAudioMode,
+    /// Compatibility setting: pass `-allowed_extensions ALL` to ffmpeg
+    #[serde(default)]
+    pub ffmpeg_allow_all_extensions: bool,
+    /// Fetch soft subtitle tracks from the player page or playlist and mux
+    /// them into the episode (sidecar files when kept as HLS)
+    #[serde(default)]
+    pub download_subtitles: bool,
+    /// Subtitle files to keep: WebVTT as downloaded, SubRip, or both
+    #[serde(default)]
+    pub subtitle_format: crate::subtitles::SubtitleFormat,
+    /// Output path under the download folder, without extension. Tokens:
+    /// `{anime}`, `{episode}`, `{episode:02}`, `{resolution}`, `{audio}`,
+    /// `{host}`; `/` starts a subfolder
+    #[serde(default = "default_filename_template")]
+    pub filename_template: String,
+    /// Regex matched against file names (without extension) when importing
+    /// local files; the first capture group that matched is the episode number
+    #[serde(default = "default_local_episode_pattern")]
+    pub local_episode_pattern: String,
+    /// Opt-in video re-encode to trade download time for smaller files
+    #[serde(default)]
+    pub transcode: TranscodeSettings,
+    /// Hardware decoder ffmpeg uses while transcoding; falls back to
+    /// software if it fails
+    #[serde(default)]
+    pub hwaccel: HwAccel,
+    /// Container muxed episodes are written to
+    #[serde(default)]
+    pub output_format: OutputFormat,
+    /// Minimum level written to the log file: error, warn, info or debug
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DualAudioMode {
+    /// Only the requested audio
+    #[default]
+    Off,
+    /// Second language saved next to the episode as `<ep>.<audio>.<ext>`
+    SeparateFiles,
+    /// Second language muxed into the episode file as an extra audio track
+    Muxed,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    #[default]
+    Mp4,
+    /// Copes better with TS timestamps and carries soft subtitles
+    Mkv,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VideoCodec {
+    #[default]
+    H264,
+    /// Smaller at the same quality, but slower to encode and less widely playable
+    H265,
+}
+
+impl VideoCodec {
+    /// ffmpeg encoder name
+    pub fn encoder(self) -> &'static str {
+        match self {
+            VideoCodec::H264 => "libx264",
+            VideoCodec::H265 => "libx265",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HwAccel {
+    #[default]
+    None,
+    Videotoolbox,
+    Cuda,
+    Qsv,
+}
+
+impl HwAccel {
+    /// Value for ffmpeg's `-hwaccel`
+    pub fn ffmpeg_name(self) -> Option<&'static str> {
+        match self {
+            HwAccel::None => None,
+            HwAccel::Videotoolbox => Some("videotoolbox"),
+            HwAccel::Cuda => Some("cuda"),
+            HwAccel::Qsv => Some("qsv"),
+        }
+    }
+
+    /// Accelerators worth offering on this platform
+    pub fn supported() -> Vec<HwAccel> {
+        if cfg!(target_os = "macos") {
+            vec![HwAccel::None, HwAccel::Videotoolbox]
+        } else {
+            vec![HwAccel::None, HwAccel::Cuda, HwAccel::Qsv]
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranscodeSettings {
+    /// Off means `-c copy`, which is fast and lossless
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub codec: VideoCodec,
+    /// Constant rate factor; lower is better quality and bigger files
+    #[serde(default = "default_crf")]
+    pub crf: u8,
+    /// x264/x265 speed preset, e.g. `veryfast`, `medium`, `slow`
+    #[serde(default = "default_preset")]
+    pub preset: String,
+}
+
+impl Default for TranscodeSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            codec: VideoCodec::default(),
+            crf: default_crf(),
+            preset: default_preset(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationSettings {
+    #[serde(default = "default_true")]
+    pub enable_sound: bool,
+    /// OS notification popups; the in-app list still updates when off.
+    /// Completion popups carry "Open folder" and "Play" actions on Linux
+    /// only; the notification plugin has no desktop actions on Windows or
+    /// macOS, so there they are plain popups.
+    #[serde(default = "default_true")]
+    pub enable_desktop: bool,
+    #[serde(default = "default_true")]
+    pub notify_on_complete: bool,
+    #[serde(default = "default_true")]
+    pub notify_on_fail: bool,
+    /// Sound file played instead of the system sound
+    #[serde(default)]
+    pub sound_path: Option<String>,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self {
+            enable_sound: true,
+            enable_desktop: true,
+            notify_on_complete: true,
+            notify_on_fail: true,
+            sound_path: None,
+        }
+    }
+}
+
+fn default_host_profiles() -> Vec<String> {
+    vec![DEFAULT_HOST.to_string()]
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_crf() -> u8 {
+    23
+}
+
+fn default_preset() -> String {
+    "medium".to_string()
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Mp4 => "mp4",
+            OutputFormat::Mkv => "mkv",
+        }
+    }
 }
 
 fn default_max_threads() -> usize {
     8
 }
 
+fn default_max_connections_per_host() -> usize {
+    24
+}
+
+fn default_max_concurrent_episodes() -> usize {
+    1
+}
+
+fn default_ffmpeg_timeout_secs() -> u64 {
+    1800
+}
+
+fn default_segment_retries() -> usize {
+    5
+}
+
+fn default_segment_timeout_secs() -> u64 {
+    60
+}
+
+fn default_max_decrypt_concurrency() -> usize {
+    2
+}
+
+fn default_filename_template() -> String {
+    crate::download::DEFAULT_FILENAME_TEMPLATE.to_string()
+}
+
+fn default_local_episode_pattern() -> String {
+    crate::commands::DEFAULT_LOCAL_EPISODE_PATTERN.to_string()
+}
+
+fn default_user_agent() -> String {
+    crate::network::DEFAULT_USER_AGENT.to_string()
+}
+
+fn default_log_level() -> String {
+    "info".into()
+}
+
 impl Default for AppSettings {
     fn default() -> Self {
         Self {
             download_dir: None,
             theme_dark: true,
-            host_url: "https://animepahe.si".into(),
+            host_url: DEFAULT_HOST.into(),
+            host_profiles: default_host_profiles(),
             tour_completed: false,
             max_threads: default_max_threads(),
+            max_concurrent_episodes: default_max_concurrent_episodes(),
+            max_connections_per_host: default_max_connections_per_host(),
+            max_bandwidth_kbps: 0,
+            ffmpeg_timeout_secs: default_ffmpeg_timeout_secs(),
+            segment_retries: default_segment_retries(),
+            segment_timeout_secs: default_segment_timeout_secs(),
+            max_decrypt_concurrency: default_max_decrypt_concurrency(),
+            write_nfo: false,
+            auto_clear_completed: false,
+            auto_resume_on_startup: false,
+            close_to_tray: true,
+            proxy_url: String::new(),
+            user_agent: default_user_agent(),
+            default_resolution: None,
+            default_audio: None,
+            notifications: NotificationSettings::default(),
+            pause_on_metered: false,
+            work_in_temp_dir: false,
+            verify_segment_length: false,
+            dual_audio: DualAudioMode::Off,
+            ffmpeg_allow_all_extensions: false,
+            download_subtitles: false,
+            subtitle_format: crate::subtitles::SubtitleFormat::Vtt,
+            filename_template: default_filename_template(),
+            local_episode_pattern: default_local_episode_pattern(),
+            transcode: TranscodeSettings::default(),
+            hwaccel: HwAccel::default(),
+            output_format: OutputFormat::default(),
+            log_level: default_log_level(),
         }
     }
 }
@@ -34,14 +366,27 @@ impl Default for AppSettings {
 pub struct AppState {
     settings_path: PathBuf,
     pub settings: Mutex<AppSettings>,
-    cookie: Mutex<String>,
+    cookie: Mutex<CookieState>,
+}
+
+/// Session cookie sent to the host and where it came from, for debugging
+#[derive(Debug, Clone, Serialize)]
+pub struct CookieState {
+    pub value: String,
+    /// Unix time of the last refresh; `None` while on the random startup cookie
+    pub refreshed_at: Option<i64>,
+    pub refresh_count: u32,
 }
 
 impl AppState {
     pub fn init() -> Self {
         let path = settings_file_path();
         let settings = load_settings(&path).unwrap_or_default();
-        let cookie = Mutex::new(gen_cookie());
+        let cookie = Mutex::new(CookieState {
+            value: gen_cookie(),
+            refreshed_at: None,
+            refresh_count: 0,
+        });
         Self {
             settings_path: path,
             settings: Mutex::new(settings),
@@ -50,13 +395,25 @@ impl AppState {
     }
 
     pub fn cookie(&self) -> String {
+        self.cookie.lock().unwrap().value.clone()
+    }
+
+    pub fn cookie_state(&self) -> CookieState {
         self.cookie.lock().unwrap().clone()
     }
 
+    /// Swap in a refreshed cookie, or a new random one when `value` is `None`
+    pub fn set_cookie(&self, value: Option<String>) {
+        let mut cookie = self.cookie.lock().unwrap();
+        cookie.value = value.unwrap_or_else(gen_cookie);
+        cookie.refreshed_at = Some(chrono::Utc::now().timestamp());
+        cookie.refresh_count += 1;
+    }
+
     pub fn persist(&self, settings: AppSettings) -> anyhow::Result<()> {
         let mut guard = self.settings.lock().unwrap();
         let mut updated = settings.clone();
-        updated.host_url = normalize_host(&updated.host_url);
+        normalize_hosts(&mut updated);
         *guard = updated.clone();
         save_settings(&self.settings_path, &updated)
     }
@@ -72,7 +429,7 @@ fn settings_file_path() -> PathBuf {
 fn load_settings(path: &PathBuf) -> anyhow::Result<AppSettings> {
     let contents = fs::read_to_string(path)?;
     let mut settings: AppSettings = serde_json::from_str(&contents)?;
-    settings.host_url = normalize_host(&settings.host_url);
+    normalize_hosts(&mut settings);
     Ok(settings)
 }
 
@@ -96,8 +453,24 @@ fn gen_cookie() -> String {
 pub fn normalize_host(input: &str) -> String {
     let trimmed = input.trim();
     if trimmed.is_empty() {
-        "https://animepahe.si".into()
+        DEFAULT_HOST.into()
     } else {
         trimmed.trim_end_matches('/').to_string()
     }
 }
+
+/// Normalize the active host and profiles, dropping duplicates and making
+/// sure the active host is saved as a profile
+fn normalize_hosts(settings: &mut AppSettings) {
+    settings.host_url = normalize_host(&settings.host_url);
+    let mut profiles: Vec<String> = Vec::with_capacity(settings.host_profiles.len() + 1);
+    for host in settings.host_profiles.iter().map(|h| normalize_host(h)) {
+        if !profiles.contains(&host) {
+            profiles.push(host);
+        }
+    }
+    if !profiles.contains(&settings.host_url) {
+        profiles.push(settings.host_url.clone());
+    }
+    settings.host_profiles = profiles;
+}
@@ -0,0 +1,150 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitleFormat {
+    #[default]
+    Vtt,
+    Srt,
+    Both,
+}
+
+/// Bring a `.vtt` subtitle file in line with `format`: write an `.srt`
+/// sidecar for `Srt`/`Both`, and drop the original for `Srt`.
+/// Returns the subtitle files that exist afterwards.
+pub fn apply_format(vtt_path: &Path, format: SubtitleFormat) -> Result<Vec<PathBuf>> {
+    if format == SubtitleFormat::Vtt {
+        return Ok(vec![vtt_path.to_path_buf()]);
+    }
+
+    let vtt = fs::read_to_string(vtt_path)
+        .with_context(|| format!("read subtitles {}", vtt_path.display()))?;
+    let srt_path = vtt_path.with_extension("srt");
+    fs::write(&srt_path, vtt_to_srt(&vtt)).context("write srt subtitles")?;
+
+    if format == SubtitleFormat::Srt {
+        fs::remove_file(vtt_path).context("remove converted vtt")?;
+        Ok(vec![srt_path])
+    } else {
+        Ok(vec![vtt_path.to_path_buf(), srt_path])
+    }
+}
+
+/// Convert WebVTT text to SubRip. Header, `NOTE`, `STYLE` and `REGION`
+/// blocks are dropped, cue settings after the timing are ignored, and
+/// inline tags (`<i>`, `<c.yellow>`, `<00:01.000>` …) are reduced to the
+/// `<b>`/`<i>`/`<u>` subset SRT players understand.
+pub fn vtt_to_srt(vtt: &str) -> String {
+    let normalized = vtt.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    let mut out = String::new();
+    let mut index = 0;
+
+    for block in normalized.split("\n\n") {
+        let lines: Vec<&str> = block.lines().filter(|l| !l.trim().is_empty()).collect();
+        let Some(timing_pos) = lines.iter().position(|l| l.contains("-->")) else {
+            // WEBVTT header, NOTE/STYLE/REGION blocks and stray text
+            continue;
+        };
+        let Some((start, end)) = parse_timing(lines[timing_pos]) else {
+            continue;
+        };
+
+        index += 1;
+        out.push_str(&format!("{}\n{} --> {}\n", index, start, end));
+        for text in &lines[timing_pos + 1..] {
+            out.push_str(&strip_tags(text));
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+/// `00:01.500 --> 00:04.000 align:start` -> (`00:00:01,500`, `00:00:04,000`)
+fn parse_timing(line: &str) -> Option<(String, String)> {
+    let (start, rest) = line.split_once("-->")?;
+    let end = rest.split_whitespace().next()?;
+    Some((srt_timestamp(start.trim())?, srt_timestamp(end)?))
+}
+
+/// VTT allows `mm:ss.ttt` as well as `hh:mm:ss.ttt`; SRT always wants
+/// `hh:mm:ss,ttt`
+fn srt_timestamp(ts: &str) -> Option<String> {
+    let (clock, millis) = ts.split_once('.')?;
+    let millis: u32 = millis.parse().ok()?;
+    let parts: Vec<u32> = clock
+        .split(':')
+        .map(|p| p.parse().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let (h, m, s) = match parts.as_slice() {
+        [m, s] => (0, *m, *s),
+        [h, m, s] => (*h, *m, *s),
+        _ => return None,
+    };
+    Some(format!("{:02}:{:02}:{:02},{:03}", h, m, s, millis))
+}
+
+fn strip_tags(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('<') {
+        out.push_str(&rest[..open]);
+        let Some(close) = rest[open..].find('>') else {
+            out.push_str(&rest[open..]);
+            rest = "";
+            break;
+        };
+        let tag = &rest[open + 1..open + close];
+        // Keep basic formatting, minus VTT classes like <i.loud>
+        let name = tag.trim_start_matches('/').split(['.', ' ']).next().unwrap_or("");
+        if matches!(name, "b" | "i" | "u") {
+            let slash = if tag.starts_with('/') { "/" } else { "" };
+            out.push_str(&format!("<{}{}>", slash, name));
+        }
+        rest = &rest[open + close + 1..];
+    }
+    out.push_str(rest);
+    decode_entities(&out)
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&lrm;", "\u{200e}")
+        .replace("&rlm;", "\u{200f}")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_short_timestamps_and_drops_cue_settings() {
+        let vtt = "WEBVTT\n\n00:01.500 --> 00:04.000 align:start position:10%\nHello\n\n1\n01:02:03.004 --> 01:02:05.000\nWorld\n";
+        assert_eq!(
+            vtt_to_srt(vtt),
+            "1\n00:00:01,500 --> 00:00:04,000\nHello\n\n2\n01:02:03,004 --> 01:02:05,000\nWorld\n\n"
+        );
+    }
+
+    #[test]
+    fn skips_note_and_style_blocks() {
+        let vtt = "\u{feff}WEBVTT\r\n\r\nNOTE translated by fans\r\n\r\nSTYLE\r\n::cue { color: yellow }\r\n\r\n00:00.000 --> 00:01.000\r\nOnly cue\r\n";
+        assert_eq!(vtt_to_srt(vtt), "1\n00:00:00,000 --> 00:00:01,000\nOnly cue\n\n");
+    }
+
+    #[test]
+    fn strips_tags_but_keeps_basic_formatting() {
+        assert_eq!(
+            strip_tags("<i.loud>Run</i> <c.yellow>now</c> <00:01.000>&amp; <b>go</b>"),
+            "<i>Run</i> now & <b>go</b>"
+        );
+        assert_eq!(strip_tags("<v Narrator>Tom &lt;3</v>"), "Tom <3");
+    }
+}
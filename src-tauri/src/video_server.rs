@@ -42,7 +42,7 @@ pub async fn start_video_server(ffmpeg_path: String) -> Result<String, String> {
     // Spawn server in background
     tokio::spawn(async move {
         if let Err(e) = axum::serve(listener, app).await {
-            eprintln!("Video server error: {}", e);
+            log_error!("Video server error: {}", e);
         }
     });
 
@@ -54,20 +54,20 @@ async fn stream_video(
     Path(file_path): Path<String>,
     headers: HeaderMap,
 ) -> Result<Response, StatusCode> {
-    println!("Received file_path from URL: {:?}", file_path);
+    log_debug!("Received file_path from URL: {:?}", file_path);
 
     // Axum's Path extractor already URL-decodes the path, so use it directly
     let path = PathBuf::from(&file_path);
-    println!("PathBuf: {:?}", path);
-    println!("Path exists: {}", path.exists());
+    log_debug!("PathBuf: {:?}", path);
+    log_debug!("Path exists: {}", path.exists());
 
     if !path.exists() {
-        eprintln!("Video file not found: {:?}", path);
-        eprintln!("File path as string: {}", path.display());
+        log_warn!("Video file not found: {:?}", path);
+        log_debug!("File path as string: {}", path.display());
         return Err(StatusCode::NOT_FOUND);
     }
 
-    println!("✓ Video file found, streaming: {:?}", path);
+    log_debug!("Video file found, streaming: {:?}", path);
 
     // Check if client supports range requests
     let range_header = headers.get(header::RANGE);
@@ -75,7 +75,7 @@ async fn stream_video(
     // For now, we'll transcode the entire video with compatible audio
     // In the future, we can add range request support for seeking
     if range_header.is_some() {
-        println!("Range requests not yet supported, streaming full video");
+        log_debug!("Range requests not yet supported, streaming full video");
     }
 
     // Start ffmpeg to transcode audio on-the-fly
@@ -106,21 +106,21 @@ async fn stream_video(
         .stdout(Stdio::piped())
         .kill_on_drop(true);
 
-    println!("Starting ffmpeg transcoding with command: {:?}", ffmpeg_cmd);
+    log_debug!("Starting ffmpeg transcoding with command: {:?}", ffmpeg_cmd);
 
     let mut child = ffmpeg_cmd
         .spawn()
         .map_err(|e| {
-            eprintln!("Failed to spawn ffmpeg: {}", e);
+            log_warn!("Failed to spawn ffmpeg: {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
     let mut stdout = child.stdout.take().ok_or_else(|| {
-        eprintln!("Failed to get ffmpeg stdout");
+        log_warn!("Failed to get ffmpeg stdout");
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
-    println!("ffmpeg process started, waiting for output...");
+    log_debug!("ffmpeg process started, waiting for output...");
 
     // Log stderr in background - show ALL output for debugging
     if let Some(mut stderr) = child.stderr.take() {
@@ -132,10 +132,10 @@ async fn stream_video(
                     break;
                 }
                 let chunk = String::from_utf8_lossy(&buf[..n]);
-                print!("[ffmpeg] {}", chunk);
+                log_debug!("[ffmpeg] {}", chunk.trim_end());
                 total_output.push_str(&chunk);
             }
-            println!("\n[ffmpeg] Process ended. Total stderr output: {} bytes", total_output.len());
+            log_debug!("[ffmpeg] Process ended. Total stderr output: {} bytes", total_output.len());
         });
     }
 
@@ -144,15 +144,15 @@ async fn stream_video(
     let mut first_chunk = vec![0u8; 8192];
     let bytes_read = match tokio::io::AsyncReadExt::read(&mut stdout, &mut first_chunk).await {
         Ok(0) => {
-            eprintln!("ffmpeg produced no output");
+            log_warn!("ffmpeg produced no output");
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
         Ok(n) => {
-            println!("✓ Received first {} bytes from ffmpeg", n);
+            log_debug!("Received first {} bytes from ffmpeg", n);
             n
         }
         Err(e) => {
-            eprintln!("Failed to read from ffmpeg: {}", e);
+            log_warn!("Failed to read from ffmpeg: {}", e);
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     };
@@ -175,10 +175,10 @@ async fn stream_video(
         .header(header::CACHE_CONTROL, "no-cache")
         .body(body)
         .map_err(|e| {
-            eprintln!("Failed to build response: {}", e);
+            log_warn!("Failed to build response: {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
-    println!("Response sent with Content-Type: video/x-matroska");
+    log_debug!("Response sent with Content-Type: video/x-matroska");
     Ok(response)
 }
@@ -12,7 +12,7 @@ import { SettingsScreen } from './screens/settings/SettingsScreen';
 import { TitleScreen } from './screens/title/TitleScreen';
 import { EpisodesScreen } from './screens/episodes/EpisodesScreen';
 import { PlayerScreen } from './screens/player/PlayerScreen';
-import { usePreferenceStore } from './core/store';
+import { usePreferenceStore, DEFAULT_HOST_URL } from './core/store';
 import { useEffect } from 'react';
 
 /**
@@ -39,7 +39,7 @@ export default function App() {
           settings={{
             downloadDir: null,
             themeDark,
-            hostUrl: 'https://animepahe.si',
+            hostUrl: DEFAULT_HOST_URL,
             tourCompleted: false,
             analyticsEnabled,
             maxThreads: 8,
@@ -114,6 +114,13 @@ export function ResumeDownloadsDialog({ open, onOpenChange }: ResumeDownloadsDia
 
   const getStatusBadge = (status: DownloadRecord["status"]) => {
     switch (status.toLowerCase()) {
+      case "queued":
+        return (
+          <Badge variant="secondary" className="flex items-center gap-1">
+            <AlertCircle className="w-3 h-3" />
+            Queued
+          </Badge>
+        );
       case "inprogress":
         return (
           <Badge variant="default" className="flex items-center gap-1">
@@ -135,6 +142,13 @@ export function ResumeDownloadsDialog({ open, onOpenChange }: ResumeDownloadsDia
             Completed
           </Badge>
         );
+      case "paused":
+        return (
+          <Badge variant="secondary" className="flex items-center gap-1">
+            <AlertCircle className="w-3 h-3" />
+            Paused
+          </Badge>
+        );
       case "cancelled":
         return (
           <Badge variant="secondary" className="flex items-center gap-1">
@@ -1,4 +1,5 @@
 import { useState } from "react";
+import { DEFAULT_HOST_URL } from "../core/store";
 import { Button } from "../ui/components/base/button";
 import { Input } from "../ui/components/base/input";
 import { Switch } from "../ui/components/base/switch";
@@ -136,7 +137,7 @@ export function SettingsDropdown({
             onChange={(e) => handleHostChange(e.target.value)}
             onBlur={handleHostSave}
             className="text-sm"
-            placeholder="https://animepahe.si"
+            placeholder={DEFAULT_HOST_URL}
           />
           <div className="flex gap-2">
             <Button variant="outline" size="sm" onClick={handleHostSave} className="flex-1">
@@ -69,7 +69,7 @@ export const tourSteps: TourStep[] = [
     id: "complete",
     title: "You're All Set! ✨",
     content:
-      "That's everything you need to know to get started. The app will automatically check for required dependencies (Node.js, FFmpeg, OpenSSL) and guide you through installation if needed. Happy downloading!",
+      "That's everything you need to know to get started. The app will automatically check for required dependencies (FFmpeg) and guide you through installation if needed. Happy downloading!",
     placement: "bottom",
   },
 ];
@@ -10,9 +10,17 @@ import type {
   EpisodeInfo,
   RequirementsCheckResponse,
   DownloadRecord,
+  DownloadStatus,
   LibraryEntry,
   AnimeStats,
+  LibrarySort,
   LibraryStats,
+  LibraryScanReport,
+  RelocatedEntry,
+  LocalImportReport,
+  LibraryQuery,
+  LibraryPage,
+  EpisodeGaps,
 } from "../types";
 
 export async function loadSettings(): Promise<Settings> {
@@ -66,7 +74,8 @@ export async function previewSources(
   slug: string,
   host: string,
   episodes: number[],
-  cached: FetchEpisodesResponse
+  cached: FetchEpisodesResponse,
+  estimateSize = false
 ): Promise<PreviewItem[]> {
   return invoke("preview_sources", {
     req: {
@@ -74,6 +83,7 @@ export async function previewSources(
       host,
       episodes,
       cached: cached.episodes,
+      estimate_size: estimateSize,
     },
   });
 }
@@ -122,6 +132,7 @@ interface AppSettingsRaw {
   tour_completed: boolean;
   analytics_enabled: boolean;
   max_threads: number;
+  host_profiles?: string[];
 }
 
 
@@ -133,6 +144,7 @@ function normalizeSettings(raw: AppSettingsRaw): Settings {
     tourCompleted: raw.tour_completed ?? false,
     analyticsEnabled: raw.analytics_enabled ?? false,
     maxThreads: raw.max_threads ?? 8,
+    hostProfiles: raw.host_profiles ?? [raw.host_url],
   };
 }
 
@@ -160,8 +172,8 @@ function emptyToNull(value?: string): string | null | undefined {
   return trimmed.length === 0 ? null : trimmed;
 }
 
-export async function cancelDownload(episode: number): Promise<void> {
-  await invoke("cancel_download", { episode });
+export async function cancelDownload(downloadId: string): Promise<void> {
+  await invoke("cancel_download", { downloadId });
 }
 
 // Resume download API functions
@@ -181,6 +193,10 @@ export async function clearCompletedDownloads(): Promise<void> {
   await invoke("clear_completed_downloads");
 }
 
+export async function clearDownloads(statuses: DownloadStatus[]): Promise<number> {
+  return invoke("clear_downloads", { statuses });
+}
+
 export async function validateDownloadIntegrity(
   downloadId: string
 ): Promise<boolean> {
@@ -210,12 +226,68 @@ export async function getLibraryEntry(
   return invoke("get_library_entry", { slug, episode });
 }
 
+export async function scanLibrary(
+  searchRoot?: string | null
+): Promise<LibraryScanReport> {
+  return invoke("scan_library", { searchRoot: searchRoot ?? null });
+}
+
+export async function relocateEntry(
+  id: number,
+  newPath: string
+): Promise<RelocatedEntry> {
+  return invoke("relocate_entry", { id, newPath });
+}
+
+export async function relocateAnime(
+  slug: string,
+  newDir: string
+): Promise<RelocatedEntry[]> {
+  return invoke("relocate_anime", { slug, newDir });
+}
+
+export async function importLocalFiles(
+  dir: string,
+  slug: string,
+  animeName: string
+): Promise<LocalImportReport> {
+  return invoke("import_local_files", { dir, slug, animeName });
+}
+
 export async function getLibraryEntries(): Promise<LibraryEntry[]> {
   return invoke("get_library_entries");
 }
 
-export async function getAnimeLibrary(): Promise<AnimeStats[]> {
-  return invoke("get_anime_library");
+export async function queryLibrary(query: LibraryQuery = {}): Promise<LibraryPage> {
+  return invoke("query_library", { query });
+}
+
+export async function getEpisodeGaps(slug: string): Promise<EpisodeGaps> {
+  return invoke("get_episode_gaps", { slug });
+}
+
+export async function getAnimeLibrary(tag?: string, sort?: LibrarySort): Promise<AnimeStats[]> {
+  return invoke("get_anime_library", { tag: tag ?? null, sort: sort ?? null });
+}
+
+export async function setAnimeRating(slug: string, rating: number | null): Promise<void> {
+  return invoke("set_anime_rating", { slug, rating });
+}
+
+export async function addTag(slug: string, tag: string): Promise<void> {
+  return invoke("add_tag", { slug, tag });
+}
+
+export async function removeTag(slug: string, tag: string): Promise<void> {
+  return invoke("remove_tag", { slug, tag });
+}
+
+export async function listTags(slug?: string): Promise<string[]> {
+  return invoke("list_tags", { slug: slug ?? null });
+}
+
+export async function getAnimeByTag(tag: string): Promise<AnimeStats[]> {
+  return invoke("get_anime_by_tag", { tag });
 }
 
 export async function getAnimeEpisodes(slug: string): Promise<LibraryEntry[]> {
@@ -266,6 +338,19 @@ export async function migrateLibraryPosters(): Promise<void> {
   return invoke("migrate_library_posters");
 }
 
+// Host profiles
+export async function addHostProfile(url: string): Promise<Settings> {
+  return normalizeSettings(await invoke<AppSettingsRaw>("add_host_profile", { url }));
+}
+
+export async function removeHostProfile(url: string): Promise<Settings> {
+  return normalizeSettings(await invoke<AppSettingsRaw>("remove_host_profile", { url }));
+}
+
+export async function selectHostProfile(url: string): Promise<Settings> {
+  return normalizeSettings(await invoke<AppSettingsRaw>("select_host_profile", { url }));
+}
+
 // Notification API functions
 export async function playNotificationSound(): Promise<void> {
   await invoke("play_notification_sound");
@@ -3,7 +3,7 @@
  * Uses Zustand for reactive state across the application
  */
 
-export { usePreferenceStore } from './preference-store';
+export { usePreferenceStore, DEFAULT_HOST_URL } from './preference-store';
 export { useQueueStore } from './queue-store';
 export { useLibraryStore } from './library-store';
 export { useNetworkStore } from './network-store';
@@ -3,10 +3,13 @@ import { persist, createJSONStorage } from 'zustand/middleware';
 import type { PreferenceState } from './types';
 import { isTauri, safeInvoke } from '../utils/tauri';
 
+// Mirrors settings::DEFAULT_HOST in the backend, which wins once settings load
+export const DEFAULT_HOST_URL = 'https://animepahe.si';
+
 const defaultSettings = {
   downloadDir: null,
   themeDark: true,
-  hostUrl: 'https://animepahe.si',
+  hostUrl: DEFAULT_HOST_URL,
   tourCompleted: false,
   analyticsEnabled: true,
   maxThreads: 8,
@@ -1,13 +1,14 @@
 import { create } from 'zustand';
 import type { QueueState, QueueItem } from './types';
 import { listen } from '@tauri-apps/api/event';
-import type { DownloadStatusEvent, DownloadProgressEvent } from '../types';
+import type { DownloadStatusEvent, DownloadProgressEvent, DownloadStartedEvent } from '../types';
 
 export const useQueueStore = create<QueueState>((set, get) => ({
   items: [],
   statusMap: {},
   progressMap: {},
   downloadPaths: {},
+  downloadIds: {},
   isBusy: false,
 
   // Actions
@@ -70,10 +71,24 @@ export const useQueueStore = create<QueueState>((set, get) => ({
     }));
   },
 
+  setDownloadId: (episode, downloadId) => {
+    set((state) => ({
+      downloadIds: {
+        ...state.downloadIds,
+        [episode]: downloadId,
+      },
+    }));
+  },
+
   cancelDownload: async (episode) => {
+    const downloadId = get().downloadIds[episode];
+    if (!downloadId) {
+      console.error(`No running download for episode ${episode}`);
+      return;
+    }
     try {
       const { invoke } = await import('@tauri-apps/api/core');
-      await invoke('cancel_download', { episode });
+      await invoke('cancel_download', { downloadId });
     } catch (err) {
       console.error('Failed to cancel download:', err);
     }
@@ -100,6 +115,7 @@ export const useQueueStore = create<QueueState>((set, get) => ({
       statusMap: {},
       progressMap: {},
       downloadPaths: {},
+      downloadIds: {},
       isBusy: false,
     });
   },
@@ -111,6 +127,10 @@ listen<DownloadStatusEvent>('download-status', (event) => {
   useQueueStore.getState().updateStatus(episode, status, path ?? undefined);
 });
 
+listen<DownloadStartedEvent>('download-started', (event) => {
+  useQueueStore.getState().setDownloadId(event.payload.episode, event.payload.downloadId);
+});
+
 listen<DownloadProgressEvent>('download-progress', (event) => {
   useQueueStore.getState().updateProgress(event.payload.episode, {
     done: event.payload.done,
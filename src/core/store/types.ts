@@ -50,6 +50,7 @@ export interface QueueState {
   statusMap: Record<number, string>;
   progressMap: Record<number, { done: number; total: number; speedBps: number; elapsedSeconds: number }>;
   downloadPaths: Record<number, string>;
+  downloadIds: Record<number, string>;
   isBusy: boolean;
 
   // Actions
@@ -57,6 +58,7 @@ export interface QueueState {
   removeFromQueue: (id: string) => void;
   updateStatus: (episode: number, status: string, path?: string) => void;
   updateProgress: (episode: number, progress: QueueItem['progress']) => void;
+  setDownloadId: (episode: number, downloadId: string) => void;
   cancelDownload: (episode: number) => void;
   retryDownload: (episode: number) => void;
   clearCompleted: () => void;
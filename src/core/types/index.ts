@@ -5,6 +5,7 @@ export interface Settings {
   tourCompleted: boolean;
   analyticsEnabled: boolean;
   maxThreads: number;
+  hostProfiles?: string[];
 }
 
 export interface SearchItem {
@@ -46,6 +47,7 @@ export interface EpisodeInfo {
   number: number;
   session: string;
   snapshotUrl?: string | null;
+  airedAt?: string | null;
   title?: string | null;
   filesize?: number | null;
 }
@@ -63,11 +65,30 @@ export interface FetchEpisodesResponse {
   malLink?: string | null;
 }
 
+export interface EpisodePagesResponse {
+  episodes: EpisodeInfo[];
+  lastPage: number;
+}
+
+export interface AnimeDetails {
+  title: string;
+  synopsis: string | null;
+  genres: string[];
+  season: string | null;
+  year: number | null;
+  anime_type: string | null;
+  status: string | null;
+  mal_link: string | null;
+  poster_url: string | null;
+  episode_count: number | null;
+}
+
 export interface CandidateSource {
   src: string;
   audio?: string | null;
   resolution?: string | null;
   av1?: string | null;
+  approx_bytes?: number;
 }
 
 export interface PreviewItem {
@@ -81,12 +102,22 @@ export interface DownloadStatusEvent {
   path?: string | null;
 }
 
+export interface DownloadStartedEvent {
+  episode: number;
+  animeSlug: string;
+  downloadId: string; // what cancel_download takes; episode numbers repeat across shows
+}
+
 export interface DownloadProgressEvent {
   episode: number;
   done: number;
   total: number;
   speedBps: number; // bytes per second
   elapsedSeconds: number; // time spent downloading
+  indeterminate: boolean;
+  downloadedBytes: number;
+  totalBytes: number; // estimate; 0 until known
+  etaSeconds?: number | null; // from a smoothed speed; null while unknown
 }
 
 export interface RequirementStatus {
@@ -101,6 +132,12 @@ export interface RequirementsCheckResponse {
   requirements: RequirementStatus[];
 }
 
+export interface CookieState {
+  value: string;
+  refreshed_at: number | null;
+  refresh_count: number;
+}
+
 export interface TourStep {
   id: string;
   title: string;
@@ -128,10 +165,12 @@ export interface TourContextType {
 
 // Resume download types
 export type DownloadStatus =
+  | "queued"
   | "inprogress"
   | "completed"
   | "failed"
-  | "cancelled";
+  | "cancelled"
+  | "paused";
 
 export interface DownloadRecord {
   id: string;
@@ -148,6 +187,9 @@ export interface DownloadRecord {
   error_message: string | null;
   audio_type: string | null;
   resolution: string | null;
+  priority: number;
+  work_dir: string | null;
+  retry_after: number | null;
 }
 
 // Library types
@@ -166,6 +208,31 @@ export interface LibraryEntry {
   watch_count: number;
   duration_seconds: number | null;
   host: string;
+  watched: boolean;
+  playback_position_seconds: number | null;
+}
+
+export interface EpisodeGaps {
+  downloaded: number[];
+  missing: number[];
+}
+
+export type EntrySort = "date" | "name" | "size" | "episode";
+
+export interface LibraryQuery {
+  sort?: EntrySort;
+  descending?: boolean | null;
+  resolution?: string | null;
+  audio?: string | null;
+  host?: string | null;
+  watched?: boolean | null;
+  offset?: number;
+  limit?: number | null;
+}
+
+export interface LibraryPage {
+  entries: LibraryEntry[];
+  total: number;
 }
 
 export interface AnimeStats {
@@ -175,6 +242,42 @@ export interface AnimeStats {
   total_size: number;
   thumbnail_url: string | null;
   last_downloaded: number;
+  rating: number | null;
+}
+
+export type LibrarySort = "recent" | "rating";
+
+export interface LibraryScanIssue {
+  id: number;
+  anime_name: string;
+  episode: number;
+  file_path: string;
+  recorded_size: number;
+  actual_size: number | null;
+  relocated_to: string | null;
+}
+
+export interface LibraryScanReport {
+  scanned: number;
+  missing: LibraryScanIssue[];
+  size_mismatch: LibraryScanIssue[];
+  relocated: LibraryScanIssue[];
+}
+
+export interface RelocatedEntry {
+  id: number;
+  old_path: string;
+  new_path: string;
+}
+
+export interface SkippedFile {
+  path: string;
+  reason: string;
+}
+
+export interface LocalImportReport {
+  imported: LibraryEntry[];
+  skipped: SkippedFile[];
 }
 
 export interface LibraryStats {
@@ -190,6 +293,7 @@ export interface DownloadCompleteNotification {
   file_path: string;
   file_size: number;
   success: boolean;
+  desktop: boolean; // backend notification settings allow an OS popup (completion popups are sent by the backend)
 }
 
 export interface NotificationSettings {
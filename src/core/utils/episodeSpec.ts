@@ -12,7 +12,7 @@ export interface ParseResult {
  * Parse episode specification string into array of episode numbers
  *
  * Supported formats:
- * - Single episode: "1"
+ * - Single episode: "1", or a special such as "12.5"
  * - Multiple episodes: "1,3,5"
  * - Range: "1-5"
  * - All episodes: "*"
@@ -77,9 +77,9 @@ export function parseEpisodeSpec(value: string, availableEpisodes: number[]): Pa
       continue;
     }
 
-    // Handle single episode number
+    // Handle single episode number (specials like 12.5 are picked individually)
     const numberValue = Number(part);
-    if (!Number.isInteger(numberValue)) {
+    if (!Number.isFinite(numberValue) || numberValue < 0) {
       return { episodes: [], error: `'${part}' is not a valid episode number.` };
     }
     if (!availableSet.has(numberValue)) {
@@ -115,7 +115,7 @@ export function isValidEpisodeSpec(value: string): boolean {
       if (!rangeParts.every((n) => /^\d+$/.test(n))) return false;
     } else {
       // Check for single number
-      if (!/^\d+$/.test(part) && part !== '*') return false;
+      if (!/^\d+(\.\d+)?$/.test(part) && part !== '*') return false;
     }
   }
 
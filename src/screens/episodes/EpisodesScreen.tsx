@@ -170,17 +170,17 @@ export function EpisodesScreen() {
         selected = episodes.slice(-count);
       } else if (pattern.includes('-')) {
         // Range pattern: "1-5", "10-20"
-        const [start, end] = pattern.split('-').map((n) => parseInt(n.trim()));
+        const [start, end] = pattern.split('-').map((n) => parseFloat(n.trim()));
         if (!isNaN(start) && !isNaN(end)) {
           selected = episodes.filter((ep) => ep >= start && ep <= end);
         }
       } else if (pattern.includes(',')) {
-        // Comma-separated: "1,3,5,7"
-        const nums = pattern.split(',').map((n) => parseInt(n.trim()));
+        // Comma-separated: "1,3,5,7" or specials like "12.5"
+        const nums = pattern.split(',').map((n) => parseFloat(n.trim()));
         selected = episodes.filter((ep) => nums.includes(ep));
       } else {
         // Single episode
-        const num = parseInt(pattern);
+        const num = parseFloat(pattern);
         if (!isNaN(num) && episodes.includes(num)) {
           selected = [num];
         }
@@ -29,7 +29,7 @@ import {
   BatteryLow,
   Zap,
 } from 'lucide-react';
-import { usePreferenceStore, useNetworkStore } from '../../core/store';
+import { usePreferenceStore, useNetworkStore, DEFAULT_HOST_URL } from '../../core/store';
 import { open as selectDirectory } from '@tauri-apps/plugin-dialog';
 import { getVersion } from '@tauri-apps/api/app';
 import UpdateDialog from '../../components/UpdateDialog';
@@ -110,7 +110,7 @@ export function SettingsScreen() {
   };
 
   const handleHostUrlReset = () => {
-    const defaultUrl = 'https://animepahe.si';
+    const defaultUrl = DEFAULT_HOST_URL;
     setHostUrlInput(defaultUrl);
     setHostUrl(defaultUrl);
   };
@@ -357,7 +357,7 @@ export function SettingsScreen() {
               id="host-url"
               value={hostUrlInput}
               onChange={(e) => setHostUrlInput(e.target.value)}
-              placeholder="https://animepahe.si"
+              placeholder={DEFAULT_HOST_URL}
             />
             <div className="flex gap-2">
               <Button onClick={handleHostUrlSave} variant="outline" size="sm">
@@ -44,30 +44,7 @@ export function useDownloadNotifications() {
       }
     }
 
-    // Show desktop notification
-    console.log('[NOTIFICATION] Checking desktop notification permission');
-    try {
-      let permissionGranted = await isPermissionGranted();
-      console.log('[NOTIFICATION] Permission granted:', permissionGranted);
-
-      if (!permissionGranted) {
-        console.log('[NOTIFICATION] Requesting permission');
-        const permission = await requestPermission();
-        permissionGranted = permission === 'granted';
-        console.log('[NOTIFICATION] Permission after request:', permissionGranted);
-      }
-
-      if (permissionGranted) {
-        console.log('[NOTIFICATION] Sending desktop notification');
-        await sendNotification({
-          title: 'Download Complete',
-          body: `${notification.anime_name} - Episode ${notification.episode}`,
-        });
-        console.log('[NOTIFICATION] Desktop notification sent successfully');
-      }
-    } catch (error) {
-      console.error('[NOTIFICATION] Failed to show desktop notification:', error);
-    }
+    // The desktop popup (with Open folder / Play actions) is sent by the backend
 
     // Update tray if enabled
     if (settings.showInTray) {
@@ -135,21 +112,23 @@ export function useDownloadNotifications() {
     incrementFailed();
 
     // Show desktop notification
-    try {
-      let permissionGranted = await isPermissionGranted();
-      if (!permissionGranted) {
-        const permission = await requestPermission();
-        permissionGranted = permission === 'granted';
-      }
-
-      if (permissionGranted) {
-        await sendNotification({
-          title: 'Download Failed',
-          body: `${notification.anime_name} - Episode ${notification.episode}`,
-        });
+    if (notification.desktop) {
+      try {
+        let permissionGranted = await isPermissionGranted();
+        if (!permissionGranted) {
+          const permission = await requestPermission();
+          permissionGranted = permission === 'granted';
+        }
+
+        if (permissionGranted) {
+          await sendNotification({
+            title: 'Download Failed',
+            body: `${notification.anime_name} - Episode ${notification.episode}`,
+          });
+        }
+      } catch (error) {
+        console.error('Failed to show desktop notification:', error);
       }
-    } catch (error) {
-      console.error('Failed to show desktop notification:', error);
     }
 
     // Show in-app toast